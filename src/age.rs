@@ -0,0 +1,103 @@
+use crate::query::any_leaf;
+use crate::{OperatorContext, QueryError};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Value;
+
+/// Evaluates `$olderThan` (`older` is `true`) or `$newerThan` on RFC 3339 timestamps.
+///
+/// The age of a timestamp is the time elapsed from it until [EvaluationOptions::now](crate::EvaluationOptions::now),
+/// or the current time if that isn't set. Timestamps in the future have a negative age.
+/// Values that aren't RFC 3339 strings don't match, and an array evaluatee matches if any of its leaves does.
+pub(crate) fn compare_age(
+    operator: &str,
+    evaluatee: Option<&Value>,
+    condition: &Value,
+    context: &OperatorContext,
+    older: bool,
+) -> Result<bool, QueryError> {
+    let threshold = parse_duration(condition).ok_or_else(|| QueryError::OperatorError {
+        operator: operator.to_string(),
+        reason: "condition must be a number of seconds or an ISO 8601 duration like \"P1DT12H\""
+            .to_string(),
+    })?;
+    let now = context.options.now.unwrap_or_else(Utc::now);
+    Ok(evaluatee.is_some_and(|e| {
+        any_leaf(e, &mut |e| {
+            let Some(instant) = e
+                .as_str()
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+            else {
+                return false;
+            };
+            let age = now.signed_duration_since(instant);
+            if older {
+                age > threshold
+            } else {
+                age < threshold
+            }
+        })
+    }))
+}
+
+/// Parses a duration given in seconds, or as an ISO 8601 duration string like `"P1W"` or `"PT1H30M"`.
+///
+/// Durations in years or months are rejected, as those vary in length. Only seconds may be fractional.
+fn parse_duration(condition: &Value) -> Option<Duration> {
+    match condition {
+        Value::Number(seconds) => {
+            let millis = seconds.as_f64()? * 1000.0;
+            millis
+                .is_finite()
+                .then(|| Duration::try_milliseconds(millis as i64))
+                .flatten()
+        }
+        Value::String(s) => parse_iso_duration(s),
+        _ => None,
+    }
+}
+
+fn parse_iso_duration(s: &str) -> Option<Duration> {
+    let rest = s.strip_prefix('P')?;
+    let (date, time) = match rest.split_once('T') {
+        Some((_, "")) => return None,
+        Some((date, time)) => (date, Some(time)),
+        None => (rest, None),
+    };
+    if date.is_empty() && time.is_none() {
+        return None;
+    }
+    let mut total = Duration::zero();
+    for (part, units) in [(date, "WD"), (time.unwrap_or(""), "HMS")] {
+        let mut number = String::new();
+        let mut last_unit = None;
+        for c in part.chars() {
+            if c.is_ascii_digit() || c == '.' {
+                number.push(c);
+                continue;
+            }
+            // units must be known, appear at most once and in order, and follow a number
+            let position = units.find(c)?;
+            if last_unit.is_some_and(|last| last >= position) || number.is_empty() {
+                return None;
+            }
+            last_unit = Some(position);
+            let duration = if c == 'S' {
+                Duration::try_milliseconds((number.parse::<f64>().ok()? * 1000.0) as i64)?
+            } else {
+                let n: i64 = number.parse().ok()?;
+                match c {
+                    'W' => Duration::try_weeks(n)?,
+                    'D' => Duration::try_days(n)?,
+                    'H' => Duration::try_hours(n)?,
+                    _ => Duration::try_minutes(n)?,
+                }
+            };
+            total = total.checked_add(&duration)?;
+            number.clear();
+        }
+        if !number.is_empty() {
+            return None;
+        }
+    }
+    Some(total)
+}
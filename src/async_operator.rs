@@ -1,7 +1,151 @@
-use crate::QueryError;
+use crate::operator::EvaluationOptions;
+use crate::sub_query::SubQueries;
+use crate::{OperatorProvider, QueryError};
 use async_trait::async_trait;
 use serde_json::Value;
 use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+
+/// The future returned by an [AsyncStandardOperator].
+pub type AsyncOperatorFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<bool, QueryError>> + Send + 'a>>;
+
+/// Async version of [StandardOperator](crate::StandardOperator).
+///
+/// ```
+/// use mongoquery::AsyncOperatorFuture;
+/// use serde_json::Value;
+///
+/// fn is_known<'a>(evaluatee: Option<&'a Value>, _condition: &'a Value) -> AsyncOperatorFuture<'a> {
+///     Box::pin(async move {
+///         // e.g. look the evaluatee up in a database
+///         Ok(evaluatee.is_some())
+///     })
+/// }
+/// ```
+pub type AsyncStandardOperator =
+    for<'a> fn(Option<&'a Value>, &'a Value) -> AsyncOperatorFuture<'a>;
+
+/// Evaluation state made available to [AsyncContextOperator]s, like
+/// [OperatorContext](crate::OperatorContext) is to synchronous ones.
+#[derive(Clone, Copy)]
+pub struct AsyncOperatorContext<'a> {
+    /// The value the query is being evaluated against, if any.
+    pub root: Option<&'a Value>,
+    /// The options the query is being evaluated with.
+    pub options: &'a EvaluationOptions,
+    pub(crate) evaluation: &'a (dyn AsyncSubQueryEvaluator + Sync),
+}
+
+impl AsyncOperatorContext<'_> {
+    /// Evaluates `query` against `value` as part of the query being evaluated: with all of its
+    /// operators, async and custom ones included, and the same options and root value.
+    pub fn evaluate_sub_query<'b>(
+        &'b self,
+        query: &'b Value,
+        value: Option<&'b Value>,
+    ) -> AsyncOperatorFuture<'b> {
+        self.evaluation.evaluate_sub_query(query, value)
+    }
+}
+
+impl fmt::Debug for AsyncOperatorContext<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncOperatorContext")
+            .field("root", &self.root)
+            .field("options", &self.options)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The evaluation an [AsyncOperatorContext] belongs to.
+pub(crate) trait AsyncSubQueryEvaluator {
+    fn evaluate_sub_query<'b>(
+        &'b self,
+        query: &'b Value,
+        value: Option<&'b Value>,
+    ) -> AsyncOperatorFuture<'b>;
+}
+
+/// An [AsyncStandardOperator] that is also given the [AsyncOperatorContext] of the evaluation.
+///
+/// Use this for operators whose conditions hold queries, to evaluate them with
+/// [AsyncOperatorContext::evaluate_sub_query].
+pub type AsyncContextOperator = for<'a> fn(
+    Option<&'a Value>,
+    &'a Value,
+    &'a AsyncOperatorContext<'a>,
+) -> AsyncOperatorFuture<'a>;
+
+/// A trait that provides [AsyncStandardOperator]s to [AsyncQuerier](crate::AsyncQuerier),
+/// on top of the synchronous operators of its [OperatorProvider].
+///
+/// Providers without async operators are used by [AsyncQuerier](crate::AsyncQuerier) through an
+/// empty implementation, like `impl AsyncOperatorProvider for MyOperators {}`.
+pub trait AsyncOperatorProvider: OperatorProvider {
+    /// A function that provides [AsyncStandardOperator]s to [AsyncQuerier](crate::AsyncQuerier).
+    ///
+    /// Async standard operators take precedence over synchronous operators of the same name.
+    /// By default, no async standard operators are provided.
+    fn get_async_operators() -> HashMap<String, AsyncStandardOperator> {
+        HashMap::new()
+    }
+
+    /// A function that provides [AsyncContextOperator]s to [AsyncQuerier](crate::AsyncQuerier).
+    ///
+    /// Async context operators take precedence over async standard operators of the same name.
+    /// By default, no async context operators are provided.
+    fn get_async_context_operators() -> HashMap<String, AsyncContextOperator> {
+        HashMap::new()
+    }
+}
+
+impl SubQueries<'_> {
+    /// Evaluates the sub-queries as part of the async evaluation of `context`.
+    pub(crate) async fn evaluate_async(
+        self,
+        context: &AsyncOperatorContext<'_>,
+    ) -> Result<bool, QueryError> {
+        match self {
+            SubQueries::Matched(matched) => Ok(matched),
+            SubQueries::Match { query, value } => {
+                context.evaluate_sub_query(query, Some(&value)).await
+            }
+            SubQueries::EachMatchesAnElement { queries, elements } => {
+                for query in queries {
+                    let mut matched = false;
+                    for e in elements {
+                        if context.evaluate_sub_query(query, Some(e)).await? {
+                            matched = true;
+                            break;
+                        }
+                    }
+                    if !matched {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            SubQueries::CountWhere {
+                predicate,
+                elements,
+                count,
+            } => {
+                let mut matching = 0;
+                for e in elements {
+                    if context.evaluate_sub_query(predicate, Some(e)).await? {
+                        matching += 1;
+                    }
+                }
+                context
+                    .evaluate_sub_query(count, Some(&Value::from(matching)))
+                    .await
+            }
+        }
+    }
+}
 
 /// Async version of [CustomOperator](crate::CustomOperator)
 #[async_trait]
@@ -1,13 +1,74 @@
-use crate::async_operator::AsyncCustomOperator;
-use crate::operator::StandardOperator;
-use crate::query::extract;
-use crate::{OperatorProvider, QueryError};
+use crate::async_operator::{
+    AsyncContextOperator, AsyncCustomOperator, AsyncOperatorContext, AsyncOperatorFuture,
+    AsyncOperatorProvider, AsyncStandardOperator, AsyncSubQueryEvaluator,
+};
+use crate::operator::{
+    ContextOperator, EvaluationOptions, OperatorContext, StandardOperator, SubQueryEvaluator,
+};
+use crate::query::{
+    is_json_path, is_operator_document, resolve_field_refs, scalar_match, select, QueryRef,
+    WILDCARD_FIELD,
+};
+use crate::query::{CompiledPattern, CompiledRegex};
+use crate::{compile_regex, OperatorProvider, QueryError};
 use async_recursion::async_recursion;
 use serde_json::{Map, Number, Value};
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::convert::Infallible;
 use std::marker::PhantomData;
 
+/// Operators and state shared by a single evaluation of an [AsyncQuery].
+struct AsyncEvaluation<'a> {
+    std_ops: &'a HashMap<String, StandardOperator>,
+    ctx_ops: &'a HashMap<String, ContextOperator>,
+    async_ops: &'a HashMap<String, AsyncStandardOperator>,
+    async_ctx_ops: &'a HashMap<String, AsyncContextOperator>,
+    custom_ops: &'a HashMap<String, Box<dyn AsyncCustomOperator>>,
+    root: Option<&'a Value>,
+    options: &'a EvaluationOptions,
+    /// Evaluates a sub-query of an operator, parsed for the provider of the evaluation.
+    sub_query:
+        for<'b> fn(&'b Value, Option<&'b Value>, &'b AsyncEvaluation) -> AsyncOperatorFuture<'b>,
+    /// Evaluates a sub-query of a synchronous context operator, which can't await async operators.
+    sync_sub_query: fn(&Value, Option<&Value>, &AsyncEvaluation) -> Result<bool, QueryError>,
+}
+
+impl AsyncEvaluation<'_> {
+    fn context(&self) -> OperatorContext<'_> {
+        OperatorContext {
+            root: self.root,
+            options: self.options,
+            evaluation: self,
+            regex: None,
+        }
+    }
+
+    fn async_context(&self) -> AsyncOperatorContext<'_> {
+        AsyncOperatorContext {
+            root: self.root,
+            options: self.options,
+            evaluation: self,
+        }
+    }
+}
+
+impl AsyncSubQueryEvaluator for AsyncEvaluation<'_> {
+    fn evaluate_sub_query<'b>(
+        &'b self,
+        query: &'b Value,
+        value: Option<&'b Value>,
+    ) -> AsyncOperatorFuture<'b> {
+        (self.sub_query)(query, value, self)
+    }
+}
+
+impl SubQueryEvaluator for AsyncEvaluation<'_> {
+    fn evaluate_sub_query(&self, query: &Value, value: Option<&Value>) -> Result<bool, QueryError> {
+        (self.sync_sub_query)(query, value, self)
+    }
+}
+
 /// An async variant of [Query](crate::Query).
 #[derive(Debug)]
 pub enum AsyncQuery<T>
@@ -34,6 +95,10 @@ where
     Not {
         op: AsyncQuery<T>,
     },
+    /// Matches arrays with at least one element satisfying `op`
+    ElemMatch {
+        op: AsyncQuery<T>,
+    },
     /// Condition evaluation on Field
     Field {
         field_name: String,
@@ -43,12 +108,20 @@ where
     Operator {
         operator: String,
         condition: Value,
+        /// The regular expression of the condition, compiled once when the query is built,
+        /// for the operators that have one.
+        regex: Option<CompiledRegex>,
+    },
+    /// Malformed operator that fails when evaluated
+    Invalid {
+        operator: String,
+        reason: String,
     },
 }
 
 impl<T> AsyncQuery<T>
 where
-    T: OperatorProvider,
+    T: AsyncOperatorProvider,
 {
     pub(crate) fn from_value(v: &Value) -> AsyncQuery<T> {
         match v {
@@ -71,56 +144,99 @@ where
         value: Option<&Value>,
         custom_ops: &HashMap<String, Box<dyn AsyncCustomOperator>>,
     ) -> Result<bool, QueryError> {
-        self.evaluate_with_ops(value, &T::get_operators(), custom_ops)
-            .await
+        let eval = AsyncEvaluation {
+            std_ops: &T::get_operators(),
+            ctx_ops: &T::get_context_operators(),
+            async_ops: &T::get_async_operators(),
+            async_ctx_ops: &T::get_async_context_operators(),
+            custom_ops,
+            root: value,
+            options: &EvaluationOptions::default(),
+            sub_query: |query, value, eval| {
+                Box::pin(async move {
+                    AsyncQuery::<T>::from_value(query)
+                        .evaluate_with_ops(value, eval, None)
+                        .await
+                })
+            },
+            sync_sub_query: |query, value, eval| {
+                QueryRef::<T>::evaluate_sub_query(
+                    query,
+                    value,
+                    eval.root,
+                    eval.std_ops,
+                    eval.ctx_ops,
+                    eval.options,
+                )
+            },
+        };
+        self.evaluate_with_ops(value, &eval, None).await
+    }
+
+    /// Reads line-delimited JSON (NDJSON) from the reader, yielding the documents matching this query.
+    ///
+    /// Blank lines are skipped. Lines that fail to read or to parse as JSON yield a
+    /// [QueryError::SerializationError], and documents failing evaluation yield the error,
+    /// without ending the stream.
+    #[cfg(feature = "stream")]
+    pub fn filter_async_read<'q, R>(
+        &'q self,
+        reader: R,
+    ) -> impl futures_util::Stream<Item = Result<Value, QueryError>> + 'q
+    where
+        R: futures_util::AsyncRead + Unpin + 'q,
+    {
+        use futures_util::{AsyncBufReadExt, StreamExt};
+
+        futures_util::io::BufReader::new(reader)
+            .lines()
+            .filter_map(move |line| async move {
+                let document = match line {
+                    Ok(line) if line.trim().is_empty() => return None,
+                    Ok(line) => serde_json::from_str::<Value>(&line),
+                    Err(e) => Err(serde_json::Error::io(e)),
+                };
+                match document {
+                    Ok(document) => match self.evaluate(Some(&document)).await {
+                        Ok(true) => Some(Ok(document)),
+                        Ok(false) => None,
+                        Err(e) => Some(Err(e)),
+                    },
+                    Err(e) => Some(Err(e.into())),
+                }
+            })
     }
 
     async fn evaluate_with_ops(
         &self,
         value: Option<&Value>,
-        std_ops: &HashMap<String, StandardOperator>,
-        custom_ops: &HashMap<String, Box<dyn AsyncCustomOperator>>,
+        eval: &AsyncEvaluation<'_>,
+        field: Option<&str>,
     ) -> Result<bool, QueryError> {
         Ok(match self {
+            // scalars match the value itself or, for arrays, any of its leaves
             AsyncQuery::NullScalar => {
-                if let Some(Value::Null) = value {
-                    true
-                } else if let Some(Value::Array(v)) = value {
-                    v.contains(&Value::Null)
-                } else {
-                    false
-                }
-            }
-            AsyncQuery::NumericScalar(n) => {
-                if let Some(Value::Number(input)) = value {
-                    input == n
-                } else if let Some(Value::Array(v)) = value {
-                    v.contains(&Value::Number(n.clone()))
-                } else {
-                    false
-                }
-            }
-            AsyncQuery::BooleanScalar(b) => {
-                if let Some(Value::Bool(input)) = value {
-                    input == b
-                } else if let Some(Value::Array(v)) = value {
-                    v.contains(&Value::Bool(*b))
-                } else {
-                    false
-                }
+                // `{"field": null}` matches both an explicit null and a missing field.
+                value.is_none_or(|v| scalar_match(v, eval.options, &mut Value::is_null))
             }
+            AsyncQuery::NumericScalar(n) => value.is_some_and(|v| {
+                scalar_match(
+                    v,
+                    eval.options,
+                    &mut |e| matches!(e, Value::Number(e) if e == n),
+                )
+            }),
+            AsyncQuery::BooleanScalar(b) => value
+                .is_some_and(|v| scalar_match(v, eval.options, &mut |e| e.as_bool() == Some(*b))),
             AsyncQuery::StringScalar(s) => {
-                if let Some(Value::String(input)) = value {
-                    input == s
-                } else if let Some(Value::Array(v)) = value {
-                    v.contains(&Value::String(s.clone()))
-                } else {
-                    false
-                }
+                value.is_some_and(|v| scalar_match(v, eval.options, &mut |e| e.as_str() == Some(s)))
             }
             AsyncQuery::Sequence(seq) => {
                 if let Some(Value::Array(v)) = value {
+                    // the whole array or, like scalars, one of its elements
                     seq == v
+                        || (!eval.options.strict_array
+                            && v.iter().any(|e| matches!(e, Value::Array(e) if seq == e)))
                 } else if let Some(v) = value {
                     seq.contains(v)
                 } else {
@@ -129,7 +245,7 @@ where
             }
             AsyncQuery::Compound(compound) => {
                 for cond in compound {
-                    if cond.evaluate(value, std_ops, custom_ops).await? == false {
+                    if !cond.evaluate(value, eval, field).await? {
                         return Ok(false);
                     }
                 }
@@ -142,7 +258,7 @@ where
 
 impl<T> AsyncCondition<T>
 where
-    T: OperatorProvider,
+    T: AsyncOperatorProvider,
 {
     fn from_map(map: &Map<String, Value>) -> Vec<AsyncCondition<T>> {
         let mut v = Vec::with_capacity(map.len());
@@ -161,14 +277,38 @@ where
                         condition,
                     )));
                 }
-                "$not" => v.push(AsyncCondition::Not {
-                    op: AsyncQuery::from_value(condition),
+                "$elemMatch" => v.push(if condition.is_object() {
+                    AsyncCondition::ElemMatch {
+                        op: AsyncQuery::from_value(condition),
+                    }
+                } else {
+                    AsyncCondition::Invalid {
+                        operator: "elemMatch".to_string(),
+                        reason: "argument must be a document".to_string(),
+                    }
+                }),
+                "$not" => v.push(if is_operator_document(condition) {
+                    AsyncCondition::Not {
+                        op: AsyncQuery::from_value(condition),
+                    }
+                } else {
+                    AsyncCondition::Invalid {
+                        operator: "not".to_string(),
+                        reason: "argument must be an operator document".to_string(),
+                    }
                 }),
+                op if op
+                    .strip_prefix('$')
+                    .is_some_and(|key| T::ignored_keys().contains(&key)) => {}
                 op => {
-                    if let Some(stripped) = op.strip_prefix('$') {
+                    if let Some(stripped) = op
+                        .strip_prefix('$')
+                        .filter(|_| !is_json_path(op) && op != WILDCARD_FIELD)
+                    {
                         v.push(AsyncCondition::Operator {
                             operator: stripped.to_string(),
                             condition: condition.clone(),
+                            regex: compile_regex(stripped, condition),
                         })
                     } else {
                         v.push(AsyncCondition::Field {
@@ -186,13 +326,13 @@ where
     async fn evaluate(
         &self,
         value: Option<&'async_recursion Value>,
-        std_ops: &HashMap<String, StandardOperator>,
-        custom_ops: &HashMap<String, Box<dyn AsyncCustomOperator>>,
+        eval: &AsyncEvaluation<'_>,
+        field: Option<&'async_recursion str>,
     ) -> Result<bool, QueryError> {
         Ok(match self {
             AsyncCondition::And(operators) => {
                 for op in operators {
-                    if op.evaluate_with_ops(value, std_ops, custom_ops).await? == false {
+                    if !op.evaluate_with_ops(value, eval, field).await? {
                         return Ok(false);
                     }
                 }
@@ -200,7 +340,7 @@ where
             }
             AsyncCondition::Or(operators) => {
                 for op in operators {
-                    if op.evaluate_with_ops(value, std_ops, custom_ops).await? == true {
+                    if op.evaluate_with_ops(value, eval, field).await? {
                         return Ok(true);
                     }
                 }
@@ -208,31 +348,86 @@ where
             }
             AsyncCondition::Nor(operators) => {
                 for op in operators {
-                    if op.evaluate_with_ops(value, std_ops, custom_ops).await? == true {
+                    if op.evaluate_with_ops(value, eval, field).await? {
                         return Ok(false);
                     }
                 }
                 return Ok(true);
             }
-            AsyncCondition::Not { op } => !op.evaluate_with_ops(value, std_ops, custom_ops).await?,
+            AsyncCondition::Not { op } => !op.evaluate_with_ops(value, eval, field).await?,
+            AsyncCondition::ElemMatch { op } => {
+                if let Some(Value::Array(arr)) = value {
+                    for e in arr {
+                        if op.evaluate_with_ops(Some(e), eval, field).await? {
+                            return Ok(true);
+                        }
+                    }
+                }
+                return Ok(false);
+            }
+            AsyncCondition::Field { field_name, op } if field_name == WILDCARD_FIELD => {
+                if let Some(Value::Object(obj)) = value {
+                    for (name, field) in obj {
+                        if op.evaluate_with_ops(Some(field), eval, Some(name)).await? {
+                            return Ok(true);
+                        }
+                    }
+                }
+                return Ok(false);
+            }
             AsyncCondition::Field { field_name, op } => {
-                let field = extract(value, &field_name.split('.').collect::<Vec<_>>());
-                op.evaluate_with_ops(field.as_ref(), std_ops, custom_ops)
+                let selected = select(value, field_name, eval.options)?;
+                op.evaluate_with_ops(selected.as_deref(), eval, Some(field_name))
                     .await?
             }
             AsyncCondition::Operator {
                 operator,
                 condition,
+                regex,
             } => {
-                if let Some(custom_op) = custom_ops.get(operator) {
-                    custom_op.evaluate(value, condition).await?
-                } else if let Some(std_op) = std_ops.get(operator) {
-                    std_op(value, condition)?
+                let condition = &*if eval.options.resolve_field_refs {
+                    resolve_field_refs(condition, &eval.context())?
+                } else {
+                    Cow::Borrowed(condition)
+                };
+                let result = if let Some(custom_op) = eval.custom_ops.get(operator) {
+                    custom_op.evaluate(value, condition).await
+                } else if let Some(async_ctx_op) = eval.async_ctx_ops.get(operator) {
+                    async_ctx_op(value, condition, &eval.async_context()).await
+                } else if let Some(async_op) = eval.async_ops.get(operator) {
+                    async_op(value, condition).await
+                } else if let Some(ctx_op) = eval.ctx_ops.get(operator) {
+                    ctx_op(
+                        value,
+                        condition,
+                        &OperatorContext {
+                            regex: regex.as_deref().map(CompiledPattern::regex),
+                            ..eval.context()
+                        },
+                    )
+                } else if let Some(std_op) = eval.std_ops.get(operator) {
+                    std_op(value, condition)
                 } else {
-                    return Err(QueryError::UnsupportedOperator {
+                    Err(QueryError::UnsupportedOperator {
                         operator: operator.clone(),
-                    });
-                }
+                    })
+                };
+                #[cfg(feature = "log")]
+                log::trace!(
+                    target: "mongoquery",
+                    "${} {} on field {:?}: {:?}",
+                    operator,
+                    condition,
+                    field,
+                    result
+                );
+                result?
+            }
+            AsyncCondition::Invalid { operator, reason } => {
+                return Err(QueryError::OperatorError {
+                    operator: operator.clone(),
+                    reason: reason.clone(),
+                });
             }
         })
     }
@@ -240,7 +435,7 @@ where
 
 fn compound_condition_from_value<T>(v: &Value) -> Vec<AsyncQuery<T>>
 where
-    T: OperatorProvider,
+    T: AsyncOperatorProvider,
 {
     match v {
         Value::Array(vec) => vec.iter().map(AsyncQuery::from_value).collect(),
@@ -0,0 +1,131 @@
+use crate::QueryError;
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+use core::fmt;
+use core::str::FromStr;
+
+macro_rules! base_operators {
+    ($($variant:ident => $name:literal,)*) => {
+        /// The operators understood by [BaseQuerier](crate::BaseQuerier), for building queries
+        /// without stringly-typed operator names.
+        ///
+        /// `OlderThan` and `NewerThan` are only provided with the `chrono` feature,
+        /// `DecimalEq`, `DecimalGt` and `DecimalLt` with the `decimal` feature,
+        /// and `HashEq` with the `sha2` feature.
+        ///
+        /// Names are written without the leading `$`, as everywhere else in this crate's API;
+        /// use [key](BaseOperator::key) for the key of the operator in a query document.
+        /// ```
+        /// use mongoquery::{BaseOperator, BaseQuerier, Querier};
+        /// use serde_json::{json, Map, Value};
+        ///
+        /// let mut condition = Map::new();
+        /// condition.insert(BaseOperator::Gt.key(), json!(20));
+        /// let querier = BaseQuerier::new(&json!({"qty": condition}));
+        /// assert!(querier.evaluate(Some(&json!({"qty": 25}))).unwrap());
+        ///
+        /// assert_eq!(BaseOperator::ElemMatch, "$elemMatch".parse().unwrap());
+        /// ```
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum BaseOperator {
+            $($variant,)*
+        }
+
+        impl BaseOperator {
+            /// Every base operator, in alphabetical order of their names.
+            pub const ALL: &'static [BaseOperator] = &[$(BaseOperator::$variant,)*];
+
+            /// The name of this operator, without the leading `$`, e.g. `"elemMatch"`.
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $(BaseOperator::$variant => $name,)*
+                }
+            }
+        }
+
+        impl FromStr for BaseOperator {
+            type Err = QueryError;
+
+            /// Parses an operator name, with or without the leading `$`.
+            ///
+            /// Unknown names fail with [QueryError::UnsupportedOperator].
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s.strip_prefix('$').unwrap_or(s) {
+                    $($name => Ok(BaseOperator::$variant),)*
+                    _ => Err(QueryError::UnsupportedOperator {
+                        operator: s.to_string(),
+                    }),
+                }
+            }
+        }
+    };
+}
+
+base_operators! {
+    All => "all",
+    And => "and",
+    ArrayDepth => "arrayDepth",
+    ArrayIntersects => "arrayIntersects",
+    AtIndex => "atIndex",
+    Between => "between",
+    CountIn => "countIn",
+    CountWhere => "countWhere",
+    DecimalEq => "decimalEq",
+    DecimalGt => "decimalGt",
+    DecimalLt => "decimalLt",
+    DeepKeyMatch => "deepKeyMatch",
+    ElemMatch => "elemMatch",
+    EndsWith => "endsWith",
+    Eq => "eq",
+    Exists => "exists",
+    Expr => "expr",
+    FieldEq => "fieldEq",
+    Gt => "gt",
+    Gte => "gte",
+    HasAnyKey => "hasAnyKey",
+    HasKeys => "hasKeys",
+    HashEq => "hashEq",
+    In => "in",
+    IsEven => "isEven",
+    IsNegative => "isNegative",
+    IsOdd => "isOdd",
+    IsPositive => "isPositive",
+    Lt => "lt",
+    Lte => "lte",
+    Mod => "mod",
+    Ne => "ne",
+    NewerThan => "newerThan",
+    Nin => "nin",
+    Nor => "nor",
+    Not => "not",
+    Nullish => "nullish",
+    NumKeys => "numKeys",
+    OlderThan => "olderThan",
+    Or => "or",
+    PointerExists => "pointerExists",
+    Regex => "regex",
+    RegexCapture => "regexCapture",
+    SetEq => "setEq",
+    Size => "size",
+    StartsWith => "startsWith",
+    StringMatches => "stringMatches",
+    Strlen => "strlen",
+    Truthy => "truthy",
+    Type => "type",
+    TypeIn => "typeIn",
+}
+
+impl BaseOperator {
+    /// The key of this operator in a query document, e.g. `"$elemMatch"`.
+    pub fn key(self) -> String {
+        format!("${}", self.as_str())
+    }
+}
+
+impl fmt::Display for BaseOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -0,0 +1,54 @@
+use crate::query::any_leaf;
+use crate::QueryError;
+use rust_decimal::Decimal;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+/// Compares the evaluatee against the condition as exact decimals, for `$decimalEq` and the like.
+///
+/// Both may be numbers or strings like `"4.25"` or `"1e-3"`. An array evaluatee matches if any of
+/// its leaves does. Values that don't parse as decimals fail with [QueryError::OperatorError].
+pub(crate) fn compare_decimal(
+    operator: &str,
+    evaluatee: Option<&Value>,
+    condition: &Value,
+    accept: fn(Ordering) -> bool,
+) -> Result<bool, QueryError> {
+    let parse = |value: &Value| {
+        parse_decimal(value).ok_or_else(|| QueryError::OperatorError {
+            operator: operator.to_string(),
+            reason: format!("{} is not a decimal", value),
+        })
+    };
+    let condition = parse(condition)?;
+    let Some(evaluatee) = evaluatee else {
+        return Ok(false);
+    };
+    let mut error = None;
+    let matched = any_leaf(evaluatee, &mut |e| match parse(e) {
+        Ok(e) => accept(e.cmp(&condition)),
+        Err(e) => {
+            error.get_or_insert(e);
+            false
+        }
+    });
+    match error {
+        Some(e) if !matched => Err(e),
+        _ => Ok(matched),
+    }
+}
+
+/// Parses a number, or a string in decimal or scientific notation, as a decimal.
+///
+/// Numbers are parsed from their JSON text, so `4.25` is exactly `4.25` rather than the nearest `f64`.
+fn parse_decimal(value: &Value) -> Option<Decimal> {
+    let text = match value {
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.trim().to_string(),
+        _ => return None,
+    };
+    Decimal::from_str(&text)
+        .or_else(|_| Decimal::from_scientific(&text))
+        .ok()
+}
@@ -0,0 +1,93 @@
+use crate::query::extract;
+use crate::{OperatorContext, QueryError};
+use alloc::borrow::Cow;
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+use regex::RegexBuilder;
+use serde_json::{Map, Value};
+
+/// Evaluates an aggregation expression, as used by `$expr`, against the root document of the context.
+///
+/// Strings starting with `$` are field paths resolved against the root (missing fields resolve to `null`),
+/// objects with a single `$`-prefixed key are expression operators, and everything else is a literal.
+/// Only `$regexMatch` is supported as an expression operator for now.
+pub(crate) fn evaluate(expression: &Value, context: &OperatorContext) -> Result<Value, QueryError> {
+    match expression {
+        Value::String(s) if s.starts_with('$') => {
+            Ok(extract(context.root, s[1..].split('.'), context.options)
+                .map_or(Value::Null, Cow::into_owned))
+        }
+        Value::Object(obj) if obj.len() == 1 && obj.keys().all(|k| k.starts_with('$')) => {
+            let (operator, args) = obj.iter().next().expect("length is checked");
+            match operator.as_str() {
+                "$regexMatch" => regex_match(args, context),
+                operator => Err(QueryError::OperatorError {
+                    operator: "expr".to_string(),
+                    reason: format!("unsupported expression operator: {}", operator),
+                }),
+            }
+        }
+        literal => Ok(literal.clone()),
+    }
+}
+
+/// Whether the result of an expression counts as a match: everything but `false`, `null` and `0` does.
+pub(crate) fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        _ => true,
+    }
+}
+
+/// `{"$regexMatch": {"input": <expression>, "regex": <pattern>, "options": <flags>}}`
+///
+/// A `null` or missing input doesn't match.
+/// The supported options are `i` (case-insensitive), `m` (multi-line), `s` (dot matches newline)
+/// and `x` (ignore whitespace).
+fn regex_match(args: &Value, context: &OperatorContext) -> Result<Value, QueryError> {
+    let error = |reason: &str| QueryError::OperatorError {
+        operator: "regexMatch".to_string(),
+        reason: reason.to_string(),
+    };
+    let args: &Map<String, Value> = args
+        .as_object()
+        .ok_or_else(|| error("arguments must be a document"))?;
+    let input = evaluate(args.get("input").unwrap_or(&Value::Null), context)?;
+    let pattern = match args
+        .get("regex")
+        .map(|regex| evaluate(regex, context))
+        .transpose()?
+    {
+        Some(Value::String(pattern)) => pattern,
+        _ => return Err(error("regex must be a string")),
+    };
+    let mut builder = RegexBuilder::new(&pattern);
+    match args.get("options") {
+        None => {}
+        Some(Value::String(options)) => {
+            for option in options.chars() {
+                match option {
+                    'i' => builder.case_insensitive(true),
+                    'm' => builder.multi_line(true),
+                    's' => builder.dot_matches_new_line(true),
+                    'x' => builder.ignore_whitespace(true),
+                    _ => return Err(error("options may only contain i, m, s and x")),
+                };
+            }
+        }
+        Some(_) => return Err(error("options must be a string")),
+    }
+    let regex = builder.build().map_err(|e| QueryError::CompileError {
+        operator: "regexMatch".to_string(),
+        detail: e.to_string(),
+    })?;
+    match input {
+        Value::Null => Ok(Value::Bool(false)),
+        Value::String(input) => Ok(Value::Bool(regex.is_match(&input))),
+        _ => Err(error("input must be a string")),
+    }
+}
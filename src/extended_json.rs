@@ -0,0 +1,101 @@
+use alloc::{format, string::String};
+use serde_json::{Map, Number, Value};
+
+/// Folds MongoDB Extended JSON wrappers into plain JSON, so that queries written against plain JSON
+/// match exported documents.
+///
+/// The following single-key wrappers are folded, at any depth:
+/// - `{"$numberInt": "5"}` and `{"$numberLong": "5"}` become the integer `5`.
+/// - `{"$numberDouble": "1.5"}` becomes the number `1.5`.
+/// - `{"$oid": "<hex>"}` becomes the `"<hex>"` string.
+/// - `{"$date": ...}` becomes an ISO-8601 UTC string with millisecond precision,
+///   e.g. `"2023-01-01T00:00:00.000Z"`, which sorts chronologically.
+///   Both the relaxed (ISO string) and canonical (`{"$numberLong": "<millis>"}`) forms are accepted.
+///
+/// Wrappers whose content can't be represented in plain JSON, like `{"$numberDouble": "NaN"}`,
+/// are left as they are, and so are all other values.
+/// ```
+/// use mongoquery::{canonicalize_extended_json, BaseQuerier, Querier};
+/// use serde_json::json;
+///
+/// let exported = json!({"_id": {"$oid": "5d505646cf6d4fe581014ab2"}, "qty": {"$numberLong": "25"}});
+/// let document = canonicalize_extended_json(&exported);
+/// assert_eq!(json!({"_id": "5d505646cf6d4fe581014ab2", "qty": 25}), document);
+///
+/// let querier = BaseQuerier::new(&json!({"qty": {"$gt": 20}}));
+/// assert!(querier.evaluate(Some(&document)).unwrap());
+/// ```
+pub fn canonicalize_extended_json(value: &Value) -> Value {
+    match value {
+        Value::Object(obj) => {
+            if let Some(folded) = fold_wrapper(obj) {
+                return folded;
+            }
+            Value::Object(
+                obj.iter()
+                    .map(|(key, value)| (key.clone(), canonicalize_extended_json(value)))
+                    .collect(),
+            )
+        }
+        Value::Array(arr) => Value::Array(arr.iter().map(canonicalize_extended_json).collect()),
+        value => value.clone(),
+    }
+}
+
+/// Folds a single Extended JSON wrapper, if `obj` is one that can be represented in plain JSON.
+fn fold_wrapper(obj: &Map<String, Value>) -> Option<Value> {
+    if obj.len() != 1 {
+        return None;
+    }
+    let (key, value) = obj.iter().next()?;
+    match (key.as_str(), value) {
+        ("$numberInt", Value::String(s)) => s.parse::<i32>().ok().map(Value::from),
+        ("$numberLong", Value::String(s)) => s.parse::<i64>().ok().map(Value::from),
+        ("$numberDouble", Value::String(s)) => s
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(Value::Number),
+        ("$oid", Value::String(s)) => Some(Value::String(s.clone())),
+        ("$date", Value::String(s)) => Some(Value::String(s.clone())),
+        ("$date", Value::Object(millis)) => match fold_wrapper(millis)? {
+            Value::Number(n) => n
+                .as_i64()
+                .map(|millis| Value::String(format_millis(millis))),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Formats milliseconds since the Unix epoch as an ISO-8601 UTC timestamp.
+fn format_millis(millis: i64) -> String {
+    let (days, millis_of_day) = (millis.div_euclid(86_400_000), millis.rem_euclid(86_400_000));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        millis_of_day / 3_600_000,
+        millis_of_day / 60_000 % 60,
+        millis_of_day / 1_000 % 60,
+        millis_of_day % 1_000
+    )
+}
+
+/// Converts days since the Unix epoch to a proleptic Gregorian `(year, month, day)`.
+///
+/// See Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
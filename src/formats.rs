@@ -0,0 +1,81 @@
+//! Conversions from other structured data formats to the JSON values queries are evaluated on.
+#[cfg(feature = "yaml")]
+use crate::QueryError;
+use serde_json::{Number, Value};
+
+/// Converts a float the way serde_json does: non-finite floats, which JSON can't represent, become `null`.
+fn float(f: f64) -> Value {
+    Number::from_f64(f).map_or(Value::Null, Value::Number)
+}
+
+/// Converts a YAML value to JSON.
+///
+/// Tags are dropped, keeping the tagged value. Scalar mapping keys become their YAML text,
+/// e.g. `1` and `true` become `"1"` and `"true"`, while sequence or mapping keys fail with
+/// [QueryError::SerializationError].
+#[cfg(feature = "yaml")]
+pub(crate) fn yaml_to_json(value: &serde_yaml::Value) -> Result<Value, QueryError> {
+    use serde_yaml::Value as Yaml;
+
+    Ok(match value {
+        Yaml::Null => Value::Null,
+        Yaml::Bool(b) => Value::Bool(*b),
+        Yaml::Number(n) => {
+            if let Some(n) = n.as_i64() {
+                Value::from(n)
+            } else if let Some(n) = n.as_u64() {
+                Value::from(n)
+            } else {
+                float(n.as_f64().unwrap_or(f64::NAN))
+            }
+        }
+        Yaml::String(s) => Value::String(s.clone()),
+        Yaml::Sequence(seq) => {
+            Value::Array(seq.iter().map(yaml_to_json).collect::<Result<_, _>>()?)
+        }
+        Yaml::Mapping(mapping) => {
+            let mut obj = serde_json::Map::with_capacity(mapping.len());
+            for (key, value) in mapping {
+                let key = match key {
+                    Yaml::String(s) => s.clone(),
+                    Yaml::Null => "null".to_string(),
+                    Yaml::Bool(b) => b.to_string(),
+                    Yaml::Number(n) => n.to_string(),
+                    _ => {
+                        return Err(<serde_json::Error as serde::ser::Error>::custom(
+                            "mapping keys must be scalars",
+                        )
+                        .into())
+                    }
+                };
+                obj.insert(key, yaml_to_json(value)?);
+            }
+            Value::Object(obj)
+        }
+        Yaml::Tagged(tagged) => yaml_to_json(&tagged.value)?,
+    })
+}
+
+/// Converts a TOML value to JSON.
+///
+/// Datetimes become their RFC 3339 text, e.g. `"1979-05-27T07:32:00Z"`, which sorts chronologically
+/// among datetimes of the same kind and offset.
+#[cfg(feature = "toml")]
+pub(crate) fn toml_to_json(value: &toml::Value) -> Value {
+    use toml::Value as Toml;
+
+    match value {
+        Toml::String(s) => Value::String(s.clone()),
+        Toml::Integer(i) => Value::from(*i),
+        Toml::Float(f) => float(*f),
+        Toml::Boolean(b) => Value::Bool(*b),
+        Toml::Datetime(datetime) => Value::String(datetime.to_string()),
+        Toml::Array(arr) => Value::Array(arr.iter().map(toml_to_json).collect()),
+        Toml::Table(table) => Value::Object(
+            table
+                .iter()
+                .map(|(key, value)| (key.clone(), toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
@@ -0,0 +1,84 @@
+use crate::QueryError;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Evaluates `$hashEq` with a condition `{"algo": "sha256", "hex": "..."}`, matching when the hash of
+/// the canonical JSON text of the evaluatee equals the hex digest, regardless of its case.
+///
+/// The canonical text is compact, with object keys in sorted order, so that it doesn't depend on
+/// how the document was formatted: `{"b": 1, "a": [1, 2]}` hashes as `{"a":[1,2],"b":1}`.
+/// Arrays are hashed whole, and missing fields don't match.
+pub(crate) fn hash_eq(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+    let error = |reason: &str| QueryError::OperatorError {
+        operator: "hashEq".to_string(),
+        reason: reason.to_string(),
+    };
+    let condition = condition
+        .as_object()
+        .ok_or_else(|| error("condition must be a document"))?;
+    if let Some(key) = condition.keys().find(|&key| key != "algo" && key != "hex") {
+        return Err(error(&format!("unknown key `{}`", key)));
+    }
+    match condition.get("algo") {
+        Some(Value::String(algo)) if algo == "sha256" => {}
+        Some(Value::String(algo)) => return Err(error(&format!("unknown algorithm `{}`", algo))),
+        _ => {
+            return Err(error(
+                "`algo` must be the name of an algorithm, like \"sha256\"",
+            ))
+        }
+    }
+    let hex = condition
+        .get("hex")
+        .and_then(Value::as_str)
+        .filter(|hex| hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+        .ok_or_else(|| error("`hex` must be a SHA-256 digest of 64 hexadecimal digits"))?;
+    let Some(evaluatee) = evaluatee else {
+        return Ok(false);
+    };
+    let mut text = String::new();
+    write_canonical(evaluatee, &mut text);
+    let digest = Sha256::digest(text.as_bytes());
+    let mut digest_hex = String::with_capacity(64);
+    for byte in digest {
+        write!(digest_hex, "{:02x}", byte).expect("writing to a string can't fail");
+    }
+    Ok(digest_hex.eq_ignore_ascii_case(hex))
+}
+
+/// Writes the compact JSON text of the value, with object keys in sorted order.
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Array(arr) => {
+            out.push('[');
+            for (i, e) in arr.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(e, out);
+            }
+            out.push(']');
+        }
+        Value::Object(obj) => {
+            let mut entries: Vec<_> = obj.iter().collect();
+            entries.sort_unstable_by_key(|(key, _)| *key);
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(value, out);
+            }
+            out.push('}');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
@@ -0,0 +1,84 @@
+use crate::query::{any_leaf, extract};
+use crate::EvaluationOptions;
+use crate::HashMap;
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use serde_json::Value;
+
+/// A collection of documents pre-hashed by the value at a field path.
+///
+/// Build an index once over a static collection, then filter it repeatedly with
+/// [Query::evaluate_with_index](crate::Query::evaluate_with_index).
+/// Arrays are indexed by each of their leaves, mirroring how `{"field": <scalar>}` matches arrays.
+/// ```
+/// use mongoquery::{BaseQuerier, Index, Querier};
+/// use serde_json::json;
+///
+/// let documents = vec![json!({"status": "A", "qty": 25}), json!({"status": "D", "qty": 50})];
+/// let index = Index::new("status", &documents);
+///
+/// let querier = BaseQuerier::new(&json!({"status": "D"}));
+/// assert_eq!(vec![&documents[1]], querier.evaluate_with_index(&index).unwrap());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Index<'d> {
+    field: String,
+    documents: Vec<&'d Value>,
+    buckets: HashMap<String, Vec<usize>>,
+}
+
+impl<'d> Index<'d> {
+    /// Indexes `documents` by the value at the dotted `field` path.
+    pub fn new(field: impl ToString, documents: impl IntoIterator<Item = &'d Value>) -> Self {
+        let field = field.to_string();
+        let documents: Vec<_> = documents.into_iter().collect();
+        let mut buckets: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, document) in documents.iter().enumerate() {
+            if let Some(value) = extract(
+                Some(document),
+                field.split('.'),
+                &EvaluationOptions::default(),
+            ) {
+                any_leaf(&value, &mut |leaf| {
+                    let bucket = buckets.entry(index_key(leaf)).or_default();
+                    // an array holding the same value twice must only yield its document once
+                    if bucket.last() != Some(&i) {
+                        bucket.push(i);
+                    }
+                    false
+                });
+            }
+        }
+        Self {
+            field,
+            documents,
+            buckets,
+        }
+    }
+
+    /// The dotted field path this index was built on.
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    /// All the indexed documents, in their original order.
+    pub fn documents(&self) -> &[&'d Value] {
+        &self.documents
+    }
+
+    /// The documents whose field equals `value` or is an array with it as a leaf, in their original order.
+    pub(crate) fn candidates(&self, value: &Value) -> impl Iterator<Item = &'d Value> + '_ {
+        self.buckets
+            .get(&index_key(value))
+            .into_iter()
+            .flatten()
+            .map(|&i| self.documents[i])
+    }
+}
+
+/// Values are keyed by their JSON serialization, which is equal exactly when the values are.
+fn index_key(value: &Value) -> String {
+    value.to_string()
+}
@@ -0,0 +1,59 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// A set of field paths shared by the queries built with it, to save memory when holding many
+/// queries over the same fields.
+///
+/// Queries built with [Querier::new_interned](crate::Querier::new_interned) reference the interned
+/// copy of each of their field paths rather than their own. The interner can be shared across threads,
+/// and strings stay interned for as long as it lives.
+/// ```
+/// use mongoquery::{BaseQuerier, Interner, Querier};
+/// use serde_json::json;
+///
+/// let interner = Interner::new();
+/// let a = BaseQuerier::new_interned(&json!({"qty": {"$gt": 20}}), &interner);
+/// let b = BaseQuerier::new_interned(&json!({"qty": 25, "item": "xyz"}), &interner);
+/// assert_eq!(2, interner.len());
+/// ```
+#[derive(Default)]
+pub struct Interner {
+    strings: Mutex<HashSet<Arc<str>>>,
+}
+
+impl Interner {
+    /// Constructs an empty interner.
+    pub fn new() -> Self {
+        Interner::default()
+    }
+
+    /// The interned copy of the string, interning it first if needed.
+    pub fn intern(&self, s: &str) -> Arc<str> {
+        let mut strings = self.strings.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(interned) = strings.get(s) {
+            return Arc::clone(interned);
+        }
+        let interned: Arc<str> = Arc::from(s);
+        strings.insert(Arc::clone(&interned));
+        interned
+    }
+
+    /// The number of interned strings.
+    pub fn len(&self) -> usize {
+        self.strings.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Whether no string is interned.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl fmt::Debug for Interner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interner")
+            .field("len", &self.len())
+            .finish()
+    }
+}
@@ -16,20 +16,78 @@
 //! assert!(querier.evaluate(Some(&object)).unwrap());
 //! ```
 //! [mongoquery]: https://github.com/kapouille/mongoquery
-pub use async_operator::{AsyncCustomOperator, AsyncOperatorContainer};
+//!
+//! # Features
+//! The default `std` feature links the standard library. Without it, the crate is `no_std` and needs
+//! `alloc` only: the synchronous engine is available, while [AsyncQuery], the async operator traits,
+//! [Interner] and the features other than `log` and `sha2` require `std`.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::borrow::Cow;
+use alloc::sync::Arc;
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+#[cfg(feature = "std")]
+pub use async_operator::{
+    AsyncContextOperator, AsyncCustomOperator, AsyncOperatorContainer, AsyncOperatorContext,
+    AsyncOperatorFuture, AsyncOperatorProvider, AsyncStandardOperator,
+};
+#[cfg(feature = "std")]
 pub use async_query::AsyncQuery;
-pub use operator::{CustomOperator, OperatorContainer, StandardOperator};
-pub use query::Query;
-use serde_json::Value;
-use std::cmp::Ordering;
+pub use base_operator::BaseOperator;
+use core::cmp::Ordering;
+use core::fmt::Debug;
+pub use extended_json::canonicalize_extended_json;
+#[cfg(not(feature = "std"))]
+use hashbrown::HashMap;
+pub use index::Index;
+#[cfg(feature = "std")]
+pub use interner::Interner;
+pub use operator::{
+    ContextOperator, CustomOperator, DetailedOperator, EvaluationOptions, FallbackOperator,
+    MatchOutcome, NegatedOperator, OperatorContainer, OperatorContext, StandardOperator,
+};
+#[cfg(feature = "timing")]
+pub use query::TimingHook;
+use query::{any_leaf, CompiledRegex, Condition};
+pub use query::{FailureInfo, JsonType, MatchReport, Mismatch, Query, QueryRef, SchemaWarning};
+use regex::Regex;
+use serde_json::{Number, Value};
+pub use shared::SharedQuery;
+#[cfg(feature = "std")]
 use std::collections::HashMap;
-use std::fmt::Debug;
+use sub_query::SubQueries;
 use thiserror::Error;
 
+#[cfg(feature = "chrono")]
+mod age;
+#[cfg(feature = "std")]
 mod async_operator;
+#[cfg(feature = "std")]
 mod async_query;
+mod base_operator;
+#[cfg(feature = "decimal")]
+mod decimal;
+mod expression;
+mod extended_json;
+#[cfg(any(feature = "yaml", feature = "toml"))]
+mod formats;
+#[cfg(feature = "sha2")]
+mod hash;
+mod index;
+#[cfg(feature = "std")]
+mod interner;
 mod operator;
 mod query;
+mod shared;
+mod sub_query;
 
 /// An enum that denotes possible query failure conditions.
 #[derive(Error, Debug)]
@@ -40,6 +98,22 @@ pub enum QueryError {
     /// Operator raised an error.
     #[error("Operator error: {reason} (from {operator}")]
     OperatorError { operator: String, reason: String },
+    /// A pattern in the condition of an operator failed to compile.
+    ///
+    /// Surfaced by [Querier::try_new] when the query is built, or by the operator during evaluation.
+    #[error("Compile error: {detail} (from {operator})")]
+    CompileError { operator: String, detail: String },
+    /// Evaluation invoked more operators than its budget allows.
+    ///
+    /// See [QueryRef::evaluate_budgeted].
+    #[error("Budget exceeded: more than {max_steps} operator invocations")]
+    BudgetExceeded { max_steps: usize },
+    /// The value to evaluate could not be serialized to JSON.
+    #[error("Serialization error: {source}")]
+    SerializationError {
+        #[from]
+        source: serde_json::Error,
+    },
 }
 
 /// A trait that provides static operators to [Querier].
@@ -49,6 +123,23 @@ pub trait OperatorProvider: Debug + Send + Sync {
     /// [Querier] calls this function at the start of the query execution to retrieve
     /// all the available standard operators.
     fn get_operators() -> HashMap<String, StandardOperator>;
+
+    /// A function that provides [ContextOperator]s to [Querier].
+    ///
+    /// Context operators take precedence over standard operators of the same name.
+    /// By default, no context operators are provided.
+    fn get_context_operators() -> HashMap<String, ContextOperator> {
+        HashMap::new()
+    }
+
+    /// The meta keys, without the leading `$`, that queries may carry without affecting what they match.
+    ///
+    /// Such keys parse as conditions that always hold, rather than as operators, so that queries copied
+    /// from MongoDB tooling, like `{"qty": 25, "$comment": "restock"}`, can be evaluated as they are.
+    /// By default, these are `comment`, `hint` and `maxTimeMS`.
+    fn ignored_keys() -> &'static [&'static str] {
+        &["comment", "hint", "maxTimeMS"]
+    }
 }
 
 /// A main interface to [mongoquery](crate).
@@ -77,12 +168,39 @@ pub trait Querier {
     fn new(query: &Value) -> Query<Self::Provider> {
         Query::from_value(query)
     }
+
+    /// Constructs new Query object, failing with [QueryError::CompileError] if any of its
+    /// regular expressions doesn't compile. See [QueryRef::validate].
+    fn try_new(query: &Value) -> Result<Query<Self::Provider>, QueryError> {
+        let query = Query::from_value(query);
+        query.validate()?;
+        Ok(query)
+    }
+
+    /// Constructs new QueryRef object that borrows from `query` instead of cloning it.
+    fn new_borrowed(query: &Value) -> QueryRef<'_, Self::Provider> {
+        QueryRef::from_value_borrowed(query)
+    }
+
+    /// Constructs new Query object whose field paths are interned by `interner`, see [Interner].
+    #[cfg(feature = "std")]
+    fn new_interned(query: &Value, interner: &Interner) -> Query<Self::Provider> {
+        let mut query = Query::from_value(query);
+        query.intern_fields(interner);
+        query
+    }
+
+    /// Constructs new [SharedQuery] object, for reuse across threads.
+    fn new_shared(query: &Value) -> SharedQuery<Self::Provider> {
+        SharedQuery::new(Query::from_value(query))
+    }
 }
 
 /// An async variant of [Querier].
+#[cfg(feature = "std")]
 pub trait AsyncQuerier {
-    /// An associated OperatorProvider that provides operators to this Querier.
-    type Provider: OperatorProvider;
+    /// An associated AsyncOperatorProvider that provides operators to this Querier.
+    type Provider: AsyncOperatorProvider;
 
     /// Constructs new Query object.
     fn new(query: &Value) -> AsyncQuery<Self::Provider> {
@@ -90,26 +208,301 @@ pub trait AsyncQuerier {
     }
 }
 
+/// Matches a single field of the value against a condition, using [BaseOperators].
+///
+/// `matches_field(&doc, "qty", &json!({"$gt": 20}))` is equivalent to evaluating
+/// the query `{"qty": {"$gt": 20}}`, without building the query document.
+/// The field is a path as in any query, e.g. `"size.uom"` or `"tags.0"`.
+/// ```
+/// use mongoquery::matches_field;
+/// use serde_json::json;
+///
+/// let doc = json!({"qty": 25, "size": {"uom": "cm"}, "tags": ["blank", "red"]});
+/// assert!(matches_field(&doc, "qty", &json!({"$gt": 20})).unwrap());
+/// assert!(matches_field(&doc, "size.uom", &json!("cm")).unwrap());
+/// assert!(!matches_field(&doc, "tags", &json!({"$size": 3})).unwrap());
+/// ```
+pub fn matches_field(value: &Value, field: &str, condition: &Value) -> Result<bool, QueryError> {
+    let query: QueryRef<'_, BaseOperators> = QueryRef::Compound(vec![Condition::Field {
+        field_name: field.into(),
+        op: QueryRef::from_value_borrowed(condition),
+    }]);
+    query.evaluate(Some(value))
+}
+
+/// Resolves a dotted field path against the value, the way field conditions do.
+///
+/// Numeric segments index into arrays, negative ones from the end, while other segments reach into
+/// the documents of an array in parallel, yielding an array of the results, if any, of the elements holding
+/// the rest of the path. The resolved value is
+/// borrowed from `value` unless an array was traversed in parallel. See [QueryRef] for the details.
+/// ```
+/// use mongoquery::resolve_path;
+/// use serde_json::json;
+///
+/// let doc = json!({"size": {"uom": "cm"}, "memos": [{"by": "shipping"}, {"by": "billing"}]});
+/// assert_eq!(Some(&json!("cm")), resolve_path(&doc, "size.uom").as_deref());
+/// assert_eq!(Some(&json!("billing")), resolve_path(&doc, "memos.-1.by").as_deref());
+/// assert_eq!(Some(&json!(["shipping", "billing"])), resolve_path(&doc, "memos.by").as_deref());
+/// assert_eq!(None, resolve_path(&doc, "size.h"));
+///
+/// let doc = json!({"memos": [{"by": "shipping"}, {"memo": "approved"}]});
+/// assert_eq!(Some(&json!(["shipping"])), resolve_path(&doc, "memos.by").as_deref());
+/// assert_eq!(None, resolve_path(&doc, "memos.to"));
+/// ```
+pub fn resolve_path<'v>(value: &'v Value, path: &str) -> Option<Cow<'v, Value>> {
+    query::extract(Some(value), path.split('.'), &EvaluationOptions::default())
+}
+
+/// Compares two values the way the comparison operators (`$gt`, `$lt`, ...) do.
+///
+/// Numbers are compared as `f64`s. Only finite numbers are comparable: [serde_json] can't represent
+/// NaN or infinities (`json!(f64::NAN)` is `null`), but should a custom deserializer produce one,
+/// it compares as `None` against everything, itself included, so no comparison matches it.
+/// String sentinels like `"NaN"` or `"Infinity"` are plain strings.
 pub fn value_partial_cmp(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+    let finite = |n: &serde_json::Number| n.as_f64().filter(|f| f.is_finite());
     if let (Value::Null, Value::Null) = (lhs, rhs) {
         Some(Ordering::Equal)
     } else if let (Value::Bool(lhs), Value::Bool(rhs)) = (lhs, rhs) {
         lhs.partial_cmp(rhs)
     } else if let (Value::Number(lhs), Value::Number(rhs)) = (lhs, rhs) {
-        lhs.as_f64()?.partial_cmp(&rhs.as_f64()?)
+        finite(lhs)?.partial_cmp(&finite(rhs)?)
     } else if let (Value::String(lhs), Value::String(rhs)) = (lhs, rhs) {
         lhs.partial_cmp(rhs)
     } else if let (Value::Array(lhs), Value::Array(rhs)) = (lhs, rhs) {
         lhs.len().partial_cmp(&rhs.len())
     } else if let (Value::Bool(_), Value::Number(rhs)) = (lhs, rhs) {
-        (1f64).partial_cmp(&rhs.as_f64()?)
+        (1f64).partial_cmp(&finite(rhs)?)
     } else if let (Value::Number(lhs), Value::Bool(_)) = (lhs, rhs) {
-        lhs.as_f64()?.partial_cmp(&1f64)
+        finite(lhs)?.partial_cmp(&1f64)
     } else {
         None
     }
 }
 
+/// A regular expression made only of literal characters, which can be matched without a regex engine.
+#[derive(Debug, PartialEq)]
+enum LiteralPattern<'a> {
+    /// `^literal$`
+    Exact(&'a str),
+    /// `^literal`
+    Prefix(&'a str),
+    /// `literal$`
+    Suffix(&'a str),
+    /// `literal`
+    Contains(&'a str),
+}
+
+impl<'a> LiteralPattern<'a> {
+    fn parse(pattern: &'a str) -> Option<LiteralPattern<'a>> {
+        let (starts, rest) = match pattern.strip_prefix('^') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let (ends, literal) = match rest.strip_suffix('$') {
+            Some(literal) => (true, literal),
+            None => (false, rest),
+        };
+        if literal.contains(|c| "\\.+*?()|[]{}^$".contains(c)) {
+            return None;
+        }
+        Some(match (starts, ends) {
+            (true, true) => LiteralPattern::Exact(literal),
+            (true, false) => LiteralPattern::Prefix(literal),
+            (false, true) => LiteralPattern::Suffix(literal),
+            (false, false) => LiteralPattern::Contains(literal),
+        })
+    }
+
+    fn is_match(&self, s: &str) -> bool {
+        match self {
+            LiteralPattern::Exact(literal) => s == *literal,
+            LiteralPattern::Prefix(literal) => s.starts_with(literal),
+            LiteralPattern::Suffix(literal) => s.ends_with(literal),
+            LiteralPattern::Contains(literal) => s.contains(literal),
+        }
+    }
+}
+
+/// A regular expression condition, as used by `$regex`.
+pub(crate) enum Pattern<'a> {
+    Literal(LiteralPattern<'a>),
+    Regex(Cow<'a, Regex>),
+}
+
+impl<'a> Pattern<'a> {
+    /// Compiles the condition of `operator`, skipping the regex engine for literal patterns,
+    /// unless `regex` was already compiled from it.
+    pub(crate) fn from_condition(
+        operator: &str,
+        condition: &'a Value,
+        regex: Option<&'a Regex>,
+    ) -> Result<Pattern<'a>, QueryError> {
+        let pattern = condition
+            .as_str()
+            .ok_or_else(|| QueryError::OperatorError {
+                operator: operator.to_string(),
+                reason: "condition must be a string".to_string(),
+            })?;
+        Ok(match (LiteralPattern::parse(pattern), regex) {
+            (Some(literal), _) => Pattern::Literal(literal),
+            (None, Some(regex)) => Pattern::Regex(Cow::Borrowed(regex)),
+            (None, None) => Pattern::Regex(Cow::Owned(compile(operator, pattern)?)),
+        })
+    }
+
+    fn is_match(&self, s: &str) -> bool {
+        match self {
+            Pattern::Literal(literal) => literal.is_match(s),
+            Pattern::Regex(regex) => regex.is_match(s),
+        }
+    }
+}
+
+fn compile(operator: &str, pattern: &str) -> Result<Regex, QueryError> {
+    Regex::new(pattern).map_err(|e| QueryError::CompileError {
+        operator: operator.to_string(),
+        detail: e.to_string(),
+    })
+}
+
+/// Compiles the regular expression in the condition of the `$regex`, `$deepKeyMatch` and `$regexCapture`
+/// operators as the query is built, so that it isn't compiled again for every document evaluated.
+///
+/// Literal `$regex` and `$deepKeyMatch` patterns need no compiling, and patterns that fail to compile
+/// are left for the operator to report when evaluated.
+pub(crate) fn compile_regex(operator: &str, condition: &Value) -> Option<CompiledRegex> {
+    let pattern = match operator {
+        "regex" | "deepKeyMatch" => condition
+            .as_str()
+            .filter(|pattern| LiteralPattern::parse(pattern).is_none()),
+        "regexCapture" => condition.get("pattern").and_then(Value::as_str),
+        _ => None,
+    };
+    pattern
+        .and_then(|pattern| Regex::new(pattern).ok())
+        .map(|regex| Arc::new(regex) as CompiledRegex)
+}
+
+/// The root document of the context, for operators that can't be evaluated without one.
+///
+/// Fails with [QueryError::OperatorError] when the query is evaluated against a missing value
+/// or one that isn't a document, rather than letting the operator silently not match.
+pub(crate) fn root_document<'a>(
+    operator: &str,
+    context: &OperatorContext<'a>,
+) -> Result<&'a Value, QueryError> {
+    match context.root {
+        Some(root @ Value::Object(_)) => Ok(root),
+        Some(_) => Err(QueryError::OperatorError {
+            operator: operator.to_string(),
+            reason: "root value is not a document".to_string(),
+        }),
+        None => Err(QueryError::OperatorError {
+            operator: operator.to_string(),
+            reason: "no root document to evaluate against".to_string(),
+        }),
+    }
+}
+
+/// Evaluates a numeric operator like `$isEven`, whose boolean condition says whether `property` should hold.
+///
+/// `property` returns `None` for numbers it is undefined for, which match neither way,
+/// and so do values that aren't numbers. An array evaluatee matches if any of its leaves does.
+fn number_property(
+    operator: &str,
+    evaluatee: Option<&Value>,
+    condition: &Value,
+    property: fn(&Number) -> Option<bool>,
+) -> Result<bool, QueryError> {
+    let expected = condition
+        .as_bool()
+        .ok_or_else(|| QueryError::OperatorError {
+            operator: operator.to_string(),
+            reason: "non-boolean condition".to_string(),
+        })?;
+    Ok(evaluatee.is_some_and(|e| {
+        any_leaf(
+            e,
+            &mut |e| matches!(e, Value::Number(n) if property(n) == Some(expected)),
+        )
+    }))
+}
+
+/// Whether an integral number is odd, or `None` if it has a fractional part.
+fn parity(n: &Number) -> Option<bool> {
+    if let Some(n) = n.as_i64() {
+        Some(n % 2 != 0)
+    } else if let Some(n) = n.as_u64() {
+        Some(n % 2 != 0)
+    } else {
+        n.as_f64()
+            .filter(|n| n % 1.0 == 0.0)
+            .map(|n| n % 2.0 != 0.0)
+    }
+}
+
+/// Evaluates `$hasKeys` if `all` is set, or `$hasAnyKey` otherwise.
+fn has_keys(
+    operator: &str,
+    evaluatee: Option<&Value>,
+    condition: &Value,
+    all: bool,
+) -> Result<bool, QueryError> {
+    let keys = condition
+        .as_array()
+        .filter(|keys| keys.iter().all(Value::is_string))
+        .ok_or_else(|| QueryError::OperatorError {
+            operator: operator.to_string(),
+            reason: "condition must be a list of strings".to_string(),
+        })?;
+    let Some(Value::Object(obj)) = evaluatee else {
+        return Ok(false);
+    };
+    let mut present = keys
+        .iter()
+        .filter_map(Value::as_str)
+        .map(|key| obj.contains_key(key));
+    Ok(if all {
+        present.all(|p| p)
+    } else {
+        present.any(|p| p)
+    })
+}
+
+/// Evaluates an affix operator like `$startsWith`, where `has_affix` tests a string for the condition.
+fn affix(
+    operator: &str,
+    evaluatee: Option<&Value>,
+    condition: &Value,
+    has_affix: fn(&str, &str) -> bool,
+) -> Result<bool, QueryError> {
+    let affix = condition
+        .as_str()
+        .ok_or_else(|| QueryError::OperatorError {
+            operator: operator.to_string(),
+            reason: "condition must be a string".to_string(),
+        })?;
+    Ok(match evaluatee {
+        Some(Value::String(s)) => has_affix(s, affix),
+        Some(Value::Array(arr)) => arr
+            .iter()
+            .filter_map(Value::as_str)
+            .any(|s| has_affix(s, affix)),
+        _ => false,
+    })
+}
+
+/// Whether `matches` holds for the value or, if it is an array, for any value nested in it.
+fn any_nested<F: FnMut(&Value) -> bool>(value: &Value, matches: &mut F) -> bool {
+    matches(value)
+        || match value {
+            Value::Array(arr) => arr.iter().any(|e| any_nested(e, matches)),
+            _ => false,
+        }
+}
+
 /// Basic [OperatorProvider] that implements some common MongoDB Query Operators.
 #[derive(Debug)]
 pub struct BaseOperators {}
@@ -128,65 +521,106 @@ impl BaseOperators {
             })
         }
     }
+    /// Matches when whether the evaluatee is null or missing is the boolean condition.
+    ///
+    /// Unlike `{"field": null}`, arrays containing null aren't nullish.
+    fn nullish(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        if let Value::Bool(should_be_nullish) = condition {
+            Ok(matches!(evaluatee, None | Some(Value::Null)) == *should_be_nullish)
+        } else {
+            Err(QueryError::OperatorError {
+                operator: "nullish".to_string(),
+                reason: "non-boolean condition".to_string(),
+            })
+        }
+    }
+    /// Matches the way a field equality does: a scalar condition matches the evaluatee or,
+    /// for arrays, any of its leaves, while arrays and documents match as a whole,
+    /// either the evaluatee itself or one of its elements. Like `{"a": null}`, `{"$eq": null}` also
+    /// matches a missing field, so `{"$ne": null}` only matches present, non-null fields.
     fn eq(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
-        Ok(evaluatee.map(|e| e == condition).unwrap_or(false))
+        Ok(match (evaluatee, condition) {
+            (None, Value::Null) => true,
+            (None, _) => false,
+            (Some(e), Value::Array(_) | Value::Object(_)) => {
+                e == condition || matches!(e, Value::Array(arr) if arr.contains(condition))
+            }
+            (Some(e), _) => any_leaf(e, &mut |e| e == condition),
+        })
     }
     fn ne(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
         Ok(!BaseOperators::eq(evaluatee, condition)?)
     }
-    fn gt(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
-        Ok(if let Some(evaluatee) = evaluatee {
-            matches!(
-                value_partial_cmp(evaluatee, condition),
-                Some(Ordering::Greater)
-            )
-        } else {
-            false
-        })
+    /// Compares the evaluatee against the condition with [value_partial_cmp].
+    ///
+    /// An array evaluatee matches if any of its leaves does, unless the condition is an array itself.
+    /// Incomparable values don't match. Under [EvaluationOptions::strict_comparison], running into one
+    /// fails the evaluation instead, unless another leaf of the evaluatee matched.
+    fn compare(
+        operator: &str,
+        evaluatee: Option<&Value>,
+        condition: &Value,
+        context: &OperatorContext,
+        accept: fn(Ordering) -> bool,
+    ) -> Result<bool, QueryError> {
+        let mut incomparable = None;
+        let mut compare = |e: &Value| match value_partial_cmp(e, condition) {
+            Some(ordering) => accept(ordering),
+            None => {
+                if context.options.strict_comparison && incomparable.is_none() {
+                    incomparable = Some(e.clone());
+                }
+                false
+            }
+        };
+        let matched = match evaluatee {
+            Some(arr @ Value::Array(_)) if !condition.is_array() => any_leaf(arr, &mut compare),
+            Some(evaluatee) => compare(evaluatee),
+            None => false,
+        };
+        match incomparable {
+            Some(e) if !matched => Err(QueryError::OperatorError {
+                operator: operator.to_string(),
+                reason: format!("cannot compare {} with {}", e, condition),
+            }),
+            _ => Ok(matched),
+        }
     }
-    fn gte(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
-        Ok(if let Some(evaluatee) = evaluatee {
-            matches!(
-                value_partial_cmp(evaluatee, condition),
-                Some(Ordering::Greater | Ordering::Equal)
-            )
-        } else {
-            false
-        })
+    fn gt(
+        evaluatee: Option<&Value>,
+        condition: &Value,
+        context: &OperatorContext,
+    ) -> Result<bool, QueryError> {
+        BaseOperators::compare("gt", evaluatee, condition, context, Ordering::is_gt)
     }
-    fn lt(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
-        Ok(if let Some(evaluatee) = evaluatee {
-            matches!(
-                value_partial_cmp(evaluatee, condition),
-                Some(Ordering::Less)
-            )
-        } else {
-            false
-        })
+    fn gte(
+        evaluatee: Option<&Value>,
+        condition: &Value,
+        context: &OperatorContext,
+    ) -> Result<bool, QueryError> {
+        BaseOperators::compare("gte", evaluatee, condition, context, Ordering::is_ge)
     }
-    fn lte(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
-        Ok(if let Some(evaluatee) = evaluatee {
-            matches!(
-                value_partial_cmp(evaluatee, condition),
-                Some(Ordering::Less | Ordering::Equal)
-            )
-        } else {
-            false
-        })
+    fn lt(
+        evaluatee: Option<&Value>,
+        condition: &Value,
+        context: &OperatorContext,
+    ) -> Result<bool, QueryError> {
+        BaseOperators::compare("lt", evaluatee, condition, context, Ordering::is_lt)
+    }
+    fn lte(
+        evaluatee: Option<&Value>,
+        condition: &Value,
+        context: &OperatorContext,
+    ) -> Result<bool, QueryError> {
+        BaseOperators::compare("lte", evaluatee, condition, context, Ordering::is_le)
     }
     fn r#in(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
         if let Value::Array(cond) = condition {
             match evaluatee {
-                Some(Value::Array(evaluatee)) => {
-                    for i in cond {
-                        for j in evaluatee {
-                            if i == j {
-                                return Ok(true);
-                            }
-                        }
-                    }
-                    Ok(false)
-                }
+                // elements of nested arrays are candidates too, at any depth
+                Some(Value::Array(evaluatee)) => Ok(evaluatee
+                    .iter()
+                    .any(|e| any_nested(e, &mut |e| cond.contains(e)))),
                 Some(v) => Ok(cond.contains(v)),
                 None => Ok(false),
             }
@@ -198,24 +632,914 @@ impl BaseOperators {
         }
     }
 
+    /// Matches when the evaluatee isn't in the condition, missing evaluatees included,
+    /// so that `{"$nin": [...]}` and `{"$not": {"$in": [...]}}` match the same values.
     fn nin(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
         Ok(!BaseOperators::r#in(evaluatee, condition)?)
     }
+
+    /// Matches when every member of the condition matches the evaluatee.
+    ///
+    /// Plain members must equal the evaluatee or one of its elements. Alternatively, all the members
+    /// can be `{"$elemMatch": <query>}` documents, each of which some element must satisfy;
+    /// mixing both kinds is an error, as in MongoDB. An empty condition matches nothing.
+    fn all<'a>(
+        evaluatee: Option<&'a Value>,
+        condition: &'a Value,
+    ) -> Result<SubQueries<'a>, QueryError> {
+        let error = |reason: &str| QueryError::OperatorError {
+            operator: "all".to_string(),
+            reason: reason.to_string(),
+        };
+        let members = condition
+            .as_array()
+            .ok_or_else(|| error("condition must be a list"))?;
+        let elem_matches: Vec<_> = members
+            .iter()
+            .filter_map(|member| match member {
+                Value::Object(obj) if obj.len() == 1 => obj.get("$elemMatch"),
+                _ => None,
+            })
+            .collect();
+        let evaluatee = match evaluatee {
+            Some(evaluatee) if !members.is_empty() => evaluatee,
+            _ => return Ok(SubQueries::Matched(false)),
+        };
+        if elem_matches.is_empty() {
+            return Ok(SubQueries::Matched(members.iter().all(|member| {
+                evaluatee == member
+                    || matches!(evaluatee, Value::Array(arr) if arr.contains(member))
+            })));
+        }
+        if elem_matches.len() != members.len() {
+            return Err(error("$elemMatch can't be mixed with other members"));
+        }
+        if elem_matches.iter().any(|query| !query.is_object()) {
+            return Err(error("$elemMatch argument must be a document"));
+        }
+        Ok(match evaluatee {
+            Value::Array(elements) => SubQueries::EachMatchesAnElement {
+                queries: elem_matches,
+                elements,
+            },
+            _ => SubQueries::Matched(false),
+        })
+    }
+
+    /// Matches arrays sharing at least one element with the condition array.
+    ///
+    /// Unlike `$in`, only arrays match, and elements are compared as a whole on both sides.
+    fn array_intersects(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        let other = condition
+            .as_array()
+            .ok_or_else(|| QueryError::OperatorError {
+                operator: "arrayIntersects".to_string(),
+                reason: "condition must be a list".to_string(),
+            })?;
+        Ok(match evaluatee {
+            Some(Value::Array(arr)) => arr.iter().any(|e| other.contains(e)),
+            _ => false,
+        })
+    }
+
+    /// Matches arrays holding the same set of elements as the condition, regardless of order and duplicates.
+    fn set_eq(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        let expected = condition
+            .as_array()
+            .ok_or_else(|| QueryError::OperatorError {
+                operator: "setEq".to_string(),
+                reason: "condition must be a list".to_string(),
+            })?;
+        Ok(match evaluatee {
+            Some(Value::Array(actual)) => {
+                actual.iter().all(|e| expected.contains(e))
+                    && expected.iter().all(|e| actual.contains(e))
+            }
+            _ => false,
+        })
+    }
+
+    /// Matches values within the `[low, high]` range of the condition, compared with [value_partial_cmp].
+    ///
+    /// Both bounds are inclusive unless a third `[low_exclusive, high_exclusive]` element says otherwise.
+    /// An array evaluatee matches if any single element lies within the range.
+    fn between(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        let malformed = || QueryError::OperatorError {
+            operator: "between".to_string(),
+            reason: "condition must be [low, high] or [low, high, [bool, bool]]".to_string(),
+        };
+        let (low, high, low_exclusive, high_exclusive) =
+            match condition.as_array().map(Vec::as_slice) {
+                Some([low, high]) => (low, high, false, false),
+                Some([low, high, Value::Array(exclusive)]) => match exclusive.as_slice() {
+                    [Value::Bool(l), Value::Bool(h)] => (low, high, *l, *h),
+                    _ => return Err(malformed()),
+                },
+                _ => return Err(malformed()),
+            };
+        let in_range = |v: &Value| {
+            let above_low = match value_partial_cmp(v, low) {
+                Some(Ordering::Greater) => true,
+                Some(Ordering::Equal) => !low_exclusive,
+                _ => false,
+            };
+            let below_high = match value_partial_cmp(v, high) {
+                Some(Ordering::Less) => true,
+                Some(Ordering::Equal) => !high_exclusive,
+                _ => false,
+            };
+            above_low && below_high
+        };
+        Ok(match evaluatee {
+            Some(Value::Array(arr)) => arr.iter().any(in_range),
+            Some(v) => in_range(v),
+            None => false,
+        })
+    }
+
+    /// `{"$mod": [divisor, remainder]}` matches numbers whose remainder by the divisor is the given one.
+    ///
+    /// As in MongoDB, the evaluatee, divisor and remainder are truncated to integers first.
+    /// An optional third element switches to float mode, `{"$mod": [0.25, 0, 1e-9]}`, where nothing is
+    /// truncated and the remainder may be off by up to that tolerance, for decimal data like prices.
+    /// An array evaluatee matches if any of its leaves does.
+    fn r#mod(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        let malformed = || QueryError::OperatorError {
+            operator: "mod".to_string(),
+            reason: "condition must be [divisor, remainder] or [divisor, remainder, tolerance]"
+                .to_string(),
+        };
+        let (divisor, remainder, tolerance) = match condition.as_array().map(Vec::as_slice) {
+            Some([divisor, remainder]) => (divisor, remainder, None),
+            Some([divisor, remainder, tolerance]) => (
+                divisor,
+                remainder,
+                Some(tolerance.as_f64().ok_or_else(malformed)?),
+            ),
+            _ => return Err(malformed()),
+        };
+        let (divisor, remainder) = (
+            divisor.as_f64().ok_or_else(malformed)?,
+            remainder.as_f64().ok_or_else(malformed)?,
+        );
+        let matches: Box<dyn Fn(f64) -> bool> = match tolerance {
+            None => {
+                // casts truncate toward zero
+                let (divisor, remainder) = (divisor as i64, remainder as i64);
+                if divisor == 0 {
+                    return Err(QueryError::OperatorError {
+                        operator: "mod".to_string(),
+                        reason: "divisor must not be zero".to_string(),
+                    });
+                }
+                Box::new(move |n| (n as i64).wrapping_rem(divisor) == remainder)
+            }
+            Some(tolerance) => {
+                if divisor == 0.0 {
+                    return Err(QueryError::OperatorError {
+                        operator: "mod".to_string(),
+                        reason: "divisor must not be zero".to_string(),
+                    });
+                }
+                Box::new(move |n| {
+                    // a remainder just below the divisor is also just above zero
+                    let actual = n % divisor;
+                    (actual - remainder).abs() <= tolerance
+                        || (actual.abs() - divisor.abs() - remainder).abs() <= tolerance
+                })
+            }
+        };
+        Ok(evaluatee.is_some_and(|e| any_leaf(e, &mut |e| e.as_f64().is_some_and(&matches))))
+    }
+    /// Matches strings against the regular expression in the condition.
+    ///
+    /// An array evaluatee matches if any of its string elements does; other evaluatees never match,
+    /// unless [EvaluationOptions::coerce_regex] is set, which matches numbers and booleans by their JSON text.
+    /// Flags are given inline in the pattern, e.g. `(?i)^jk`.
+    /// Literal patterns, optionally anchored with `^` and/or `$`, are matched with plain string
+    /// methods instead of compiling a regular expression.
+    fn regex(
+        evaluatee: Option<&Value>,
+        condition: &Value,
+        context: &OperatorContext,
+    ) -> Result<bool, QueryError> {
+        let pattern = Pattern::from_condition("regex", condition, context.regex)?;
+        let is_match = |value: &Value| match value {
+            Value::String(s) => pattern.is_match(s),
+            Value::Number(_) | Value::Bool(_) if context.options.coerce_regex => {
+                pattern.is_match(&value.to_string())
+            }
+            _ => false,
+        };
+        Ok(match evaluatee {
+            Some(Value::Array(arr)) => arr.iter().any(is_match),
+            Some(value) => is_match(value),
+            None => false,
+        })
+    }
+    /// Matches strings, or arrays with any string element, where a capture group of a regular expression
+    /// equals a string, for a condition `{"pattern": "(\\w)(\\w+)", "group": 1, "equals": "x"}`.
+    ///
+    /// The group is an index, `0` being the whole match, or the name of a named group.
+    /// Groups that don't participate in the match don't equal anything. The pattern is compiled
+    /// once, when the query is built; a pattern that doesn't compile fails with [QueryError::CompileError]
+    /// when evaluated, and a group the pattern doesn't have fails with [QueryError::OperatorError].
+    fn regex_capture(
+        evaluatee: Option<&Value>,
+        condition: &Value,
+        context: &OperatorContext,
+    ) -> Result<bool, QueryError> {
+        let error = |reason: String| QueryError::OperatorError {
+            operator: "regexCapture".to_string(),
+            reason,
+        };
+        let condition = condition
+            .as_object()
+            .ok_or_else(|| error("condition must be a document".to_string()))?;
+        if let Some(key) = condition
+            .keys()
+            .find(|&key| !matches!(key.as_str(), "pattern" | "group" | "equals"))
+        {
+            return Err(error(format!("unknown key `{}`", key)));
+        }
+        let pattern = condition
+            .get("pattern")
+            .and_then(Value::as_str)
+            .ok_or_else(|| error("`pattern` must be a string".to_string()))?;
+        let expected = condition
+            .get("equals")
+            .and_then(Value::as_str)
+            .ok_or_else(|| error("`equals` must be a string".to_string()))?;
+        let regex = match context.regex {
+            Some(regex) => Cow::Borrowed(regex),
+            None => Cow::Owned(compile("regexCapture", pattern)?),
+        };
+        let group = match condition.get("group") {
+            Some(Value::Number(n)) => n
+                .as_u64()
+                .filter(|&i| i < regex.captures_len() as u64)
+                .map(|i| i as usize)
+                .ok_or_else(|| error(format!("pattern has no group {}", n)))?,
+            Some(Value::String(name)) => regex
+                .capture_names()
+                .position(|n| n == Some(name))
+                .ok_or_else(|| error(format!("pattern has no group named `{}`", name)))?,
+            _ => return Err(error("`group` must be an index or a name".to_string())),
+        };
+        let captures = |s: &str| {
+            regex
+                .captures(s)
+                .and_then(|captures| captures.get(group))
+                .is_some_and(|capture| capture.as_str() == expected)
+        };
+        Ok(match evaluatee {
+            Some(Value::String(s)) => captures(s),
+            Some(Value::Array(arr)) => arr.iter().filter_map(Value::as_str).any(captures),
+            _ => false,
+        })
+    }
+    /// Matches strings, or arrays with any string element, starting with the condition string.
+    fn starts_with(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        affix("startsWith", evaluatee, condition, |s, affix| {
+            s.starts_with(affix)
+        })
+    }
+    /// Matches strings, or arrays with any string element, ending with the condition string.
+    fn ends_with(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        affix("endsWith", evaluatee, condition, |s, affix| {
+            s.ends_with(affix)
+        })
+    }
+    /// Matches when any key of the evaluatee, at any depth, matches the regular expression in the condition.
+    ///
+    /// Objects nested in arrays are searched too. Scalars have no keys and never match.
+    fn deep_key_match(
+        evaluatee: Option<&Value>,
+        condition: &Value,
+        context: &OperatorContext,
+    ) -> Result<bool, QueryError> {
+        fn any_key(value: &Value, pattern: &Pattern) -> bool {
+            match value {
+                Value::Object(obj) => obj
+                    .iter()
+                    .any(|(key, value)| pattern.is_match(key) || any_key(value, pattern)),
+                Value::Array(arr) => arr.iter().any(|e| any_key(e, pattern)),
+                _ => false,
+            }
+        }
+        let pattern = Pattern::from_condition("deepKeyMatch", condition, context.regex)?;
+        Ok(evaluatee.is_some_and(|e| any_key(e, &pattern)))
+    }
+
+    /// Matches when the truthiness of the evaluatee equals the boolean condition.
+    ///
+    /// `true`, non-zero numbers, non-empty strings, and non-empty arrays and objects are truthy;
+    /// everything else, including a missing evaluatee, is falsy.
+    fn truthy(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        let expected = condition
+            .as_bool()
+            .ok_or_else(|| QueryError::OperatorError {
+                operator: "truthy".to_string(),
+                reason: "non-boolean condition".to_string(),
+            })?;
+        let truthy = match evaluatee {
+            None | Some(Value::Null) => false,
+            Some(Value::Bool(b)) => *b,
+            Some(Value::Number(n)) => n.as_f64().is_some_and(|n| n != 0.0),
+            Some(Value::String(s)) => !s.is_empty(),
+            Some(Value::Array(arr)) => !arr.is_empty(),
+            Some(Value::Object(obj)) => !obj.is_empty(),
+        };
+        Ok(truthy == expected)
+    }
+
+    /// `{"$isEven": true}` matches even integers, `{"$isEven": false}` odd ones.
+    fn is_even(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        number_property("isEven", evaluatee, condition, |n| {
+            parity(n).map(|odd| !odd)
+        })
+    }
+    /// `{"$isOdd": true}` matches odd integers, `{"$isOdd": false}` even ones.
+    fn is_odd(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        number_property("isOdd", evaluatee, condition, parity)
+    }
+    /// `{"$isPositive": true}` matches numbers greater than zero, `{"$isPositive": false}` the others.
+    fn is_positive(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        number_property("isPositive", evaluatee, condition, |n| {
+            n.as_f64().map(|n| n > 0.0)
+        })
+    }
+    /// `{"$isNegative": true}` matches numbers less than zero, `{"$isNegative": false}` the others.
+    fn is_negative(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        number_property("isNegative", evaluatee, condition, |n| {
+            n.as_f64().map(|n| n < 0.0)
+        })
+    }
+
+    /// Whether the value is of the BSON type given by a string alias or a numeric type code.
+    ///
+    /// Only the JSON-representable types are supported: `double` (1), `string` (2), `object` (3),
+    /// `array` (4), `bool` (8), `null` (10), `int` (16) and `long` (18), plus the `number` alias.
+    /// Integers fitting in 32 bits are `int`s, other integers are `long`s and the rest are `double`s.
+    /// Numeric codes of types JSON can't represent (e.g. 7 for ObjectId) never match,
+    /// while unknown aliases are an error, reported as coming from `operator`.
+    fn is_of_type(operator: &str, value: &Value, type_spec: &Value) -> Result<bool, QueryError> {
+        let is_int = |value: &Value| value.as_i64().is_some_and(|n| i32::try_from(n).is_ok());
+        let is_long = |value: &Value| (value.is_i64() || value.is_u64()) && !is_int(value);
+        let code = match type_spec {
+            Value::Number(n) => match n.as_i64() {
+                Some(code) => code,
+                None => {
+                    return Err(QueryError::OperatorError {
+                        operator: operator.to_string(),
+                        reason: "type code must be an integer".to_string(),
+                    })
+                }
+            },
+            Value::String(alias) => match alias.as_str() {
+                "double" => 1,
+                "string" => 2,
+                "object" => 3,
+                "array" => 4,
+                "bool" => 8,
+                "null" => 10,
+                "int" => 16,
+                "long" => 18,
+                "number" => return Ok(value.is_number()),
+                alias => {
+                    return Err(QueryError::OperatorError {
+                        operator: operator.to_string(),
+                        reason: format!("unknown type alias: {}", alias),
+                    })
+                }
+            },
+            _ => {
+                return Err(QueryError::OperatorError {
+                    operator: operator.to_string(),
+                    reason: "type must be a string alias or a numeric code".to_string(),
+                })
+            }
+        };
+        Ok(match code {
+            1 => value.is_f64(),
+            2 => value.is_string(),
+            3 => value.is_object(),
+            4 => value.is_array(),
+            8 => value.is_boolean(),
+            10 => value.is_null(),
+            16 => is_int(value),
+            18 => is_long(value),
+            _ => false,
+        })
+    }
+    /// Matches when the evaluatee is of any of the types in the condition, a type or an array of types.
+    ///
+    /// See [BaseOperators::is_of_type] for the supported types.
+    /// An array evaluatee matches if it is itself of the type, or if any of its elements is.
+    fn r#type(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        let types = match condition {
+            Value::Array(types) => types.as_slice(),
+            condition => core::slice::from_ref(condition),
+        };
+        BaseOperators::is_of_any_type("type", evaluatee, types)
+    }
+    /// Matches when the evaluatee is of any of the types in the condition array, like
+    /// `{"$typeIn": ["string", "number"]}`.
+    ///
+    /// This is [type](Self::r#type) with a list of types only, so that the intent is explicit.
+    /// An array evaluatee matches if it is itself of one of the types, or if any of its elements is.
+    fn type_in(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        let types = condition
+            .as_array()
+            .ok_or_else(|| QueryError::OperatorError {
+                operator: "typeIn".to_string(),
+                reason: "condition must be a list of types".to_string(),
+            })?;
+        BaseOperators::is_of_any_type("typeIn", evaluatee, types)
+    }
+    fn is_of_any_type(
+        operator: &str,
+        evaluatee: Option<&Value>,
+        types: &[Value],
+    ) -> Result<bool, QueryError> {
+        let evaluatee = match evaluatee {
+            Some(evaluatee) => evaluatee,
+            None => return Ok(false),
+        };
+        let matches = |value: &Value| -> Result<bool, QueryError> {
+            for type_spec in types {
+                if BaseOperators::is_of_type(operator, value, type_spec)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        };
+        if matches(evaluatee)? {
+            return Ok(true);
+        }
+        if let Value::Array(arr) = evaluatee {
+            for e in arr {
+                if matches(e)? {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+    /// Matches arrays whose element at `index` matches the query `match`, for a condition
+    /// `{"index": i, "match": <query>}`, like `{"index": 0, "match": {"$eq": 5}}`.
+    ///
+    /// Negative indices count from the end, as in paths. Arrays too short to have the element,
+    /// and evaluatees that aren't arrays, don't match.
+    fn at_index<'a>(
+        evaluatee: Option<&'a Value>,
+        condition: &'a Value,
+    ) -> Result<SubQueries<'a>, QueryError> {
+        let error = |reason: &str| QueryError::OperatorError {
+            operator: "atIndex".to_string(),
+            reason: reason.to_string(),
+        };
+        let condition = condition
+            .as_object()
+            .ok_or_else(|| error("condition must be a document"))?;
+        if let Some(key) = condition
+            .keys()
+            .find(|&key| key != "index" && key != "match")
+        {
+            return Err(error(&format!("unknown key `{}`", key)));
+        }
+        let index = condition
+            .get("index")
+            .and_then(Value::as_i64)
+            .ok_or_else(|| error("`index` must be an integer"))?;
+        let query = condition
+            .get("match")
+            .ok_or_else(|| error("`match` is required"))?;
+        let arr = match evaluatee {
+            Some(Value::Array(arr)) => arr,
+            _ => return Ok(SubQueries::Matched(false)),
+        };
+        let index = if index < 0 {
+            arr.len().checked_sub(index.unsigned_abs() as usize)
+        } else {
+            Some(index as usize)
+        };
+        match index.and_then(|i| arr.get(i)) {
+            Some(e) => Ok(SubQueries::Match {
+                query,
+                value: Cow::Borrowed(e),
+            }),
+            None => Ok(SubQueries::Matched(false)),
+        }
+    }
+    /// Matches arrays with at least `min` elements in `values`, for a condition `{"values": [...], "min": n}`.
+    ///
+    /// Elements are compared as a whole, and each counts, so `[5, 5]` has two elements in `[5]`.
+    /// Evaluatees that aren't arrays don't match.
+    fn count_in(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        let error = |reason: &str| QueryError::OperatorError {
+            operator: "countIn".to_string(),
+            reason: reason.to_string(),
+        };
+        let condition = condition
+            .as_object()
+            .ok_or_else(|| error("condition must be a document"))?;
+        if let Some(key) = condition
+            .keys()
+            .find(|&key| key != "values" && key != "min")
+        {
+            return Err(error(&format!("unknown key `{}`", key)));
+        }
+        let values = condition
+            .get("values")
+            .and_then(Value::as_array)
+            .ok_or_else(|| error("`values` must be a list"))?;
+        let min = condition
+            .get("min")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| error("`min` must be a non-negative integer"))?;
+        Ok(match evaluatee {
+            Some(Value::Array(arr)) => {
+                arr.iter()
+                    .filter(|e| values.contains(e))
+                    .take(min as usize)
+                    .count() as u64
+                    == min
+            }
+            _ => false,
+        })
+    }
+    /// Matches when the evaluatee equals the condition as an exact decimal, so that `"4.25"` equals `4.25`.
+    ///
+    /// Both may be numbers or decimal strings. Values that don't parse as decimals are errors.
+    #[cfg(feature = "decimal")]
+    fn decimal_eq(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        decimal::compare_decimal("decimalEq", evaluatee, condition, Ordering::is_eq)
+    }
+    /// Matches when the evaluatee is greater than the condition as an exact decimal, see [decimal_eq](Self::decimal_eq).
+    #[cfg(feature = "decimal")]
+    fn decimal_gt(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        decimal::compare_decimal("decimalGt", evaluatee, condition, Ordering::is_gt)
+    }
+    /// Matches when the evaluatee is less than the condition as an exact decimal, see [decimal_eq](Self::decimal_eq).
+    #[cfg(feature = "decimal")]
+    fn decimal_lt(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        decimal::compare_decimal("decimalLt", evaluatee, condition, Ordering::is_lt)
+    }
+    /// Matches arrays whose number of elements matching the query `predicate` satisfies `count`,
+    /// for a condition `{"predicate": <query>, "count": <count>}`, like
+    /// `{"predicate": {"$gte": 8}, "count": {"$gte": 2}}`.
+    ///
+    /// The count is a number of elements, or an operator document evaluated against the number.
+    /// Evaluatees that aren't arrays don't match.
+    fn count_where<'a>(
+        evaluatee: Option<&'a Value>,
+        condition: &'a Value,
+    ) -> Result<SubQueries<'a>, QueryError> {
+        let error = |reason: &str| QueryError::OperatorError {
+            operator: "countWhere".to_string(),
+            reason: reason.to_string(),
+        };
+        let condition = condition
+            .as_object()
+            .ok_or_else(|| error("condition must be a document"))?;
+        if let Some(key) = condition
+            .keys()
+            .find(|&key| key != "predicate" && key != "count")
+        {
+            return Err(error(&format!("unknown key `{}`", key)));
+        }
+        let predicate = condition
+            .get("predicate")
+            .ok_or_else(|| error("`predicate` is required"))?;
+        let count = condition
+            .get("count")
+            .filter(|&count| count.is_u64() || query::is_operator_document(count))
+            .ok_or_else(|| {
+                error("`count` must be a non-negative integer or an operator document")
+            })?;
+        Ok(match evaluatee {
+            Some(Value::Array(elements)) => SubQueries::CountWhere {
+                predicate,
+                elements,
+                count,
+            },
+            _ => SubQueries::Matched(false),
+        })
+    }
+    /// Matches values whose depth of nested arrays satisfies the condition: a depth, or an operator
+    /// document evaluated against the depth, like `{"$gte": 2}`.
+    ///
+    /// Scalars and documents have a depth of 0, and an array is one deeper than its deepest element,
+    /// so `[1]` and `[]` have a depth of 1 and `[1, [2]]` a depth of 2. Arrays within documents
+    /// don't count. Missing fields don't match.
+    fn array_depth<'a>(
+        evaluatee: Option<&'a Value>,
+        condition: &'a Value,
+    ) -> Result<SubQueries<'a>, QueryError> {
+        fn depth(value: &Value) -> usize {
+            match value {
+                Value::Array(arr) => 1 + arr.iter().map(depth).max().unwrap_or(0),
+                _ => 0,
+            }
+        }
+        if !condition.is_u64() && !query::is_operator_document(condition) {
+            return Err(QueryError::OperatorError {
+                operator: "arrayDepth".to_string(),
+                reason: "condition must be a non-negative integer or an operator document"
+                    .to_string(),
+            });
+        }
+        match evaluatee {
+            Some(evaluatee) => Ok(SubQueries::Match {
+                query: condition,
+                value: Cow::Owned(Value::from(depth(evaluatee))),
+            }),
+            None => Ok(SubQueries::Matched(false)),
+        }
+    }
+    /// Matches when the SHA-256 digest of the evaluatee's canonical JSON equals the condition's,
+    /// given as `{"algo": "sha256", "hex": "..."}`. Unknown algorithms are an error.
+    #[cfg(feature = "sha2")]
+    fn hash_eq(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        hash::hash_eq(evaluatee, condition)
+    }
+    /// Matches documents having all the top-level keys in the condition list, like `["qty", "price"]`.
+    ///
+    /// Evaluatees that aren't documents don't match.
+    fn has_keys(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        has_keys("hasKeys", evaluatee, condition, true)
+    }
+    /// Matches documents having any of the top-level keys in the condition list, see [has_keys](Self::has_keys).
+    fn has_any_key(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        has_keys("hasAnyKey", evaluatee, condition, false)
+    }
+    /// Matches documents whose number of keys satisfies the condition: a count, or an operator document
+    /// evaluated against the count, like `{"$gte": 5}`.
+    ///
+    /// Evaluatees that aren't documents don't match.
+    fn num_keys<'a>(
+        evaluatee: Option<&'a Value>,
+        condition: &'a Value,
+    ) -> Result<SubQueries<'a>, QueryError> {
+        if !condition.is_u64() && !query::is_operator_document(condition) {
+            return Err(QueryError::OperatorError {
+                operator: "numKeys".to_string(),
+                reason: "condition must be a non-negative integer or an operator document"
+                    .to_string(),
+            });
+        }
+        match evaluatee {
+            Some(Value::Object(obj)) => Ok(SubQueries::Match {
+                query: condition,
+                value: Cow::Owned(Value::from(obj.len())),
+            }),
+            _ => Ok(SubQueries::Matched(false)),
+        }
+    }
+    /// Matches arrays with exactly as many elements as the condition.
+    fn size(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        let size = condition
+            .as_u64()
+            .ok_or_else(|| QueryError::OperatorError {
+                operator: "size".to_string(),
+                reason: "condition must be a non-negative integer".to_string(),
+            })?;
+        Ok(matches!(evaluatee, Some(Value::Array(arr)) if arr.len() as u64 == size))
+    }
+    /// Matches strings whose length equals the condition.
+    ///
+    /// The length is counted in Unicode scalar values (`char`s), not bytes,
+    /// so `"héllo"` has a length of 5 even though it is 6 bytes long in UTF-8.
+    fn strlen(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        let length = condition
+            .as_u64()
+            .ok_or_else(|| QueryError::OperatorError {
+                operator: "strlen".to_string(),
+                reason: "condition must be a non-negative integer".to_string(),
+            })?;
+        match evaluatee {
+            Some(Value::String(s)) => Ok(s.chars().count() as u64 == length),
+            Some(_) => Err(QueryError::OperatorError {
+                operator: "strlen".to_string(),
+                reason: "evaluatee must be a string".to_string(),
+            }),
+            None => Ok(false),
+        }
+    }
+    /// Matches strings satisfying every constraint in a condition like
+    /// `{"minLen": 3, "maxLen": 10, "charset": "alnum"}`, where each key is optional.
+    ///
+    /// Lengths are counted in unicode scalar values, like [strlen](Self::strlen). The charset is one of
+    /// `alpha` (unicode letters), `alnum` (unicode letters and numbers), `digit` (`0` to `9`) or `ascii`,
+    /// and all characters must belong to it. Evaluatees that aren't strings don't match.
+    fn string_matches(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        let error = |reason: &str| QueryError::OperatorError {
+            operator: "stringMatches".to_string(),
+            reason: reason.to_string(),
+        };
+        let condition = condition
+            .as_object()
+            .ok_or_else(|| error("condition must be a document"))?;
+        if let Some(key) = condition
+            .keys()
+            .find(|&key| !["minLen", "maxLen", "charset"].contains(&key.as_str()))
+        {
+            return Err(error(&format!("unknown key `{}`", key)));
+        }
+        let length = |key: &str| {
+            condition
+                .get(key)
+                .map(|length| {
+                    length
+                        .as_u64()
+                        .ok_or_else(|| error(&format!("`{}` must be a non-negative integer", key)))
+                })
+                .transpose()
+        };
+        let min_len = length("minLen")?.unwrap_or(0);
+        let max_len = length("maxLen")?.unwrap_or(u64::MAX);
+        let charset: Option<fn(&char) -> bool> = match condition.get("charset") {
+            None => None,
+            Some(charset) => Some(match charset.as_str() {
+                Some("alpha") => |c| c.is_alphabetic(),
+                Some("alnum") => |c| c.is_alphanumeric(),
+                Some("digit") => char::is_ascii_digit,
+                Some("ascii") => char::is_ascii,
+                _ => {
+                    return Err(error(
+                        "`charset` must be one of \"alpha\", \"alnum\", \"digit\" or \"ascii\"",
+                    ))
+                }
+            }),
+        };
+        let Some(Value::String(s)) = evaluatee else {
+            return Ok(false);
+        };
+        let length = s.chars().count() as u64;
+        Ok((min_len..=max_len).contains(&length)
+            && charset.is_none_or(|charset| s.chars().all(|c| charset(&c))))
+    }
+
+    /// Matches when the aggregation expression in the condition evaluates to a truthy value.
+    ///
+    /// The expression is evaluated against the root document, regardless of the evaluatee.
+    /// See the `expression` module for the supported subset of aggregation expressions.
+    fn expr(
+        _evaluatee: Option<&Value>,
+        condition: &Value,
+        context: &OperatorContext,
+    ) -> Result<bool, QueryError> {
+        root_document("expr", context)?;
+        Ok(expression::is_truthy(&expression::evaluate(
+            condition, context,
+        )?))
+    }
+
+    /// Matches when the evaluatee equals the field of the root document at the dotted path in the condition.
+    ///
+    /// Doesn't match when either field is missing.
+    fn field_eq(
+        evaluatee: Option<&Value>,
+        condition: &Value,
+        context: &OperatorContext,
+    ) -> Result<bool, QueryError> {
+        if let Value::String(path) = condition {
+            let root = root_document("fieldEq", context)?;
+            let other = query::extract(Some(root), path.split('.'), context.options);
+            Ok(matches!((evaluatee, other), (Some(e), Some(o)) if *e == *o))
+        } else {
+            Err(QueryError::OperatorError {
+                operator: "fieldEq".to_string(),
+                reason: "condition must be a field path string".to_string(),
+            })
+        }
+    }
+
+    /// Matches when the condition, an RFC 6901 JSON Pointer, resolves in the root document.
+    fn pointer_exists(
+        _evaluatee: Option<&Value>,
+        condition: &Value,
+        context: &OperatorContext,
+    ) -> Result<bool, QueryError> {
+        if let Value::String(pointer) = condition {
+            Ok(root_document("pointerExists", context)?
+                .pointer(pointer)
+                .is_some())
+        } else {
+            Err(QueryError::OperatorError {
+                operator: "pointerExists".to_string(),
+                reason: "condition must be a JSON pointer string".to_string(),
+            })
+        }
+    }
+
+    /// Matches when the evaluatee, an RFC 3339 timestamp, is older than the condition:
+    /// a number of seconds or an ISO 8601 duration like `"P1D"`, counted back from
+    /// [EvaluationOptions::now], or the current time if that isn't set.
+    #[cfg(feature = "chrono")]
+    fn older_than(
+        evaluatee: Option<&Value>,
+        condition: &Value,
+        context: &OperatorContext,
+    ) -> Result<bool, QueryError> {
+        age::compare_age("olderThan", evaluatee, condition, context, true)
+    }
+
+    /// Matches when the evaluatee, an RFC 3339 timestamp, is newer than the condition,
+    /// as for [older_than](Self::older_than).
+    #[cfg(feature = "chrono")]
+    fn newer_than(
+        evaluatee: Option<&Value>,
+        condition: &Value,
+        context: &OperatorContext,
+    ) -> Result<bool, QueryError> {
+        age::compare_age("newerThan", evaluatee, condition, context, false)
+    }
 }
 
 impl OperatorProvider for BaseOperators {
     fn get_operators() -> HashMap<String, StandardOperator> {
-        let mut map: HashMap<String, StandardOperator> = HashMap::new();
-        map.insert("exists".into(), BaseOperators::exists);
-        map.insert("eq".into(), BaseOperators::eq);
-        map.insert("ne".into(), BaseOperators::ne);
-        map.insert("gt".into(), BaseOperators::gt);
-        map.insert("gte".into(), BaseOperators::gte);
-        map.insert("lt".into(), BaseOperators::lt);
-        map.insert("lte".into(), BaseOperators::lte);
-        map.insert("in".into(), BaseOperators::r#in);
-        map.insert("nin".into(), BaseOperators::nin);
-        map
+        let operators: &[(BaseOperator, StandardOperator)] = &[
+            (
+                BaseOperator::ArrayIntersects,
+                BaseOperators::array_intersects,
+            ),
+            (BaseOperator::CountIn, BaseOperators::count_in),
+            #[cfg(feature = "decimal")]
+            (BaseOperator::DecimalEq, BaseOperators::decimal_eq),
+            #[cfg(feature = "decimal")]
+            (BaseOperator::DecimalGt, BaseOperators::decimal_gt),
+            #[cfg(feature = "decimal")]
+            (BaseOperator::DecimalLt, BaseOperators::decimal_lt),
+            (BaseOperator::EndsWith, BaseOperators::ends_with),
+            (BaseOperator::Exists, BaseOperators::exists),
+            (BaseOperator::Eq, BaseOperators::eq),
+            (BaseOperator::Ne, BaseOperators::ne),
+            (BaseOperator::HasAnyKey, BaseOperators::has_any_key),
+            (BaseOperator::HasKeys, BaseOperators::has_keys),
+            #[cfg(feature = "sha2")]
+            (BaseOperator::HashEq, BaseOperators::hash_eq),
+            (BaseOperator::In, BaseOperators::r#in),
+            (BaseOperator::IsEven, BaseOperators::is_even),
+            (BaseOperator::IsNegative, BaseOperators::is_negative),
+            (BaseOperator::IsOdd, BaseOperators::is_odd),
+            (BaseOperator::IsPositive, BaseOperators::is_positive),
+            (BaseOperator::Mod, BaseOperators::r#mod),
+            (BaseOperator::Nin, BaseOperators::nin),
+            (BaseOperator::Nullish, BaseOperators::nullish),
+            (BaseOperator::Between, BaseOperators::between),
+            (BaseOperator::SetEq, BaseOperators::set_eq),
+            (BaseOperator::Size, BaseOperators::size),
+            (BaseOperator::StartsWith, BaseOperators::starts_with),
+            (BaseOperator::StringMatches, BaseOperators::string_matches),
+            (BaseOperator::Strlen, BaseOperators::strlen),
+            (BaseOperator::Truthy, BaseOperators::truthy),
+            (BaseOperator::Type, BaseOperators::r#type),
+            (BaseOperator::TypeIn, BaseOperators::type_in),
+        ];
+        operators
+            .iter()
+            .map(|&(operator, f)| (operator.as_str().to_string(), f))
+            .collect()
+    }
+
+    fn get_context_operators() -> HashMap<String, ContextOperator> {
+        let operators: &[(BaseOperator, ContextOperator)] = &[
+            (BaseOperator::All, |e, c, ctx| {
+                BaseOperators::all(e, c)?.evaluate(ctx)
+            }),
+            (BaseOperator::ArrayDepth, |e, c, ctx| {
+                BaseOperators::array_depth(e, c)?.evaluate(ctx)
+            }),
+            (BaseOperator::AtIndex, |e, c, ctx| {
+                BaseOperators::at_index(e, c)?.evaluate(ctx)
+            }),
+            (BaseOperator::CountWhere, |e, c, ctx| {
+                BaseOperators::count_where(e, c)?.evaluate(ctx)
+            }),
+            (BaseOperator::DeepKeyMatch, BaseOperators::deep_key_match),
+            (BaseOperator::Expr, BaseOperators::expr),
+            (BaseOperator::FieldEq, BaseOperators::field_eq),
+            (BaseOperator::Gt, BaseOperators::gt),
+            (BaseOperator::Gte, BaseOperators::gte),
+            (BaseOperator::Lt, BaseOperators::lt),
+            (BaseOperator::Lte, BaseOperators::lte),
+            (BaseOperator::NumKeys, |e, c, ctx| {
+                BaseOperators::num_keys(e, c)?.evaluate(ctx)
+            }),
+            (BaseOperator::PointerExists, BaseOperators::pointer_exists),
+            (BaseOperator::Regex, BaseOperators::regex),
+            (BaseOperator::RegexCapture, BaseOperators::regex_capture),
+            #[cfg(feature = "chrono")]
+            (BaseOperator::OlderThan, BaseOperators::older_than),
+            #[cfg(feature = "chrono")]
+            (BaseOperator::NewerThan, BaseOperators::newer_than),
+        ];
+        operators
+            .iter()
+            .map(|&(operator, f)| (operator.as_str().to_string(), f))
+            .collect()
     }
 }
 
@@ -225,8 +1549,38 @@ impl Querier for BaseQuerier {
     type Provider = BaseOperators;
 }
 
-/// An AsyncQuerier that uses [BaseOperator] as its operator provider.
+#[cfg(feature = "std")]
+impl AsyncOperatorProvider for BaseOperators {
+    // operators with sub-queries, evaluated with the async operators too
+    fn get_async_context_operators() -> HashMap<String, AsyncContextOperator> {
+        let operators: &[(BaseOperator, AsyncContextOperator)] = &[
+            (BaseOperator::All, |e, c, ctx| {
+                Box::pin(async move { BaseOperators::all(e, c)?.evaluate_async(ctx).await })
+            }),
+            (BaseOperator::ArrayDepth, |e, c, ctx| {
+                Box::pin(async move { BaseOperators::array_depth(e, c)?.evaluate_async(ctx).await })
+            }),
+            (BaseOperator::AtIndex, |e, c, ctx| {
+                Box::pin(async move { BaseOperators::at_index(e, c)?.evaluate_async(ctx).await })
+            }),
+            (BaseOperator::CountWhere, |e, c, ctx| {
+                Box::pin(async move { BaseOperators::count_where(e, c)?.evaluate_async(ctx).await })
+            }),
+            (BaseOperator::NumKeys, |e, c, ctx| {
+                Box::pin(async move { BaseOperators::num_keys(e, c)?.evaluate_async(ctx).await })
+            }),
+        ];
+        operators
+            .iter()
+            .map(|&(operator, f)| (operator.as_str().to_string(), f))
+            .collect()
+    }
+}
+
+/// An AsyncQuerier that uses [BaseOperators] as its operator provider.
+#[cfg(feature = "std")]
 pub struct AsyncBaseQuerier {}
+#[cfg(feature = "std")]
 impl AsyncQuerier for AsyncBaseQuerier {
     type Provider = BaseOperators;
 }
@@ -283,6 +1637,45 @@ mod test {
         assert!(query.evaluate(Some(&doc)).unwrap());
     }
 
+    #[test]
+    fn test_regex_literal_fast_path() {
+        assert_eq!(
+            Some(LiteralPattern::Prefix("jkl")),
+            LiteralPattern::parse("^jkl")
+        );
+        assert_eq!(
+            Some(LiteralPattern::Exact("jkl")),
+            LiteralPattern::parse("^jkl$")
+        );
+        assert_eq!(
+            Some(LiteralPattern::Suffix("kl")),
+            LiteralPattern::parse("kl$")
+        );
+        assert_eq!(
+            Some(LiteralPattern::Contains("k")),
+            LiteralPattern::parse("k")
+        );
+        assert_eq!(Some(LiteralPattern::Exact("")), LiteralPattern::parse("^$"));
+        for pattern in [
+            "^j.l", "^jk+", "(?i)^jkl", "^jkl\\$", "^a|b", "^[jk]", "x^y", "a$b",
+        ] {
+            assert_eq!(None, LiteralPattern::parse(pattern), "{pattern}");
+        }
+
+        let haystacks = ["jkl", "jklm", "ajkl", "xyz", "", "JKL", "jk", "日本jkl"];
+        for pattern in ["^jkl", "^jkl$", "kl$", "k", "^$", "", "日本", "^日本"] {
+            let literal = LiteralPattern::parse(pattern).unwrap();
+            let regex = Regex::new(pattern).unwrap();
+            for haystack in haystacks {
+                assert_eq!(
+                    regex.is_match(haystack),
+                    literal.is_match(haystack),
+                    "{pattern} on {haystack}"
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_query_match_empty_values() {
         let doc = json!({ "item": "journal", "qty": 25, "size": { "h": 14, "w": 21, "uom": "cm" }, "status": "A" });
@@ -1,6 +1,12 @@
+use crate::HashMap;
 use crate::QueryError;
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+};
+use core::fmt;
+use regex::Regex;
 use serde_json::Value;
-use std::collections::HashMap;
 
 /// A function pointer that represents specific MongoDB Query Operator.  
 ///
@@ -49,6 +55,106 @@ use std::collections::HashMap;
 /// not present in the query.
 pub type StandardOperator = fn(Option<&Value>, &Value) -> Result<bool, QueryError>;
 
+/// Options tuning how a [Query](crate::Query) is evaluated.
+///
+/// See [Query::evaluate_with_options](crate::Query::evaluate_with_options).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EvaluationOptions {
+    /// Whether comparing values of incomparable types (e.g. `{"$gt": 5}` against a string)
+    /// fails with [QueryError::OperatorError] rather than not matching.
+    pub strict_comparison: bool,
+    /// Whether field names in paths match document keys regardless of ASCII case,
+    /// so that `{"Qty": 25}` matches `{"qty": 25}`. Exact matches are preferred.
+    /// See [unicode_case_folding](Self::unicode_case_folding) for other scripts.
+    ///
+    /// This is not how MongoDB behaves.
+    pub case_insensitive_fields: bool,
+    /// Whether case-insensitive comparisons, like those of [case_insensitive_fields](Self::case_insensitive_fields),
+    /// use full Unicode case folding rather than ASCII case, so that `"CAFÉ"` matches `"café"`
+    /// and `"STRASSE"` matches `"straße"`.
+    ///
+    /// Folding is locale-independent: the Turkish dotted and dotless `i` don't match `I` and `İ`
+    /// the way Turkish speakers would expect.
+    pub unicode_case_folding: bool,
+    /// Whether arrays are only matched as a whole, disabling implicit array descent:
+    /// `{"ratings": 5}` then only matches a `ratings` of exactly `5`, not an array containing it,
+    /// and a path like `items.name` no longer reaches into the documents of an `items` array.
+    /// Numeric path segments still index into arrays.
+    ///
+    /// This is not how MongoDB behaves. Operators keep their own array semantics.
+    pub strict_array: bool,
+    /// Whether `{"$fieldRef": "path"}` in operator conditions is replaced by the field of the root
+    /// document at that dotted path before the operator is evaluated, so that
+    /// `{"a": {"$gt": {"$fieldRef": "threshold"}}}` compares `a` to the document's own `threshold`.
+    ///
+    /// References may appear at any depth of a condition, as in `{"$in": [{"$fieldRef": "b"}, 0]}`.
+    /// Referencing a field missing from the root document fails with [QueryError::OperatorError].
+    pub resolve_field_refs: bool,
+    /// Whether `$regex` also matches numbers and booleans, by their JSON text: `{"$regex": "^2"}`
+    /// then matches `25`, and `{"$regex": "^t"}` matches `true`.
+    ///
+    /// This is not how MongoDB behaves, whose `$regex` only matches strings.
+    pub coerce_regex: bool,
+    /// Whether a field path descending into a scalar, like `size.h.x` where `size.h` is a number,
+    /// fails with [QueryError::OperatorError] rather than resolving to a missing field.
+    ///
+    /// This catches queries that don't fit a known schema. Paths through `null` and missing fields
+    /// are still missing, and only fields of field conditions are checked.
+    pub error_on_path_through_scalar: bool,
+    /// The reference time `$olderThan` and `$newerThan` measure ages from, or `None` for the current time.
+    #[cfg(feature = "chrono")]
+    pub now: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Evaluation state made available to [ContextOperator]s.
+#[derive(Clone, Copy)]
+pub struct OperatorContext<'a> {
+    /// The value the query is being evaluated against, if any.
+    pub root: Option<&'a Value>,
+    /// The options the query is being evaluated with.
+    pub options: &'a EvaluationOptions,
+    pub(crate) evaluation: &'a dyn SubQueryEvaluator,
+    /// The regular expression of the operator's condition, if compiled when the query was built.
+    pub(crate) regex: Option<&'a Regex>,
+}
+
+impl OperatorContext<'_> {
+    /// Evaluates `query` against `value` as part of the query being evaluated: with the same
+    /// operators, options and root value, counting towards the same budget.
+    ///
+    /// Use this for operators whose conditions hold queries, like `$countWhere`'s predicate.
+    /// Sub-queries of async queries only have the synchronous operators of the provider at hand;
+    /// [AsyncContextOperator](crate::AsyncContextOperator)s evaluate theirs with every operator.
+    pub fn evaluate_sub_query(
+        &self,
+        query: &Value,
+        value: Option<&Value>,
+    ) -> Result<bool, QueryError> {
+        self.evaluation.evaluate_sub_query(query, value)
+    }
+}
+
+impl fmt::Debug for OperatorContext<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OperatorContext")
+            .field("root", &self.root)
+            .field("options", &self.options)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The evaluation an [OperatorContext] belongs to.
+pub(crate) trait SubQueryEvaluator {
+    fn evaluate_sub_query(&self, query: &Value, value: Option<&Value>) -> Result<bool, QueryError>;
+}
+
+/// A [StandardOperator] that is also given the [OperatorContext] of the evaluation.
+///
+/// Use this for static operators that need to look beyond their evaluatee, e.g. at the root document.
+/// These operators are provided via
+/// [OperatorProvider::get_context_operators](crate::OperatorProvider::get_context_operators).
+pub type ContextOperator = fn(Option<&Value>, &Value, &OperatorContext) -> Result<bool, QueryError>;
+
 /// A trait that represents custom operator.  
 /// See [StandardOperator](crate::StandardOperator)'s documentation for differences between `StandardOperator` and `CustomOperator`.
 pub trait CustomOperator {
@@ -62,19 +168,125 @@ pub trait CustomOperator {
     /// - If the return value is `Ok(false)`, then the evaluatee does not match this operator's condition.  
     /// - If the return value is `Err(QueryError)`, the entire query fails.
     fn evaluate(&self, evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError>;
+
+    /// Evaluate this operator like [evaluate](CustomOperator::evaluate), knowing which field is evaluated.
+    ///
+    /// `field_name` is the name of the innermost field condition the operator appears in, as written
+    /// in the query: `"uom"` in `{"size": {"uom": {"$op": 1}}}`, `"ratings"` in
+    /// `{"ratings": {"$elemMatch": {"$op": 1}}}`, or the matched field for `$**`.
+    /// It is `None` for operators outside of any field condition, like `{"$op": 1}`.
+    ///
+    /// By default, the field name is ignored and [evaluate](CustomOperator::evaluate) is called.
+    fn evaluate_with_field(
+        &self,
+        evaluatee: Option<&Value>,
+        condition: &Value,
+        field_name: Option<&str>,
+    ) -> Result<bool, QueryError> {
+        let _ = field_name;
+        self.evaluate(evaluatee, condition)
+    }
+
+    /// Wraps this operator into one matching exactly the evaluatees it doesn't match.
+    ///
+    /// Errors are passed through rather than negated.
+    /// ```
+    /// use mongoquery::{BaseQuerier, CustomOperator, OperatorContainer, Querier, QueryError};
+    /// use serde_json::{json, Value};
+    ///
+    /// struct IsEmpty;
+    /// impl CustomOperator for IsEmpty {
+    ///     fn evaluate(&self, evaluatee: Option<&Value>, _condition: &Value) -> Result<bool, QueryError> {
+    ///         Ok(matches!(evaluatee, Some(Value::Array(arr)) if arr.is_empty()))
+    ///     }
+    /// }
+    ///
+    /// let mut ops = OperatorContainer::new();
+    /// ops.insert("nonEmpty", IsEmpty.negated());
+    ///
+    /// let querier = BaseQuerier::new(&json!({"tags": {"$nonEmpty": true}}));
+    /// assert!(querier.evaluate_with_custom_ops(Some(&json!({"tags": ["red"]})), ops.as_ref()).unwrap());
+    /// ```
+    fn negated(self) -> NegatedOperator<Self>
+    where
+        Self: Sized,
+    {
+        NegatedOperator(self)
+    }
 }
 
+/// A [CustomOperator] matching exactly the evaluatees the wrapped operator doesn't match.
+///
+/// See [CustomOperator::negated].
+#[derive(Debug, Clone)]
+pub struct NegatedOperator<Op>(Op);
+
+impl<Op> NegatedOperator<Op> {
+    /// Unwraps the negated operator.
+    pub fn into_inner(self) -> Op {
+        self.0
+    }
+}
+
+impl<Op: CustomOperator> CustomOperator for NegatedOperator<Op> {
+    fn evaluate(&self, evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        Ok(!self.0.evaluate(evaluatee, condition)?)
+    }
+
+    fn evaluate_with_field(
+        &self,
+        evaluatee: Option<&Value>,
+        condition: &Value,
+        field_name: Option<&str>,
+    ) -> Result<bool, QueryError> {
+        Ok(!self
+            .0
+            .evaluate_with_field(evaluatee, condition, field_name)?)
+    }
+}
+
+/// The result of a [DetailedOperator] evaluation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MatchOutcome {
+    /// Whether the evaluatee matches the operator's condition.
+    pub matched: bool,
+    /// Additional information about the match, e.g. captured values.
+    pub detail: Option<Value>,
+}
+
+/// A trait that represents custom operator reporting a [MatchOutcome] rather than a bare `bool`.
+///
+/// Detailed operators are only consulted by [Query::evaluate_detailed](crate::Query::evaluate_detailed),
+/// so the boolean evaluation path is unaffected by them.
+pub trait DetailedOperator {
+    /// Evaluate this operator on a specified evaluatee with the condition.
+    ///
+    /// See [CustomOperator::evaluate] for the meaning of the arguments and the error case.
+    fn evaluate(
+        &self,
+        evaluatee: Option<&Value>,
+        condition: &Value,
+    ) -> Result<MatchOutcome, QueryError>;
+}
+
+/// A catch-all handler for operators that aren't otherwise found, see [OperatorContainer::set_fallback].
+///
+/// It is passed the operator name (without the leading `$`), the evaluatee and the condition.
+pub type FallbackOperator = dyn Fn(&str, Option<&Value>, &Value) -> Result<bool, QueryError>;
+
 /// Helper struct used to construct operator-containing HashMap.
 ///
 /// Use [OperatorContainer::as_ref] to convert this object to a reference of HashMap.
 pub struct OperatorContainer {
     hashmap: HashMap<String, Box<dyn CustomOperator>>,
+    fallback: Option<Box<FallbackOperator>>,
 }
 
 impl OperatorContainer {
     pub fn new() -> Self {
         Self {
             hashmap: HashMap::new(),
+            fallback: None,
         }
     }
 
@@ -82,6 +294,33 @@ impl OperatorContainer {
         self.hashmap.insert(name.to_string(), Box::new(operator));
     }
 
+    /// Sets a handler for the operators that are neither in this container nor provided by the querier,
+    /// instead of failing with [QueryError::UnsupportedOperator] on them.
+    ///
+    /// The fallback is only consulted by [Query::evaluate_with_container](crate::Query::evaluate_with_container),
+    /// as the HashMap of [as_ref](Self::as_ref) doesn't carry it.
+    /// ```
+    /// use mongoquery::{BaseQuerier, OperatorContainer, Querier};
+    /// use serde_json::json;
+    ///
+    /// let mut ops = OperatorContainer::new();
+    /// ops.set_fallback(|operator, _evaluatee, _condition| Ok(operator.starts_with("x-")));
+    ///
+    /// let querier = BaseQuerier::new(&json!({"a": {"$x-future": 1}}));
+    /// assert!(querier.evaluate_with_container(Some(&json!({"a": 5})), &ops).unwrap());
+    /// ```
+    pub fn set_fallback<F>(&mut self, handler: F)
+    where
+        F: Fn(&str, Option<&Value>, &Value) -> Result<bool, QueryError> + 'static,
+    {
+        self.fallback = Some(Box::new(handler));
+    }
+
+    /// The handler set with [set_fallback](Self::set_fallback), if any.
+    pub fn fallback(&self) -> Option<&FallbackOperator> {
+        self.fallback.as_deref()
+    }
+
     pub fn to_hashmap(self) -> HashMap<String, Box<dyn CustomOperator>> {
         self.hashmap
     }
@@ -98,3 +337,38 @@ impl Default for OperatorContainer {
         Self::new()
     }
 }
+
+/// Defines a [CustomOperator] from a closure-like body and inserts it into an [OperatorContainer].
+///
+/// The macro generates a unit struct implementing [CustomOperator] whose `evaluate` runs the given body,
+/// so small operators don't need a hand-written struct and impl each.
+/// The body receives the evaluatee (`Option<&Value>`) and the condition (`&Value`),
+/// and must return `Result<bool, QueryError>`.
+/// ```
+/// use mongoquery::{custom_operator, BaseQuerier, OperatorContainer, Querier};
+/// use serde_json::{json, Value};
+///
+/// let mut ops = OperatorContainer::new();
+/// custom_operator!(ops, "is_even", |evaluatee, _condition| {
+///     Ok(matches!(evaluatee, Some(Value::Number(n)) if n.as_i64().unwrap_or(1) % 2 == 0))
+/// });
+///
+/// let querier = BaseQuerier::new(&json!({"a": {"$is_even": true}}));
+/// assert!(querier.evaluate_with_custom_ops(Some(&json!({"a": 4})), ops.as_ref()).unwrap());
+/// ```
+#[macro_export]
+macro_rules! custom_operator {
+    ($container:expr, $name:expr, |$evaluatee:ident, $condition:ident| $body:expr) => {{
+        struct GeneratedOperator;
+        impl $crate::CustomOperator for GeneratedOperator {
+            fn evaluate(
+                &self,
+                $evaluatee: Option<&::serde_json::Value>,
+                $condition: &::serde_json::Value,
+            ) -> Result<bool, $crate::QueryError> {
+                $body
+            }
+        }
+        $container.insert($name, GeneratedOperator);
+    }};
+}
@@ -1,47 +1,489 @@
-use crate::operator::{CustomOperator, StandardOperator};
-use crate::{OperatorProvider, QueryError};
+use crate::operator::{
+    ContextOperator, CustomOperator, DetailedOperator, EvaluationOptions, FallbackOperator,
+    MatchOutcome, OperatorContainer, OperatorContext, StandardOperator, SubQueryEvaluator,
+};
+#[cfg(feature = "std")]
+use crate::Interner;
+use crate::{compile_regex, HashMap, Index, OperatorProvider, Pattern, QueryError, SharedQuery};
+use alloc::borrow::Cow;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::cell::{Cell, RefCell};
+use core::convert::Infallible;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::str::{FromStr, Split};
+use regex::Regex;
+use serde::Serialize;
 use serde_json::{Map, Number, Value};
-use std::collections::HashMap;
-use std::convert::Infallible;
-use std::marker::PhantomData;
-use std::str::FromStr;
+#[cfg(feature = "timing")]
+use std::time::{Duration, Instant};
+use unicase::UniCase;
+
+/// A callback receiving an operator name and the time spent evaluating it.
+///
+/// See [QueryRef::evaluate_with_timing_hook].
+#[cfg(feature = "timing")]
+pub type TimingHook<'a> = dyn Fn(&str, Duration) + 'a;
+
+/// A [QueryRef] that owns all of its data.
+pub type Query<T> = QueryRef<'static, T>;
+
+/// Evaluates a sub-query of an operator, parsed for the provider of the evaluation.
+type SubQuery = fn(&Value, Option<&Value>, &Evaluation) -> Result<bool, QueryError>;
+
+/// Operators and state shared by a single evaluation of a [Query].
+struct Evaluation<'a> {
+    std_ops: &'a HashMap<String, StandardOperator>,
+    ctx_ops: &'a HashMap<String, ContextOperator>,
+    custom_ops: &'a HashMap<String, Box<dyn CustomOperator>>,
+    detailed_ops: Option<&'a HashMap<String, Box<dyn DetailedOperator>>>,
+    /// The handler of operators found nowhere else, if any.
+    fallback: Option<&'a FallbackOperator>,
+    details: RefCell<Vec<Value>>,
+    #[cfg(feature = "timing")]
+    timing_hook: Option<&'a TimingHook<'a>>,
+    /// The maximum number of operator invocations and how many are left, if the evaluation is budgeted.
+    budget: Option<(usize, Cell<usize>)>,
+    /// The fields of the root value selected so far, by field name.
+    field_cache: RefCell<HashMap<String, Option<Rc<Cow<'a, Value>>>>>,
+    root: Option<&'a Value>,
+    options: &'a EvaluationOptions,
+    sub_query: SubQuery,
+}
+
+impl SubQueryEvaluator for Evaluation<'_> {
+    fn evaluate_sub_query(&self, query: &Value, value: Option<&Value>) -> Result<bool, QueryError> {
+        (self.sub_query)(query, value, self)
+    }
+}
+
+impl<'a> Evaluation<'a> {
+    fn new<T: OperatorProvider>(
+        std_ops: &'a HashMap<String, StandardOperator>,
+        ctx_ops: &'a HashMap<String, ContextOperator>,
+        custom_ops: &'a HashMap<String, Box<dyn CustomOperator>>,
+        root: Option<&'a Value>,
+        options: &'a EvaluationOptions,
+    ) -> Self {
+        Evaluation {
+            std_ops,
+            ctx_ops,
+            custom_ops,
+            detailed_ops: None,
+            fallback: None,
+            details: RefCell::default(),
+            #[cfg(feature = "timing")]
+            timing_hook: None,
+            budget: None,
+            field_cache: RefCell::default(),
+            root,
+            options,
+            sub_query: |query, value, eval| {
+                QueryRef::<T>::from_value_borrowed(query).evaluate_with_ops(value, eval, None)
+            },
+        }
+    }
+
+    fn context(&self) -> OperatorContext<'_> {
+        OperatorContext {
+            root: self.root,
+            options: self.options,
+            evaluation: self,
+            regex: None,
+        }
+    }
+
+    /// Resolves the field of a field condition against the value, as [select] does.
+    ///
+    /// Fields of the root value are cached, so that conditions repeating a field name,
+    /// like `{"$or": [{"qty": 5}, {"qty": {"$gt": 20}}]}`, resolve it once. Other values,
+    /// like array elements or values produced by parallel descent, may not outlive the evaluation
+    /// of their condition, and are resolved every time.
+    fn select<'v>(
+        &self,
+        value: Option<&'v Value>,
+        field_name: &str,
+    ) -> Result<Selected<'v, 'a>, QueryError> {
+        match (value, self.root) {
+            (Some(value), Some(root)) if core::ptr::eq(value, root) => {
+                if let Some(cached) = self.field_cache.borrow().get(field_name) {
+                    return Ok(Selected::Cached(cached.clone()));
+                }
+                let selected = select(Some(root), field_name, self.options)?.map(Rc::new);
+                self.field_cache
+                    .borrow_mut()
+                    .insert(field_name.to_string(), selected.clone());
+                Ok(Selected::Cached(selected))
+            }
+            _ => Ok(Selected::Fresh(select(value, field_name, self.options)?)),
+        }
+    }
+
+    /// Looks up the operator by name and evaluates it on a value of `field`, if in a field condition.
+    ///
+    /// With the `log` feature, every evaluation is logged at the trace level, with the `mongoquery` target.
+    fn call_operator(
+        &self,
+        operator: &str,
+        value: Option<&Value>,
+        condition: &Value,
+        regex: Option<&Regex>,
+        field: Option<&str>,
+    ) -> Result<bool, QueryError> {
+        if let Some((max_steps, remaining)) = &self.budget {
+            let steps = remaining
+                .get()
+                .checked_sub(1)
+                .ok_or(QueryError::BudgetExceeded {
+                    max_steps: *max_steps,
+                })?;
+            remaining.set(steps);
+        }
+        let condition = &*if self.options.resolve_field_refs {
+            resolve_field_refs(condition, &self.context())?
+        } else {
+            Cow::Borrowed(condition)
+        };
+        let result = self.dispatch(operator, value, condition, regex, field);
+        #[cfg(feature = "log")]
+        log::trace!(
+            target: "mongoquery",
+            "${} {} on field {:?}: {:?}",
+            operator,
+            condition,
+            field,
+            result
+        );
+        result
+    }
+
+    /// Evaluates the operator found first among the custom, detailed, context and standard operators.
+    fn dispatch(
+        &self,
+        operator: &str,
+        value: Option<&Value>,
+        condition: &Value,
+        regex: Option<&Regex>,
+        field: Option<&str>,
+    ) -> Result<bool, QueryError> {
+        if let Some(custom_op) = self.custom_ops.get(operator) {
+            custom_op.evaluate_with_field(value, condition, field)
+        } else if let Some(detailed_op) = self.detailed_ops.and_then(|ops| ops.get(operator)) {
+            let outcome = detailed_op.evaluate(value, condition)?;
+            if let (true, Some(detail)) = (outcome.matched, outcome.detail) {
+                self.details.borrow_mut().push(detail);
+            }
+            Ok(outcome.matched)
+        } else if let Some(ctx_op) = self.ctx_ops.get(operator) {
+            ctx_op(
+                value,
+                condition,
+                &OperatorContext {
+                    regex,
+                    ..self.context()
+                },
+            )
+        } else if let Some(std_op) = self.std_ops.get(operator) {
+            std_op(value, condition)
+        } else if let Some(fallback) = self.fallback {
+            fallback(operator, value, condition)
+        } else {
+            Err(QueryError::UnsupportedOperator {
+                operator: operator.to_string(),
+            })
+        }
+    }
+}
+
+/// A field resolved by [Evaluation::select], borrowed from the evaluated value or shared with the cache.
+enum Selected<'v, 'a> {
+    Fresh(Option<Cow<'v, Value>>),
+    Cached(Option<Rc<Cow<'a, Value>>>),
+}
+
+impl Selected<'_, '_> {
+    fn as_deref(&self) -> Option<&Value> {
+        match self {
+            Selected::Fresh(field) => field.as_deref(),
+            Selected::Cached(field) => field.as_deref().map(|field| &**field),
+        }
+    }
+}
+
+/// The outcome of [QueryRef::evaluate_with_matches].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MatchReport {
+    /// Whether the query matched.
+    pub matched: bool,
+    /// Index of the first array element that satisfied the condition, keyed by field path.
+    ///
+    /// Only field paths that resolve to an array are present.
+    pub indices: HashMap<String, usize>,
+}
+
+/// The outcome of [QueryRef::first_failure]: a top-level condition the value doesn't satisfy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailureInfo {
+    /// The field the failing condition applies to, if it is a field condition.
+    pub field: Option<String>,
+    /// The failing condition, as it would appear in a query.
+    ///
+    /// For field conditions this is the value of the field in the query, e.g. `{"$gt": 5}`.
+    pub expected: Value,
+    /// The value the condition was evaluated against: the field for field conditions,
+    /// the whole value otherwise. `None` if missing.
+    pub actual: Option<Value>,
+}
+
+/// An entry of [QueryRef::diff]: a condition the value doesn't satisfy.
+///
+/// Its [Display](fmt::Display) reads like ``field `qty`: expected `$gt 30`, actual `25` ``.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// The dotted path of the field the failing condition applies to, if it is in a field condition.
+    pub field: Option<String>,
+    /// The failing condition, as it would appear in a query, e.g. `{"$gt": 30}` or `25`.
+    pub expected: Value,
+    /// The value the condition was evaluated against: the field for field conditions,
+    /// the whole value otherwise. `None` if missing.
+    pub actual: Option<Value>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(field) = &self.field {
+            write!(f, "field `{}`: ", field)?;
+        }
+        match &self.expected {
+            Value::Object(obj) if obj.len() == 1 && is_operator_document(&self.expected) => {
+                let (operator, condition) = obj.iter().next().expect("length is checked");
+                write!(f, "expected `{} {}`", operator, condition)?;
+            }
+            expected => write!(f, "expected `{}`", expected)?,
+        }
+        match &self.actual {
+            Some(actual) => write!(f, ", actual `{}`", actual),
+            None => write!(f, ", actual missing"),
+        }
+    }
+}
+
+/// The type of a JSON value, as declared for a field by a schema. See [QueryRef::check_schema].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JsonType {
+    Null,
+    Bool,
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+impl JsonType {
+    /// The type of the value.
+    pub fn of(value: &Value) -> JsonType {
+        match value {
+            Value::Null => JsonType::Null,
+            Value::Bool(_) => JsonType::Bool,
+            Value::Number(_) => JsonType::Number,
+            Value::String(_) => JsonType::String,
+            Value::Array(_) => JsonType::Array,
+            Value::Object(_) => JsonType::Object,
+        }
+    }
+}
+
+impl fmt::Display for JsonType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            JsonType::Null => "null",
+            JsonType::Bool => "bool",
+            JsonType::Number => "number",
+            JsonType::String => "string",
+            JsonType::Array => "array",
+            JsonType::Object => "object",
+        })
+    }
+}
+
+/// An entry of [QueryRef::check_schema]: a field compared to a value of another type than the schema's.
+///
+/// Its [Display](fmt::Display) reads like ``field `qty` is of type number, but `$gt` compares it to `"5"` ``.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaWarning {
+    /// The dotted path of the field.
+    pub field: String,
+    /// The operator comparing the field, e.g. `$gt`, or `None` for an equality like `{"qty": "5"}`.
+    pub operator: Option<String>,
+    /// The type of the field in the schema.
+    pub expected: JsonType,
+    /// The value the field is compared to.
+    pub value: Value,
+}
+
+impl fmt::Display for SchemaWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "field `{}` is of type {}, but ",
+            self.field, self.expected
+        )?;
+        match &self.operator {
+            Some(operator) => write!(f, "`{}` compares it to `{}`", operator, self.value),
+            None => write!(f, "is compared to `{}`", self.value),
+        }
+    }
+}
 
 /// An object that represents MongoDB query.
+///
+/// Queries built with [Querier::new](crate::Querier::new) own their data (see [Query]),
+/// while those built with [Querier::new_borrowed](crate::Querier::new_borrowed)
+/// borrow scalars, sequences and operator conditions from the query [Value] instead of cloning them.
+///
+/// Operators outside of any field condition are evaluated against the whole value, so that
+/// `{"$gt": 20}` matches the number `25` itself, just as `{"qty": {"$gt": 20}}` matches `{"qty": 25}`.
+///
+/// # Evaluation order
+/// Conditions are evaluated in order and evaluation stops as soon as the outcome is known:
+/// - the conditions of a query document, in the key order of the document
+///   (alphabetical, unless serde_json's `preserve_order` feature is enabled), stop at the first false one,
+/// - `$and` branches stop at the first false one,
+/// - `$or` branches stop at the first true one,
+/// - `$nor` branches stop at the first true one,
+/// - `$elemMatch` stops at the first matching element,
+/// - `$**` stops at the first matching field.
+///
+/// Operators after the deciding one are neither evaluated nor able to fail the evaluation.
+/// [AsyncQuery](crate::AsyncQuery) follows the same order.
+///
+/// # Arrays
+/// Arrays are searched the way MongoDB does, except that nested arrays are searched all the way down:
+/// - a scalar condition, like `{"a": 3}` or `{"a": {"$gt": 3}}`, matches an array with that scalar
+///   anywhere in it, so both `[3, 4]` and `[[1, 2], [3, 4]]` match,
+/// - an array condition, like `{"a": [3, 4]}` or `{"a": {"$eq": [3, 4]}}`, matches an equal array
+///   or an array with an equal element, so `[[1, 2], [3, 4]]` matches too, but `[[[3, 4]]]` doesn't,
+/// - numeric path segments index into arrays (`a.1` is `[3, 4]`), while other segments reach into
+///   the documents of an array, nested or not, yielding an array of the same shape:
+///   `a.b` on `{"a": [[{"b": 1}], [{"b": 2}]]}` is `[[1], [2]]`. Elements without the rest of the path
+///   are left out, so `a.b` on `{"a": [{"b": 1}, {"c": 2}]}` is `[1]`, and missing if no element has it.
+///
+/// Each operator of an operator document is evaluated on the whole array, and decides how to search it.
+/// Comparisons like `{"a": {"$gt": 8}}` match if any leaf of the array satisfies them, stopping at the
+/// first that does, so `[5, 8, 9]` matches but `[5, 8]` doesn't. The operators of one document may be
+/// satisfied by different elements: `{"$gt": 8, "$lt": 6}` matches `[5, 9]`. Use `$elemMatch` to
+/// require a single element to satisfy all of them.
+///
+/// [EvaluationOptions::strict_array] only affects implicit equality and paths: `{"a": 3}` and
+/// `{"a": [3, 4]}` then only match a field equal to them as a whole, and only numeric segments
+/// reach into arrays. Operators keep their own array semantics, so `{"a": {"$gt": 3}}`,
+/// `{"a": {"$eq": 3}}` and `{"a": {"$in": [3]}}` still match `[3, 4]`.
+///
+/// # Field-scoped logical operators
+/// `$and`, `$or` and `$nor` may also appear within a field condition, where each branch is a
+/// condition on the field: `{"status": {"$or": ["A", "D"]}}` matches a `status` equal to either,
+/// like `{"status": {"$in": ["A", "D"]}}`, and branches may be operator documents too.
+/// ```
+/// use mongoquery::{BaseQuerier, Querier};
+/// use serde_json::json;
+///
+/// let querier = BaseQuerier::new(&json!({"qty": {"$or": [5, {"$gt": 20}]}}));
+/// assert!(querier.evaluate(Some(&json!({"qty": 5}))).unwrap());
+/// assert!(querier.evaluate(Some(&json!({"qty": 25}))).unwrap());
+/// assert!(!querier.evaluate(Some(&json!({"qty": 10}))).unwrap());
+/// ```
+/// This is not how MongoDB behaves, which only accepts these operators at the top level of a query.
+///
+/// # Wildcard fields
+/// The `$**` field matches if the condition holds for any top-level field of the value:
+/// ```
+/// use mongoquery::{BaseQuerier, Querier};
+/// use serde_json::json;
+///
+/// let querier = BaseQuerier::new(&json!({"$**": {"$eq": "shipping"}}));
+/// assert!(querier.evaluate(Some(&json!({"from": "billing", "to": "shipping"}))).unwrap());
+/// // only top-level fields are considered
+/// assert!(!querier.evaluate(Some(&json!({"memo": {"by": "shipping"}}))).unwrap());
+/// ```
+/// Fields are not searched recursively, as the condition would otherwise also be evaluated against
+/// every intermediate document. Nest `$**` in `$elemMatch` to reach one level deeper into an array.
+/// Values that aren't documents, and empty documents, never match.
 #[derive(Debug)]
-pub enum Query<T>
+pub enum QueryRef<'a, T>
 where
     T: OperatorProvider,
 {
     NullScalar,
-    NumericScalar(Number),
+    NumericScalar(Cow<'a, Number>),
     BooleanScalar(bool),
-    StringScalar(String),
-    Sequence(Vec<Value>),
-    Compound(Vec<Condition<T>>),
+    StringScalar(Cow<'a, str>),
+    Sequence(Cow<'a, [Value]>),
+    Compound(Vec<Condition<'a, T>>),
     _Marker(Infallible, PhantomData<T>),
 }
 
 #[derive(Debug)]
-pub enum Condition<T>
+pub enum Condition<'a, T>
 where
     T: OperatorProvider,
 {
-    And(Vec<Query<T>>),
-    Or(Vec<Query<T>>),
-    Nor(Vec<Query<T>>),
+    And(Vec<QueryRef<'a, T>>),
+    Or(Vec<QueryRef<'a, T>>),
+    Nor(Vec<QueryRef<'a, T>>),
     Not {
-        op: Query<T>,
+        op: QueryRef<'a, T>,
+    },
+    /// Matches arrays with at least one element satisfying `op`
+    ///
+    /// All the operators of `op` must hold for the same element: `{"$elemMatch": {"$gte": 8, "$lt": 9}}`
+    /// doesn't match `[5, 9]`, while `{"$gte": 8, "$lt": 9}` does, with `9` and `5` satisfying one operator each.
+    ElemMatch {
+        op: QueryRef<'a, T>,
     },
     /// Condition evaluation on Field
     Field {
-        field_name: String,
-        op: Query<T>,
+        field_name: Arc<str>,
+        op: QueryRef<'a, T>,
     },
     /// Non-compound operators that start with $
     Operator {
         operator: String,
-        condition: Value,
+        condition: Cow<'a, Value>,
+        /// The regular expression of the condition, compiled once when the query is built,
+        /// for the operators that have one.
+        regex: Option<CompiledRegex>,
     },
+    /// Malformed operator that fails when evaluated
+    Invalid {
+        operator: String,
+        condition: Cow<'a, Value>,
+        reason: String,
+    },
+}
+
+/// A regular expression compiled as the query is built.
+///
+/// Queries hold it behind a trait object: the match cache inside [Regex] doesn't take part in comparing
+/// or hashing queries, and shouldn't make them look like mutable keys to `clippy::mutable_key_type`.
+pub type CompiledRegex = Arc<dyn CompiledPattern>;
+
+pub trait CompiledPattern: fmt::Debug + Send + Sync {
+    fn regex(&self) -> &Regex;
+}
+
+impl CompiledPattern for Regex {
+    fn regex(&self) -> &Regex {
+        self
+    }
 }
 
 impl<T> Query<T>
@@ -49,13 +491,489 @@ where
     T: OperatorProvider,
 {
     pub(crate) fn from_value(v: &Value) -> Query<T> {
+        QueryRef::from_value_borrowed(v).into_owned()
+    }
+
+    /// Compiles the query with standard operators given inline, in addition to those of the provider,
+    /// so that custom operators don't need an [OperatorProvider] of their own.
+    ///
+    /// Inline operators are named without the `$` and take precedence over the provider's operators
+    /// of the same name. The returned [SharedQuery] carries the operator table for its evaluations.
+    /// ```
+    /// use mongoquery::{BaseOperators, Query, QueryError};
+    /// use serde_json::{json, Value};
+    ///
+    /// fn square(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+    ///     let square = evaluatee.and_then(Value::as_f64).map(|n| n * n);
+    ///     Ok(square.is_some_and(|square| Some(square) == condition.as_f64()))
+    /// }
+    ///
+    /// let query = Query::<BaseOperators>::compile_with_ops(
+    ///     &json!({"qty": {"$square": 625}}),
+    ///     &[("square", square)],
+    /// );
+    /// assert!(query.evaluate(Some(&json!({"qty": 25}))).unwrap());
+    /// ```
+    pub fn compile_with_ops(query: &Value, ops: &[(&str, StandardOperator)]) -> SharedQuery<T> {
+        let mut std_ops = T::get_operators();
+        let mut ctx_ops = T::get_context_operators();
+        for &(name, op) in ops {
+            ctx_ops.remove(name);
+            std_ops.insert(name.to_string(), op);
+        }
+        SharedQuery::with_operators(Query::from_value(query), std_ops, ctx_ops)
+    }
+}
+
+impl<'a, T> QueryRef<'a, T>
+where
+    T: OperatorProvider,
+{
+    pub(crate) fn from_value_borrowed(v: &'a Value) -> QueryRef<'a, T> {
         match v {
-            Value::Null => Query::NullScalar,
-            Value::Bool(b) => Query::BooleanScalar(*b),
-            Value::Number(n) => Query::NumericScalar(n.clone()),
-            Value::String(s) => Query::StringScalar(s.clone()),
-            Value::Array(a) => Query::Sequence(a.clone()),
-            Value::Object(obj) => Query::Compound(Condition::from_map(obj)),
+            Value::Null => QueryRef::NullScalar,
+            Value::Bool(b) => QueryRef::BooleanScalar(*b),
+            Value::Number(n) => QueryRef::NumericScalar(Cow::Borrowed(n)),
+            Value::String(s) => QueryRef::StringScalar(Cow::Borrowed(s)),
+            Value::Array(a) => QueryRef::Sequence(Cow::Borrowed(a)),
+            Value::Object(obj) => QueryRef::Compound(Condition::from_map(obj)),
+        }
+    }
+
+    /// Converts this query into one that owns all of its data.
+    pub fn into_owned(self) -> Query<T> {
+        match self {
+            QueryRef::NullScalar => QueryRef::NullScalar,
+            QueryRef::BooleanScalar(b) => QueryRef::BooleanScalar(b),
+            QueryRef::NumericScalar(n) => QueryRef::NumericScalar(Cow::Owned(n.into_owned())),
+            QueryRef::StringScalar(s) => QueryRef::StringScalar(Cow::Owned(s.into_owned())),
+            QueryRef::Sequence(seq) => QueryRef::Sequence(Cow::Owned(seq.into_owned())),
+            QueryRef::Compound(compound) => {
+                QueryRef::Compound(compound.into_iter().map(Condition::into_owned).collect())
+            }
+            QueryRef::_Marker(..) => unreachable!("marker variant will never be constructed"),
+        }
+    }
+
+    /// Converts this query back to its JSON form.
+    ///
+    /// Parsing the result gives back an equal query. Conditions that can't share a document,
+    /// e.g. the same field twice after [simplify](QueryRef::simplify), are wrapped in an `$and`.
+    pub fn to_value(&self) -> Value {
+        match self {
+            QueryRef::NullScalar => Value::Null,
+            QueryRef::NumericScalar(n) => Value::Number(n.as_ref().clone()),
+            QueryRef::BooleanScalar(b) => Value::Bool(*b),
+            QueryRef::StringScalar(s) => Value::String(s.to_string()),
+            QueryRef::Sequence(seq) => Value::Array(seq.to_vec()),
+            QueryRef::Compound(compound) => {
+                let entries: Vec<_> = compound.iter().map(Condition::to_entry).collect();
+                let mut keys: Vec<_> = entries.iter().map(|(key, _)| key).collect();
+                keys.sort_unstable();
+                keys.dedup();
+                if keys.len() == entries.len() {
+                    Value::Object(entries.into_iter().collect())
+                } else {
+                    let branches = entries
+                        .into_iter()
+                        .map(|entry| Value::Object(Map::from_iter([entry])))
+                        .collect();
+                    Value::Object(Map::from_iter([(
+                        "$and".to_string(),
+                        Value::Array(branches),
+                    )]))
+                }
+            }
+            QueryRef::_Marker(..) => unreachable!("marker variant will never be constructed"),
+        }
+    }
+
+    /// Simplifies this query without changing which values it matches.
+    ///
+    /// `$and`s directly nested in an `$and` (and `$or`s directly nested in an `$or`) are flattened
+    /// into their parent, and duplicate sibling conditions are removed.
+    /// A single-branch `$and` or `$or` is replaced by its branch and a single-branch `$nor` by a `$not`.
+    /// A query that [is trivially false](QueryRef::is_trivially_false) becomes an empty `$or`,
+    /// unless any of its conditions could fail when evaluated: an unsupported operator, a malformed
+    /// condition or a comparison under [EvaluationOptions::strict_comparison] still fails the simplified query.
+    pub fn simplify(self) -> QueryRef<'a, T> {
+        match self {
+            QueryRef::Compound(compound) => {
+                let mut simplified = Vec::with_capacity(compound.len());
+                for cond in compound.into_iter().map(Condition::simplify) {
+                    match cond {
+                        Condition::And(mut operators) | Condition::Or(mut operators)
+                            if operators.len() == 1
+                                && matches!(operators[0], QueryRef::Compound(_)) =>
+                        {
+                            match operators.pop() {
+                                Some(QueryRef::Compound(branch)) => simplified.extend(branch),
+                                _ => unreachable!("the only branch is checked to be compound"),
+                            }
+                        }
+                        Condition::Nor(mut operators) if operators.len() == 1 => simplified.push(
+                            Condition::negation(operators.pop().expect("length is checked")),
+                        ),
+                        cond => simplified.push(cond),
+                    }
+                }
+                if simplified.iter().any(Condition::is_trivially_false)
+                    && simplified.iter().all(Condition::cannot_fail)
+                {
+                    return QueryRef::Compound(vec![Condition::Or(vec![])]);
+                }
+                QueryRef::Compound(dedup(simplified))
+            }
+            query => query,
+        }
+    }
+
+    /// Whether this query can't match any value, judging from its structure alone.
+    ///
+    /// The check is conservative: it catches an empty `$or` and fields with contradicting operators,
+    /// like `{"$exists": false}` alongside an operator requiring the field.
+    /// The standard meaning of those operators is assumed.
+    pub fn is_trivially_false(&self) -> bool {
+        match self {
+            QueryRef::Compound(compound) => compound.iter().any(Condition::is_trivially_false),
+            _ => false,
+        }
+    }
+
+    /// Whether evaluating this query can't fail, judging from its structure alone.
+    ///
+    /// Like [is_trivially_false](QueryRef::is_trivially_false), the standard meaning of the operators is
+    /// assumed, and only those that can't fail with the condition they're given are accepted.
+    fn cannot_fail(&self) -> bool {
+        match self {
+            QueryRef::Compound(compound) => compound.iter().all(Condition::cannot_fail),
+            _ => true,
+        }
+    }
+
+    /// Whether the operators of a field condition contradict each other.
+    fn has_contradicting_operators(&self) -> bool {
+        let (mut missing, mut present) = (false, false);
+        if let QueryRef::Compound(compound) = self {
+            for cond in compound {
+                if let Condition::Operator {
+                    operator,
+                    condition,
+                    ..
+                } = cond
+                {
+                    match (operator.as_str(), condition.as_ref()) {
+                        ("exists", Value::Bool(exists)) => {
+                            missing |= !exists;
+                            present |= exists;
+                        }
+                        ("eq", Value::Null) => {}
+                        (
+                            "size" | "setEq" | "eq" | "type" | "gt" | "gte" | "lt" | "lte" | "all",
+                            _,
+                        ) => present = true,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        missing && present
+    }
+
+    /// Returns the logical complement of this query, matching exactly the values this query doesn't.
+    ///
+    /// Negating an already negated query unwraps it instead of nesting another negation.
+    /// The negation is a `$not` of an operator document, and a single-branch `$nor` otherwise,
+    /// so that [to_value](QueryRef::to_value) round-trips it.
+    pub fn negate(self) -> QueryRef<'a, T> {
+        match self {
+            QueryRef::Compound(mut compound)
+                if compound.len() == 1
+                    && (matches!(&compound[0], Condition::Not { .. })
+                        || matches!(&compound[0], Condition::Nor(operators) if operators.len() == 1)) =>
+            {
+                match compound.pop() {
+                    Some(Condition::Not { op }) => op,
+                    Some(Condition::Nor(mut operators)) => {
+                        operators.pop().expect("length is checked")
+                    }
+                    _ => unreachable!("the only condition is checked to be a negation"),
+                }
+            }
+            query => QueryRef::Compound(vec![Condition::negation(query)]),
+        }
+    }
+
+    /// Lists the operators used in this query that are neither in `provider_ops`
+    /// nor among the context operators of `T`, in order of first appearance.
+    ///
+    /// Operator names are reported without the leading `$`.
+    /// Use this to reject queries that would fail with [QueryError::UnsupportedOperator] up front.
+    pub fn unsupported_operators(
+        &self,
+        provider_ops: &HashMap<String, StandardOperator>,
+    ) -> Vec<String> {
+        let ctx_ops = T::get_context_operators();
+        let mut unsupported = vec![];
+        self.visit_operators(&mut |operator, _| {
+            if !provider_ops.contains_key(operator)
+                && !ctx_ops.contains_key(operator)
+                && !unsupported.iter().any(|op| op == operator)
+            {
+                unsupported.push(operator.to_string());
+            }
+        });
+        unsupported
+    }
+
+    /// Collects every literal this query compares against, in order of appearance:
+    /// the values of field equalities and the conditions of operators.
+    ///
+    /// Literals are returned as owned values since scalars aren't stored as [Value]s in the query.
+    pub fn literal_values(&self) -> Vec<Value> {
+        let mut literals = vec![];
+        self.collect_literals(&mut literals);
+        literals
+    }
+
+    fn collect_literals(&self, literals: &mut Vec<Value>) {
+        match self {
+            QueryRef::NullScalar => literals.push(Value::Null),
+            QueryRef::NumericScalar(n) => literals.push(Value::Number(n.as_ref().clone())),
+            QueryRef::BooleanScalar(b) => literals.push(Value::Bool(*b)),
+            QueryRef::StringScalar(s) => literals.push(Value::String(s.to_string())),
+            QueryRef::Sequence(seq) => literals.push(Value::Array(seq.to_vec())),
+            QueryRef::Compound(compound) => {
+                for cond in compound {
+                    match cond {
+                        Condition::And(operators)
+                        | Condition::Or(operators)
+                        | Condition::Nor(operators) => {
+                            for op in operators {
+                                op.collect_literals(literals);
+                            }
+                        }
+                        Condition::Not { op }
+                        | Condition::ElemMatch { op }
+                        | Condition::Field { op, .. } => op.collect_literals(literals),
+                        Condition::Operator { condition, .. } => {
+                            literals.push(condition.as_ref().clone())
+                        }
+                        Condition::Invalid { .. } => {}
+                    }
+                }
+            }
+            QueryRef::_Marker(..) => unreachable!("marker variant will never be constructed"),
+        }
+    }
+
+    /// Checks that the regular expressions of the `$regex`, `$deepKeyMatch` and `$regexCapture` operators
+    /// in this query compile, so that evaluating it can't fail with [QueryError::CompileError].
+    ///
+    /// Malformed conditions that aren't strings are left for evaluation to report.
+    pub fn validate(&self) -> Result<(), QueryError> {
+        let mut result = Ok(());
+        self.visit_operators(&mut |operator, condition| {
+            let pattern = match operator {
+                "regex" | "deepKeyMatch" => Some(condition),
+                "regexCapture" => condition.get("pattern"),
+                _ => None,
+            };
+            if let (true, Some(pattern)) = (result.is_ok(), pattern.filter(|p| p.is_string())) {
+                result = Pattern::from_condition(operator, pattern, None).map(drop);
+            }
+        });
+        result
+    }
+
+    /// Checks the field conditions of this query against a schema mapping dotted field paths to their type,
+    /// returning a warning for every field compared to a value of another type, as those comparisons can't
+    /// behave as intended: `{"qty": {"$gt": "5"}}` never matches a numeric `qty`.
+    ///
+    /// Equalities, `$eq`, `$ne`, the `$gt` family and the elements of `$in` and `$nin` are checked,
+    /// including within `$and`, `$or`, `$nor` and `$not`. Comparisons to `null`, which also matches
+    /// missing fields, are always fine. Fields missing from the schema, fields of type
+    /// [JsonType::Array], whose elements may be of any type, and conditions within `$elemMatch` aren't checked.
+    /// ```
+    /// use mongoquery::{BaseQuerier, JsonType, Querier};
+    /// use serde_json::json;
+    /// use std::collections::HashMap;
+    ///
+    /// let schema = HashMap::from([("qty".to_string(), JsonType::Number)]);
+    /// let warnings = BaseQuerier::new(&json!({"qty": {"$gt": "5"}})).check_schema(&schema);
+    /// assert_eq!(
+    ///     "field `qty` is of type number, but `$gt` compares it to `\"5\"`",
+    ///     warnings[0].to_string()
+    /// );
+    /// ```
+    pub fn check_schema(&self, schema: &HashMap<String, JsonType>) -> Vec<SchemaWarning> {
+        let mut warnings = Vec::new();
+        self.check_schema_at(None, schema, &mut warnings);
+        warnings
+    }
+
+    fn check_schema_at(
+        &self,
+        field: Option<&str>,
+        schema: &HashMap<String, JsonType>,
+        warnings: &mut Vec<SchemaWarning>,
+    ) {
+        let expected = field
+            .and_then(|field| Some((field, *schema.get(field)?)))
+            .filter(|&(_, expected)| expected != JsonType::Array);
+        let check = |operator: Option<&str>, value: &Value, warnings: &mut Vec<SchemaWarning>| {
+            if let Some((field, expected)) = expected {
+                if !value.is_null() && JsonType::of(value) != expected {
+                    warnings.push(SchemaWarning {
+                        field: field.to_string(),
+                        operator: operator.map(|operator| format!("${}", operator)),
+                        expected,
+                        value: value.clone(),
+                    });
+                }
+            }
+        };
+        match self {
+            QueryRef::NumericScalar(_) | QueryRef::BooleanScalar(_) | QueryRef::StringScalar(_) => {
+                check(None, &self.to_value(), warnings)
+            }
+            QueryRef::Compound(compound) => {
+                for cond in compound {
+                    match cond {
+                        Condition::And(operators)
+                        | Condition::Or(operators)
+                        | Condition::Nor(operators) => {
+                            for op in operators {
+                                op.check_schema_at(field, schema, warnings);
+                            }
+                        }
+                        Condition::Not { op } => op.check_schema_at(field, schema, warnings),
+                        Condition::Field { field_name, op }
+                            if &**field_name != WILDCARD_FIELD && !is_json_path(field_name) =>
+                        {
+                            let path = match field {
+                                Some(field) => format!("{}.{}", field, field_name),
+                                None => field_name.to_string(),
+                            };
+                            op.check_schema_at(Some(&path), schema, warnings);
+                        }
+                        Condition::Operator {
+                            operator,
+                            condition,
+                            ..
+                        } => match (operator.as_str(), condition.as_ref()) {
+                            ("eq" | "ne" | "gt" | "gte" | "lt" | "lte", condition) => {
+                                check(Some(operator), condition, warnings)
+                            }
+                            ("in" | "nin", Value::Array(values)) => {
+                                for value in values {
+                                    check(Some(operator), value, warnings);
+                                }
+                            }
+                            _ => {}
+                        },
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Replaces the field paths of this query with their interned copies.
+    #[cfg(feature = "std")]
+    pub(crate) fn intern_fields(&mut self, interner: &Interner) {
+        if let QueryRef::Compound(compound) = self {
+            for cond in compound {
+                match cond {
+                    Condition::And(operators)
+                    | Condition::Or(operators)
+                    | Condition::Nor(operators) => {
+                        for op in operators {
+                            op.intern_fields(interner);
+                        }
+                    }
+                    Condition::Not { op } | Condition::ElemMatch { op } => {
+                        op.intern_fields(interner)
+                    }
+                    Condition::Field { field_name, op } => {
+                        *field_name = interner.intern(field_name);
+                        op.intern_fields(interner);
+                    }
+                    Condition::Operator { .. } | Condition::Invalid { .. } => {}
+                }
+            }
+        }
+    }
+
+    /// Calls `visit` with the name and condition of every operator in this query.
+    fn visit_operators(&self, visit: &mut impl FnMut(&str, &Value)) {
+        if let QueryRef::Compound(compound) = self {
+            for cond in compound {
+                match cond {
+                    Condition::And(operators)
+                    | Condition::Or(operators)
+                    | Condition::Nor(operators) => {
+                        for op in operators {
+                            op.visit_operators(visit);
+                        }
+                    }
+                    Condition::Not { op }
+                    | Condition::ElemMatch { op }
+                    | Condition::Field { op, .. } => op.visit_operators(visit),
+                    Condition::Operator {
+                        operator,
+                        condition,
+                        ..
+                    } => visit(operator, condition),
+                    Condition::Invalid { .. } => {}
+                }
+            }
+        }
+    }
+
+    /// Scores how expensive this query may be to evaluate, e.g. to reject untrusted queries over a threshold.
+    ///
+    /// Every comparison scores one point plus its nesting depth in logical operators,
+    /// `$elemMatch` and `$not`. Array conditions (as in `$in`) add their length,
+    /// and regular expressions weigh ten times as much as other operators.
+    /// Scores are only meaningful relative to each other.
+    pub fn complexity(&self) -> usize {
+        self.complexity_at(0)
+    }
+
+    fn complexity_at(&self, depth: usize) -> usize {
+        const REGEX_WEIGHT: usize = 10;
+        match self {
+            QueryRef::Sequence(seq) => 1 + depth + seq.len(),
+            QueryRef::Compound(compound) => compound
+                .iter()
+                .map(|cond| match cond {
+                    Condition::And(operators)
+                    | Condition::Or(operators)
+                    | Condition::Nor(operators) => {
+                        1 + operators
+                            .iter()
+                            .map(|op| op.complexity_at(depth + 1))
+                            .sum::<usize>()
+                    }
+                    Condition::Not { op } | Condition::ElemMatch { op } => {
+                        1 + op.complexity_at(depth + 1)
+                    }
+                    Condition::Field { op, .. } => op.complexity_at(depth),
+                    Condition::Operator {
+                        operator,
+                        condition,
+                        ..
+                    } => {
+                        let weight = if operator == "regex" { REGEX_WEIGHT } else { 1 };
+                        let size = condition.as_array().map_or(0, Vec::len);
+                        weight * (1 + depth) + size
+                    }
+                    Condition::Invalid { .. } => 1,
+                })
+                .sum(),
+            _ => 1 + depth,
         }
     }
 
@@ -63,83 +981,772 @@ where
     pub fn evaluate(&self, value: Option<&Value>) -> Result<bool, QueryError> {
         self.evaluate_with_custom_ops(value, &HashMap::new())
     }
+    /// Evaluate this query on any serializable value, e.g. a `#[derive(Serialize)]` struct.
+    ///
+    /// The value is serialized to JSON first; failing that, [QueryError::SerializationError] is returned.
+    pub fn evaluate_serializable<S: Serialize + ?Sized>(
+        &self,
+        value: &S,
+    ) -> Result<bool, QueryError> {
+        self.evaluate(Some(&serde_json::to_value(value)?))
+    }
+    /// Evaluate this query on a document given as a bare map, as if it were a [Value::Object].
+    ///
+    /// Queries made of field conditions only look fields up in the map, without wrapping it into a [Value].
+    /// Other queries, and those using context operators that need the whole document, like `$fieldEq`,
+    /// are evaluated on a copy of the map.
+    pub fn evaluate_map(&self, map: &Map<String, Value>) -> Result<bool, QueryError> {
+        let (std_ops, ctx_ops) = (T::get_operators(), T::get_context_operators());
+        let fields_only = match self {
+            QueryRef::Compound(compound) => compound.iter().all(|cond| {
+                matches!(cond, Condition::Field { field_name, .. }
+                    if &**field_name != WILDCARD_FIELD && !is_json_path(field_name))
+            }),
+            _ => false,
+        };
+        let mut uses_context = false;
+        self.visit_operators(&mut |operator, _| uses_context |= ctx_ops.contains_key(operator));
+        if !fields_only || uses_context {
+            return self.evaluate(Some(&Value::Object(map.clone())));
+        }
+
+        let custom_ops = HashMap::new();
+        let options = EvaluationOptions::default();
+        let eval = Evaluation::new::<T>(&std_ops, &ctx_ops, &custom_ops, None, &options);
+        if let QueryRef::Compound(compound) = self {
+            for cond in compound {
+                if let Condition::Field { field_name, op } = cond {
+                    let mut path = field_name.split('.');
+                    let field = path.next().and_then(|key| map.get(key));
+                    let field = extract(field, path, &options);
+                    if !op.evaluate_with_ops(field.as_deref(), &eval, Some(field_name))? {
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+    /// Evaluate this query on a YAML document, e.g. a configuration file.
+    ///
+    /// The document is converted to JSON first: tags are dropped, and scalar mapping keys become strings,
+    /// so `{1: a}` is queried as `{"1": "a"}`. Other keys fail with [QueryError::SerializationError].
+    #[cfg(feature = "yaml")]
+    pub fn evaluate_yaml(&self, value: &serde_yaml::Value) -> Result<bool, QueryError> {
+        self.evaluate(Some(&crate::formats::yaml_to_json(value)?))
+    }
+    /// Evaluate this query on a TOML document, e.g. a configuration file.
+    ///
+    /// The document is converted to JSON first, with datetimes becoming RFC 3339 strings,
+    /// so they can be compared with string conditions like `{"$gt": "2020-01-01"}`.
+    #[cfg(feature = "toml")]
+    pub fn evaluate_toml(&self, value: &toml::Value) -> Result<bool, QueryError> {
+        self.evaluate(Some(&crate::formats::toml_to_json(value)))
+    }
     pub fn evaluate_with_custom_ops(
         &self,
         value: Option<&Value>,
         custom_ops: &HashMap<String, Box<dyn CustomOperator>>,
     ) -> Result<bool, QueryError> {
-        self.evaluate_with_ops(value, &T::get_operators(), custom_ops)
+        let (std_ops, ctx_ops) = (T::get_operators(), T::get_context_operators());
+        let options = EvaluationOptions::default();
+        let eval = Evaluation::new::<T>(&std_ops, &ctx_ops, custom_ops, value, &options);
+        self.evaluate_with_ops(value, &eval, None)
     }
-    fn evaluate_with_ops(
+
+    /// Evaluate this query on the specified value with the operators of the container,
+    /// including its [fallback](OperatorContainer::set_fallback) for unknown operators.
+    pub fn evaluate_with_container(
+        &self,
+        value: Option<&Value>,
+        ops: &OperatorContainer,
+    ) -> Result<bool, QueryError> {
+        let (std_ops, ctx_ops) = (T::get_operators(), T::get_context_operators());
+        let options = EvaluationOptions::default();
+        let eval = Evaluation {
+            fallback: ops.fallback(),
+            ..Evaluation::new::<T>(&std_ops, &ctx_ops, ops.as_ref(), value, &options)
+        };
+        self.evaluate_with_ops(value, &eval, None)
+    }
+
+    /// Evaluate this query on the specified value with non-default [EvaluationOptions].
+    pub fn evaluate_with_options(
+        &self,
+        value: Option<&Value>,
+        options: &EvaluationOptions,
+    ) -> Result<bool, QueryError> {
+        let (std_ops, ctx_ops) = (T::get_operators(), T::get_context_operators());
+        self.evaluate_with_operators(value, &std_ops, &ctx_ops, options)
+    }
+
+    /// Evaluates this query with operators already retrieved from the provider.
+    pub(crate) fn evaluate_with_operators(
         &self,
         value: Option<&Value>,
         std_ops: &HashMap<String, StandardOperator>,
-        custom_ops: &HashMap<String, Box<dyn CustomOperator>>,
+        ctx_ops: &HashMap<String, ContextOperator>,
+        options: &EvaluationOptions,
     ) -> Result<bool, QueryError> {
-        Ok(match self {
-            Query::NullScalar => {
-                if let Some(Value::Null) = value {
-                    true
-                } else if let Some(Value::Array(v)) = value {
-                    v.contains(&Value::Null)
-                } else {
-                    false
+        let custom_ops = HashMap::new();
+        let eval = Evaluation::new::<T>(std_ops, ctx_ops, &custom_ops, value, options);
+        self.evaluate_with_ops(value, &eval, None)
+    }
+
+    /// Evaluates a sub-query of a synchronous context operator of an async query against `value`,
+    /// as part of the evaluation of `root`, with the synchronous operators of the provider.
+    #[cfg(feature = "std")]
+    pub(crate) fn evaluate_sub_query(
+        query: &Value,
+        value: Option<&Value>,
+        root: Option<&Value>,
+        std_ops: &HashMap<String, StandardOperator>,
+        ctx_ops: &HashMap<String, ContextOperator>,
+        options: &EvaluationOptions,
+    ) -> Result<bool, QueryError> {
+        let custom_ops = HashMap::new();
+        let eval = Evaluation::new::<T>(std_ops, ctx_ops, &custom_ops, root, options);
+        QueryRef::<T>::from_value_borrowed(query).evaluate_with_ops(value, &eval, None)
+    }
+
+    /// Evaluate this query on the specified value, invoking at most `max_steps` operators.
+    ///
+    /// Every operator invocation counts as a step, including each one per array element in `$elemMatch`,
+    /// so this bounds the cost of untrusted queries against large documents.
+    /// Fails with [QueryError::BudgetExceeded] as soon as an operator would exceed the budget.
+    /// Field equalities like `{"a": 1}` aren't operators and don't count.
+    pub fn evaluate_budgeted(
+        &self,
+        value: Option<&Value>,
+        max_steps: usize,
+    ) -> Result<bool, QueryError> {
+        let (std_ops, ctx_ops) = (T::get_operators(), T::get_context_operators());
+        let custom_ops = HashMap::new();
+        let options = EvaluationOptions::default();
+        let eval = Evaluation {
+            budget: Some((max_steps, Cell::new(max_steps))),
+            ..Evaluation::new::<T>(&std_ops, &ctx_ops, &custom_ops, value, &options)
+        };
+        self.evaluate_with_ops(value, &eval, None)
+    }
+
+    /// Evaluate this query on the listed fields of the value only, e.g. when only part of a document is loaded.
+    ///
+    /// Projected fields are dotted paths, each keeping the field at that path along with the documents
+    /// leading to it; arrays are kept whole. The query may only reference fields within the projection,
+    /// so `["size"]` allows `{"size.uom": "cm"}` but `["size.uom"]` doesn't allow `{"size": {"$exists": true}}`.
+    /// Referencing any other field, or `$**`, fails with [QueryError::OperatorError] before evaluating.
+    ///
+    /// Operators outside of any field condition, like `$expr`, are evaluated on the projected document.
+    /// ```
+    /// use mongoquery::{BaseQuerier, Querier, QueryError};
+    /// use serde_json::json;
+    ///
+    /// let document = json!({"item": "xyz", "qty": 25, "size": {"h": 14, "uom": "cm"}});
+    /// let querier = BaseQuerier::new(&json!({"qty": {"$gt": 20}, "size.uom": "cm"}));
+    /// assert!(querier.evaluate_projected(Some(&document), &["qty", "size"]).unwrap());
+    /// assert!(matches!(
+    ///     querier.evaluate_projected(Some(&document), &["qty"]),
+    ///     Err(QueryError::OperatorError { .. })
+    /// ));
+    /// ```
+    pub fn evaluate_projected(
+        &self,
+        value: Option<&Value>,
+        projection: &[&str],
+    ) -> Result<bool, QueryError> {
+        let mut paths = vec![];
+        self.collect_paths(None, &mut paths);
+        let covered = |path: &str| {
+            projection.iter().any(|projected| {
+                path.strip_prefix(projected)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+            })
+        };
+        if let Some(path) = paths.iter().find(|path| !covered(path)) {
+            return Err(QueryError::OperatorError {
+                operator: "projection".to_string(),
+                reason: format!("field `{}` is not in the projection", path),
+            });
+        }
+        match value {
+            Some(Value::Object(obj)) => {
+                let mut projected = Map::new();
+                for path in projection {
+                    project(&mut projected, obj, path.split('.'));
                 }
+                self.evaluate(Some(&Value::Object(projected)))
             }
-            Query::NumericScalar(n) => {
-                if let Some(Value::Number(input)) = value {
-                    input == n
-                } else if let Some(Value::Array(v)) = value {
-                    v.contains(&Value::Number(n.clone()))
-                } else {
-                    false
+            value => self.evaluate(value),
+        }
+    }
+
+    /// Collects the dotted paths of the fields this query evaluates conditions on,
+    /// `prefix` being the path of the field condition this query is nested in, if any.
+    fn collect_paths(&self, prefix: Option<&str>, paths: &mut Vec<String>) {
+        match self {
+            QueryRef::Compound(compound) => {
+                for cond in compound {
+                    match cond {
+                        Condition::And(queries)
+                        | Condition::Or(queries)
+                        | Condition::Nor(queries) => {
+                            for query in queries {
+                                query.collect_paths(prefix, paths);
+                            }
+                        }
+                        Condition::Not { op } => op.collect_paths(prefix, paths),
+                        Condition::Field { field_name, op } => {
+                            let path = match prefix {
+                                Some(prefix) => format!("{}.{}", prefix, field_name),
+                                None => field_name.to_string(),
+                            };
+                            op.collect_paths(Some(&path), paths);
+                        }
+                        Condition::ElemMatch { .. }
+                        | Condition::Operator { .. }
+                        | Condition::Invalid { .. } => paths.extend(prefix.map(str::to_string)),
+                    }
                 }
             }
-            Query::BooleanScalar(b) => {
-                if let Some(Value::Bool(input)) = value {
-                    input == b
-                } else if let Some(Value::Array(v)) = value {
-                    v.contains(&Value::Bool(*b))
-                } else {
-                    false
+            _ => paths.extend(prefix.map(str::to_string)),
+        }
+    }
+
+    /// Counts the values matching this query, without collecting them.
+    ///
+    /// Operators are looked up once for all the values, rather than once per value as with [evaluate](QueryRef::evaluate).
+    pub fn count_matching<'v, I>(&self, values: I) -> Result<usize, QueryError>
+    where
+        I: IntoIterator<Item = &'v Value>,
+    {
+        let (std_ops, ctx_ops) = (T::get_operators(), T::get_context_operators());
+        let custom_ops = HashMap::new();
+        let options = EvaluationOptions::default();
+        let mut count = 0;
+        for value in values {
+            let eval = Evaluation::new::<T>(&std_ops, &ctx_ops, &custom_ops, Some(value), &options);
+            if self.evaluate_with_ops(Some(value), &eval, None)? {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Evaluate this query on each of the documents, returning whether each matches, in order.
+    ///
+    /// Unlike a list of matching indices, the result lines up with the documents, e.g. to be combined
+    /// with other per-document masks. Operators are looked up once for all the documents,
+    /// and the first failing evaluation fails the whole call.
+    pub fn evaluate_bitmap(&self, docs: &[Value]) -> Result<Vec<bool>, QueryError> {
+        let (std_ops, ctx_ops) = (T::get_operators(), T::get_context_operators());
+        let custom_ops = HashMap::new();
+        let options = EvaluationOptions::default();
+        docs.iter()
+            .map(|doc| {
+                let eval =
+                    Evaluation::new::<T>(&std_ops, &ctx_ops, &custom_ops, Some(doc), &options);
+                self.evaluate_with_ops(Some(doc), &eval, None)
+            })
+            .collect()
+    }
+
+    /// Evaluate this query on each of the documents, returning the result of each, in order.
+    ///
+    /// Like [evaluate_bitmap](QueryRef::evaluate_bitmap), except that a failing evaluation only fails
+    /// the result of its own document, so that one bad document doesn't fail the whole batch.
+    pub fn evaluate_all_lenient(&self, docs: &[Value]) -> Vec<Result<bool, QueryError>> {
+        let (std_ops, ctx_ops) = (T::get_operators(), T::get_context_operators());
+        let custom_ops = HashMap::new();
+        let options = EvaluationOptions::default();
+        docs.iter()
+            .map(|doc| {
+                let eval =
+                    Evaluation::new::<T>(&std_ops, &ctx_ops, &custom_ops, Some(doc), &options);
+                self.evaluate_with_ops(Some(doc), &eval, None)
+            })
+            .collect()
+    }
+
+    /// Evaluate this query on the specified value, reporting how long each operator took.
+    ///
+    /// `hook` is called after every operator evaluation with the operator name (without the leading `$`)
+    /// and the time spent evaluating it, e.g. to aggregate which operators dominate a slow query.
+    #[cfg(feature = "timing")]
+    pub fn evaluate_with_timing_hook(
+        &self,
+        value: Option<&Value>,
+        custom_ops: &HashMap<String, Box<dyn CustomOperator>>,
+        hook: &TimingHook<'_>,
+    ) -> Result<bool, QueryError> {
+        let (std_ops, ctx_ops) = (T::get_operators(), T::get_context_operators());
+        let options = EvaluationOptions::default();
+        let eval = Evaluation {
+            timing_hook: Some(hook),
+            ..Evaluation::new::<T>(&std_ops, &ctx_ops, custom_ops, value, &options)
+        };
+        self.evaluate_with_ops(value, &eval, None)
+    }
+
+    /// Evaluate this query on the specified value with [DetailedOperator]s.
+    ///
+    /// Detailed operators take precedence over standard operators of the same name.
+    /// The returned [MatchOutcome] holds the details reported by every detailed operator that matched,
+    /// in evaluation order, as a JSON array (`None` if there were none).
+    pub fn evaluate_detailed(
+        &self,
+        value: Option<&Value>,
+        detailed_ops: &HashMap<String, Box<dyn DetailedOperator>>,
+    ) -> Result<MatchOutcome, QueryError> {
+        let (std_ops, ctx_ops) = (T::get_operators(), T::get_context_operators());
+        let custom_ops = HashMap::new();
+        let options = EvaluationOptions::default();
+        let eval = Evaluation {
+            detailed_ops: Some(detailed_ops),
+            ..Evaluation::new::<T>(&std_ops, &ctx_ops, &custom_ops, value, &options)
+        };
+        let matched = self.evaluate_with_ops(value, &eval, None)?;
+        let details = eval.details.into_inner();
+        Ok(MatchOutcome {
+            matched,
+            detail: (!details.is_empty()).then_some(Value::Array(details)),
+        })
+    }
+
+    /// Returns the documents of `index` matching this query, in their original order.
+    ///
+    /// When this query has a top-level `{"<indexed field>": <string, number or boolean>}` condition,
+    /// only the documents holding that value are evaluated; otherwise every document is.
+    /// Either way, the result is the same as evaluating the query on each document.
+    pub fn evaluate_with_index<'d>(&self, index: &Index<'d>) -> Result<Vec<&'d Value>, QueryError> {
+        let key = match self {
+            QueryRef::Compound(compound) => compound.iter().find_map(|cond| match cond {
+                Condition::Field { field_name, op } if &**field_name == index.field() => match op {
+                    QueryRef::NumericScalar(n) => Some(Value::Number(n.as_ref().clone())),
+                    QueryRef::BooleanScalar(b) => Some(Value::Bool(*b)),
+                    QueryRef::StringScalar(s) => Some(Value::String(s.to_string())),
+                    _ => None,
+                },
+                _ => None,
+            }),
+            _ => None,
+        };
+        let candidates: Vec<&'d Value> = match key {
+            Some(key) => index.candidates(&key).collect(),
+            None => index.documents().to_vec(),
+        };
+        let mut matching = Vec::with_capacity(candidates.len());
+        for document in candidates {
+            if self.evaluate(Some(document))? {
+                matching.push(document);
+            }
+        }
+        Ok(matching)
+    }
+
+    /// Evaluate this query on the specified value, returning the first top-level condition it fails.
+    ///
+    /// Returns `Ok(None)` when the value matches, so the query can serve as a validation spec
+    /// whose failures point at the offending field.
+    pub fn first_failure(&self, value: Option<&Value>) -> Result<Option<FailureInfo>, QueryError> {
+        let (std_ops, ctx_ops) = (T::get_operators(), T::get_context_operators());
+        let custom_ops = HashMap::new();
+        let options = EvaluationOptions::default();
+        let eval = Evaluation::new::<T>(&std_ops, &ctx_ops, &custom_ops, value, &options);
+        let compound = match self {
+            QueryRef::Compound(compound) => compound,
+            query => {
+                return Ok(match query.evaluate_with_ops(value, &eval, None)? {
+                    true => None,
+                    false => Some(FailureInfo {
+                        field: None,
+                        expected: query.to_value(),
+                        actual: value.cloned(),
+                    }),
+                })
+            }
+        };
+        for cond in compound {
+            if cond.evaluate(value, &eval, None)? {
+                continue;
+            }
+            return Ok(Some(match cond {
+                Condition::Field { field_name, op } => FailureInfo {
+                    field: Some(field_name.to_string()),
+                    expected: op.to_value(),
+                    actual: select(value, field_name, &options)?.map(Cow::into_owned),
+                },
+                cond => {
+                    let (key, condition) = cond.to_entry();
+                    let mut expected = Map::new();
+                    expected.insert(key, condition);
+                    FailureInfo {
+                        field: None,
+                        expected: Value::Object(expected),
+                        actual: value.cloned(),
+                    }
+                }
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Evaluate this query on the specified value, listing every condition it fails.
+    ///
+    /// Unlike [first_failure](QueryRef::first_failure), evaluation doesn't stop at the first failure,
+    /// and field conditions are broken down into the individual operators and nested fields
+    /// (including the ones in `$and`) that fail, with the field values they were evaluated against.
+    /// Other conditions, like `$or` or `$elemMatch`, are reported as a whole.
+    /// The list is empty when the value matches.
+    pub fn diff(&self, value: Option<&Value>) -> Result<Vec<Mismatch>, QueryError> {
+        let (std_ops, ctx_ops) = (T::get_operators(), T::get_context_operators());
+        let custom_ops = HashMap::new();
+        let options = EvaluationOptions::default();
+        let eval = Evaluation::new::<T>(&std_ops, &ctx_ops, &custom_ops, value, &options);
+        let mut mismatches = vec![];
+        self.collect_mismatches(None, None, value, &eval, &mut mismatches)?;
+        Ok(mismatches)
+    }
+
+    /// `path` is the dotted path of the innermost field condition being evaluated, if any,
+    /// and `field` its name.
+    fn collect_mismatches(
+        &self,
+        path: Option<&str>,
+        field: Option<&str>,
+        value: Option<&Value>,
+        eval: &Evaluation,
+        mismatches: &mut Vec<Mismatch>,
+    ) -> Result<(), QueryError> {
+        match self {
+            QueryRef::Compound(compound) => {
+                for cond in compound {
+                    cond.collect_mismatches(path, field, value, eval, mismatches)?;
                 }
             }
-            Query::StringScalar(s) => {
-                if let Some(Value::String(input)) = value {
-                    input == s
-                } else if let Some(Value::Array(v)) = value {
-                    v.contains(&Value::String(s.clone()))
-                } else {
-                    false
+            query => {
+                if !query.evaluate_with_ops(value, eval, field)? {
+                    mismatches.push(Mismatch {
+                        field: path.map(str::to_string),
+                        expected: query.to_value(),
+                        actual: value.cloned(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluate this query on the specified value, recording which array elements matched.
+    ///
+    /// For each top-level field condition (including the ones nested in `$and`) whose path resolves to an array,
+    /// the returned [MatchReport] holds the index of the first element satisfying that condition.
+    /// There is none when the condition is an array equal to the whole array, like `{"ratings": [5, 8, 9]}`.
+    /// Indices are only collected when the query matches.
+    pub fn evaluate_with_matches(&self, value: Option<&Value>) -> Result<MatchReport, QueryError> {
+        let (std_ops, ctx_ops) = (T::get_operators(), T::get_context_operators());
+        let custom_ops = HashMap::new();
+        let options = EvaluationOptions::default();
+        let eval = Evaluation::new::<T>(&std_ops, &ctx_ops, &custom_ops, value, &options);
+        let mut report = MatchReport {
+            matched: self.evaluate_with_ops(value, &eval, None)?,
+            ..Default::default()
+        };
+        if report.matched {
+            self.collect_indices(value, &eval, &mut report.indices)?;
+        }
+        Ok(report)
+    }
+
+    fn collect_indices(
+        &self,
+        value: Option<&Value>,
+        eval: &Evaluation,
+        indices: &mut HashMap<String, usize>,
+    ) -> Result<(), QueryError> {
+        if let QueryRef::Compound(compound) = self {
+            for cond in compound {
+                match cond {
+                    Condition::And(operators) => {
+                        for op in operators {
+                            op.collect_indices(value, eval, indices)?;
+                        }
+                    }
+                    Condition::Field { field_name, op } => {
+                        let field = eval.select(value, field_name)?;
+                        if let Some(Value::Array(arr)) = field.as_deref() {
+                            for (i, e) in arr.iter().enumerate() {
+                                // an array condition matches the whole array, which no element
+                                // accounts for, or an element equal to it
+                                let matched = match op {
+                                    QueryRef::Sequence(seq) if seq == arr => break,
+                                    QueryRef::Sequence(seq) => {
+                                        matches!(e, Value::Array(e) if seq == e)
+                                    }
+                                    op => op.evaluate_with_ops(Some(e), eval, Some(field_name))?,
+                                };
+                                if matched {
+                                    indices.insert(field_name.to_string(), i);
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
                 }
             }
-            Query::Sequence(seq) => {
+        }
+        Ok(())
+    }
+
+    /// Whether this query is a scalar other than `null` equal to the value.
+    fn equals_scalar(&self, value: &Value) -> bool {
+        match self {
+            QueryRef::NumericScalar(n) => matches!(value, Value::Number(v) if v == n.as_ref()),
+            QueryRef::BooleanScalar(b) => value.as_bool() == Some(*b),
+            QueryRef::StringScalar(s) => value.as_str() == Some(s),
+            _ => false,
+        }
+    }
+
+    /// `field` is the name of the innermost field condition being evaluated, if any.
+    fn evaluate_with_ops(
+        &self,
+        value: Option<&Value>,
+        eval: &Evaluation,
+        field: Option<&str>,
+    ) -> Result<bool, QueryError> {
+        Ok(match self {
+            // scalars match the value itself or, for arrays, any of its leaves
+            QueryRef::NullScalar => {
+                // `{"field": null}` matches both an explicit null and a missing field.
+                value.is_none_or(|v| scalar_match(v, eval.options, &mut Value::is_null))
+            }
+            QueryRef::NumericScalar(_) | QueryRef::BooleanScalar(_) | QueryRef::StringScalar(_) => {
+                value.is_some_and(|v| scalar_match(v, eval.options, &mut |e| self.equals_scalar(e)))
+            }
+            QueryRef::Sequence(seq) => {
                 if let Some(Value::Array(v)) = value {
+                    // the whole array or, like scalars, one of its elements
                     seq == v
+                        || (!eval.options.strict_array
+                            && v.iter().any(|e| matches!(e, Value::Array(e) if seq == e)))
                 } else if let Some(v) = value {
                     seq.contains(v)
                 } else {
                     false
                 }
             }
-            Query::Compound(compound) => {
+            QueryRef::Compound(compound) => {
                 for cond in compound {
-                    if cond.evaluate(value, std_ops, custom_ops)? == false {
+                    if !cond.evaluate(value, eval, field)? {
                         return Ok(false);
                     }
                 }
                 return Ok(true);
             }
-            Query::_Marker(..) => unreachable!("marker variant will never be constructed"),
+            QueryRef::_Marker(..) => unreachable!("marker variant will never be constructed"),
         })
     }
 }
 
-impl<T> Condition<T>
+impl<'a, T> Condition<'a, T>
 where
     T: OperatorProvider,
 {
-    fn from_map(map: &Map<String, Value>) -> Vec<Condition<T>> {
+    fn into_owned(self) -> Condition<'static, T> {
+        let into_owned = |operators: Vec<QueryRef<'a, T>>| {
+            operators.into_iter().map(QueryRef::into_owned).collect()
+        };
+        match self {
+            Condition::And(operators) => Condition::And(into_owned(operators)),
+            Condition::Or(operators) => Condition::Or(into_owned(operators)),
+            Condition::Nor(operators) => Condition::Nor(into_owned(operators)),
+            Condition::Not { op } => Condition::Not {
+                op: op.into_owned(),
+            },
+            Condition::ElemMatch { op } => Condition::ElemMatch {
+                op: op.into_owned(),
+            },
+            Condition::Field { field_name, op } => Condition::Field {
+                field_name,
+                op: op.into_owned(),
+            },
+            Condition::Operator {
+                operator,
+                condition,
+                regex,
+            } => Condition::Operator {
+                operator,
+                condition: Cow::Owned(condition.into_owned()),
+                regex,
+            },
+            Condition::Invalid {
+                operator,
+                condition,
+                reason,
+            } => Condition::Invalid {
+                operator,
+                condition: Cow::Owned(condition.into_owned()),
+                reason,
+            },
+        }
+    }
+
+    /// `path` is the dotted path of the innermost field condition being evaluated, if any,
+    /// and `field` its name.
+    fn collect_mismatches(
+        &self,
+        path: Option<&str>,
+        field: Option<&str>,
+        value: Option<&Value>,
+        eval: &Evaluation,
+        mismatches: &mut Vec<Mismatch>,
+    ) -> Result<(), QueryError> {
+        match self {
+            Condition::And(operators) => {
+                for op in operators {
+                    op.collect_mismatches(path, field, value, eval, mismatches)?;
+                }
+            }
+            Condition::Field { field_name, op } if &**field_name != WILDCARD_FIELD => {
+                let path = match path {
+                    Some(path) => format!("{}.{}", path, field_name),
+                    None => field_name.to_string(),
+                };
+                let selected = eval.select(value, field_name)?;
+                op.collect_mismatches(
+                    Some(&path),
+                    Some(field_name),
+                    selected.as_deref(),
+                    eval,
+                    mismatches,
+                )?;
+            }
+            cond => {
+                if !cond.evaluate(value, eval, field)? {
+                    let (key, condition) = cond.to_entry();
+                    let mut expected = Map::new();
+                    expected.insert(key, condition);
+                    mismatches.push(Mismatch {
+                        field: path.map(str::to_string),
+                        expected: Value::Object(expected),
+                        actual: value.cloned(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The negation of `op` in the form parsing its JSON form gives back: a `$not` of an operator
+    /// document, or a single-branch `$nor` of anything else.
+    fn negation(op: QueryRef<'a, T>) -> Condition<'a, T> {
+        if is_operator_document(&op.to_value()) {
+            Condition::Not { op }
+        } else {
+            Condition::Nor(vec![op])
+        }
+    }
+
+    /// The key and value of this condition in its query document.
+    fn to_entry(&self) -> (String, Value) {
+        let branches = |operators: &[QueryRef<'a, T>]| {
+            Value::Array(operators.iter().map(QueryRef::to_value).collect())
+        };
+        match self {
+            Condition::And(operators) => ("$and".to_string(), branches(operators)),
+            Condition::Or(operators) => ("$or".to_string(), branches(operators)),
+            Condition::Nor(operators) => ("$nor".to_string(), branches(operators)),
+            // a negated query that isn't an operator document can only be written as a `$nor`
+            Condition::Not { op } => match op.to_value() {
+                op if is_operator_document(&op) => ("$not".to_string(), op),
+                op => ("$nor".to_string(), Value::Array(vec![op])),
+            },
+            Condition::ElemMatch { op } => ("$elemMatch".to_string(), op.to_value()),
+            Condition::Field { field_name, op } => (field_name.to_string(), op.to_value()),
+            Condition::Operator {
+                operator,
+                condition,
+                ..
+            }
+            | Condition::Invalid {
+                operator,
+                condition,
+                ..
+            } => (format!("${}", operator), condition.as_ref().clone()),
+        }
+    }
+
+    fn is_trivially_false(&self) -> bool {
+        match self {
+            Condition::And(operators) => operators.iter().any(QueryRef::is_trivially_false),
+            Condition::Or(operators) => operators.iter().all(QueryRef::is_trivially_false),
+            Condition::ElemMatch { op } => op.is_trivially_false(),
+            Condition::Field { op, .. } => {
+                op.is_trivially_false() || op.has_contradicting_operators()
+            }
+            _ => false,
+        }
+    }
+
+    fn cannot_fail(&self) -> bool {
+        let is_type = |type_spec: &Value| match type_spec {
+            Value::String(alias) => [
+                "double", "string", "object", "array", "bool", "null", "int", "long", "number",
+            ]
+            .contains(&alias.as_str()),
+            type_spec => type_spec.is_i64(),
+        };
+        match self {
+            Condition::And(operators) | Condition::Or(operators) | Condition::Nor(operators) => {
+                operators.iter().all(QueryRef::cannot_fail)
+            }
+            Condition::Not { op } | Condition::ElemMatch { op } | Condition::Field { op, .. } => {
+                op.cannot_fail()
+            }
+            Condition::Operator {
+                operator,
+                condition,
+                ..
+            } => match (operator.as_str(), condition.as_ref()) {
+                ("eq" | "ne", _) => true,
+                ("exists", condition) => condition.is_boolean(),
+                ("in" | "nin" | "setEq", condition) => condition.is_array(),
+                ("size", condition) => condition.is_u64(),
+                ("type", Value::Array(types)) => types.iter().all(is_type),
+                ("type", type_spec) => is_type(type_spec),
+                ("all", Value::Array(members)) => members
+                    .iter()
+                    .all(|member| !matches!(member, Value::Object(obj) if obj.contains_key("$elemMatch"))),
+                _ => false,
+            },
+            Condition::Invalid { .. } => false,
+        }
+    }
+
+    fn simplify(self) -> Condition<'a, T> {
+        match self {
+            Condition::And(operators) => {
+                Condition::And(flatten(operators, |c| matches!(c, Condition::And(_))))
+            }
+            Condition::Or(operators) => {
+                Condition::Or(flatten(operators, |c| matches!(c, Condition::Or(_))))
+            }
+            Condition::Nor(operators) => Condition::Nor(dedup(
+                operators.into_iter().map(QueryRef::simplify).collect(),
+            )),
+            Condition::Not { op } => Condition::Not { op: op.simplify() },
+            Condition::ElemMatch { op } => Condition::ElemMatch { op: op.simplify() },
+            Condition::Field { field_name, op } => Condition::Field {
+                field_name,
+                op: op.simplify(),
+            },
+            cond => cond,
+        }
+    }
+
+    fn from_map(map: &'a Map<String, Value>) -> Vec<Condition<'a, T>> {
         let mut v = Vec::with_capacity(map.len());
         for (operator, condition) in map.iter() {
             match operator.as_str() {
@@ -152,19 +1759,46 @@ where
                 "$nor" => {
                     v.push(Condition::Nor(compound_condition_from_value(condition)));
                 }
-                "$not" => v.push(Condition::Not {
-                    op: Query::from_value(condition),
+                "$elemMatch" => v.push(if condition.is_object() {
+                    Condition::ElemMatch {
+                        op: QueryRef::from_value_borrowed(condition),
+                    }
+                } else {
+                    Condition::Invalid {
+                        operator: "elemMatch".to_string(),
+                        condition: Cow::Borrowed(condition),
+                        reason: "argument must be a document".to_string(),
+                    }
+                }),
+                "$not" => v.push(if is_operator_document(condition) {
+                    Condition::Not {
+                        op: QueryRef::from_value_borrowed(condition),
+                    }
+                } else {
+                    Condition::Invalid {
+                        operator: "not".to_string(),
+                        condition: Cow::Borrowed(condition),
+                        reason: "argument must be an operator document".to_string(),
+                    }
                 }),
+                // meta keys, see OperatorProvider::ignored_keys
+                op if op
+                    .strip_prefix('$')
+                    .is_some_and(|key| T::ignored_keys().contains(&key)) => {}
                 op => {
-                    if let Some(stripped) = op.strip_prefix("$") {
+                    if let Some(stripped) = op
+                        .strip_prefix("$")
+                        .filter(|_| !is_json_path(op) && op != WILDCARD_FIELD)
+                    {
                         v.push(Condition::Operator {
                             operator: stripped.to_string(),
-                            condition: condition.clone(),
+                            condition: Cow::Borrowed(condition),
+                            regex: compile_regex(stripped, condition),
                         })
                     } else {
                         v.push(Condition::Field {
-                            field_name: op.to_string(),
-                            op: Query::from_value(condition),
+                            field_name: Arc::from(op),
+                            op: QueryRef::from_value_borrowed(condition),
                         })
                     }
                 }
@@ -175,13 +1809,13 @@ where
     fn evaluate(
         &self,
         value: Option<&Value>,
-        std_ops: &HashMap<String, StandardOperator>,
-        custom_ops: &HashMap<String, Box<dyn CustomOperator>>,
+        eval: &Evaluation,
+        field: Option<&str>,
     ) -> Result<bool, QueryError> {
         Ok(match self {
             Condition::And(operators) => {
                 for op in operators {
-                    if op.evaluate_with_ops(value, std_ops, custom_ops)? == false {
+                    if !op.evaluate_with_ops(value, eval, field)? {
                         return Ok(false);
                     }
                 }
@@ -189,7 +1823,7 @@ where
             }
             Condition::Or(operators) => {
                 for op in operators {
-                    if op.evaluate_with_ops(value, std_ops, custom_ops)? == true {
+                    if op.evaluate_with_ops(value, eval, field)? {
                         return Ok(true);
                     }
                 }
@@ -197,70 +1831,688 @@ where
             }
             Condition::Nor(operators) => {
                 for op in operators {
-                    if op.evaluate_with_ops(value, std_ops, custom_ops)? == true {
+                    if op.evaluate_with_ops(value, eval, field)? {
                         return Ok(false);
                     }
                 }
                 return Ok(true);
             }
-            Condition::Not { op } => !op.evaluate_with_ops(value, std_ops, custom_ops)?,
+            Condition::Not { op } => !op.evaluate_with_ops(value, eval, field)?,
+            Condition::ElemMatch { op } => {
+                if let Some(Value::Array(arr)) = value {
+                    for e in arr {
+                        if op.evaluate_with_ops(Some(e), eval, field)? {
+                            return Ok(true);
+                        }
+                    }
+                }
+                return Ok(false);
+            }
+            Condition::Field { field_name, op } if &**field_name == WILDCARD_FIELD => {
+                if let Some(Value::Object(obj)) = value {
+                    for (name, field) in obj {
+                        if op.evaluate_with_ops(Some(field), eval, Some(name))? {
+                            return Ok(true);
+                        }
+                    }
+                }
+                return Ok(false);
+            }
+            Condition::Field { field_name, op }
+                if matches!(
+                    op,
+                    QueryRef::NumericScalar(_)
+                        | QueryRef::BooleanScalar(_)
+                        | QueryRef::StringScalar(_)
+                ) && !is_json_path(field_name)
+                    && !eval.options.strict_array
+                    && !eval.options.error_on_path_through_scalar =>
+            {
+                // such scalars match any leaf of the field, which can be walked through without
+                // building the arrays of parallel descent
+                any_extracted_leaf(value, field_name.split('.'), eval.options, &mut |leaf| {
+                    op.equals_scalar(leaf)
+                })
+            }
             Condition::Field { field_name, op } => {
-                let field = extract(value, &field_name.split('.').collect::<Vec<_>>());
-                op.evaluate_with_ops(field.as_ref(), std_ops, custom_ops)?
+                let selected = eval.select(value, field_name)?;
+                op.evaluate_with_ops(selected.as_deref(), eval, Some(field_name))?
             }
             Condition::Operator {
                 operator,
                 condition,
+                regex,
             } => {
-                if let Some(custom_op) = custom_ops.get(operator) {
-                    custom_op.evaluate(value, condition)?
-                } else if let Some(std_op) = std_ops.get(operator) {
-                    std_op(value, condition)?
-                } else {
-                    return Err(QueryError::UnsupportedOperator {
-                        operator: operator.clone(),
-                    });
+                #[cfg(feature = "timing")]
+                let start = eval.timing_hook.map(|_| Instant::now());
+                let matched = eval.call_operator(
+                    operator,
+                    value,
+                    condition,
+                    regex.as_deref().map(CompiledPattern::regex),
+                    field,
+                )?;
+                #[cfg(feature = "timing")]
+                if let (Some(hook), Some(start)) = (eval.timing_hook, start) {
+                    hook(operator, start.elapsed());
                 }
+                matched
+            }
+            Condition::Invalid {
+                operator, reason, ..
+            } => {
+                return Err(QueryError::OperatorError {
+                    operator: operator.clone(),
+                    reason: reason.clone(),
+                });
             }
         })
     }
 }
 
-// TODO: maybe apply Cow?
-pub(crate) fn extract(entry: Option<&Value>, path: &[&str]) -> Option<Value> {
-    if path.is_empty() {
-        return entry.cloned();
+impl<'a, T> PartialEq for QueryRef<'a, T>
+where
+    T: OperatorProvider,
+{
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (QueryRef::NullScalar, QueryRef::NullScalar) => true,
+            (QueryRef::NumericScalar(lhs), QueryRef::NumericScalar(rhs)) => lhs == rhs,
+            (QueryRef::BooleanScalar(lhs), QueryRef::BooleanScalar(rhs)) => lhs == rhs,
+            (QueryRef::StringScalar(lhs), QueryRef::StringScalar(rhs)) => lhs == rhs,
+            (QueryRef::Sequence(lhs), QueryRef::Sequence(rhs)) => lhs == rhs,
+            (QueryRef::Compound(lhs), QueryRef::Compound(rhs)) => lhs == rhs,
+            _ => false,
+        }
     }
-    if let Some(value) = entry {
-        match value {
-            Value::Null => Some(Value::Null),
-            Value::Array(arr) => {
-                if let Ok(v) = i64::from_str(path[0]) {
-                    // index-based indexing
-                    extract(arr.get(v as usize), &path[1..])
+}
+
+impl<'a, T> PartialEq for Condition<'a, T>
+where
+    T: OperatorProvider,
+{
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Condition::And(lhs), Condition::And(rhs)) => lhs == rhs,
+            (Condition::Or(lhs), Condition::Or(rhs)) => lhs == rhs,
+            (Condition::Nor(lhs), Condition::Nor(rhs)) => lhs == rhs,
+            (Condition::Not { op: lhs }, Condition::Not { op: rhs }) => lhs == rhs,
+            (Condition::ElemMatch { op: lhs }, Condition::ElemMatch { op: rhs }) => lhs == rhs,
+            (
+                Condition::Field {
+                    field_name: lhs_name,
+                    op: lhs,
+                },
+                Condition::Field {
+                    field_name: rhs_name,
+                    op: rhs,
+                },
+            ) => lhs_name == rhs_name && lhs == rhs,
+            (
+                Condition::Operator {
+                    operator: lhs_operator,
+                    condition: lhs,
+                    ..
+                },
+                Condition::Operator {
+                    operator: rhs_operator,
+                    condition: rhs,
+                    ..
+                },
+            ) => lhs_operator == rhs_operator && lhs == rhs,
+            (
+                Condition::Invalid {
+                    operator: lhs_operator,
+                    condition: lhs_condition,
+                    reason: lhs,
+                },
+                Condition::Invalid {
+                    operator: rhs_operator,
+                    condition: rhs_condition,
+                    reason: rhs,
+                },
+            ) => lhs_operator == rhs_operator && lhs_condition == rhs_condition && lhs == rhs,
+            _ => false,
+        }
+    }
+}
+
+impl<'a, T> Eq for QueryRef<'a, T> where T: OperatorProvider {}
+
+impl<'a, T> Eq for Condition<'a, T> where T: OperatorProvider {}
+
+/// Hashes the JSON form of the query, so that queries parsed from equal documents hash equally
+/// and can key a cache of compiled queries.
+impl<'a, T> Hash for QueryRef<'a, T>
+where
+    T: OperatorProvider,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_value(&self.to_value(), state);
+    }
+}
+
+impl<'a, T> Hash for Condition<'a, T>
+where
+    T: OperatorProvider,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let (key, value) = self.to_entry();
+        key.hash(state);
+        hash_value(&value, state);
+    }
+}
+
+/// Hashes a [Value] canonically: object keys are hashed in sorted order, so the result doesn't
+/// depend on whether serde_json preserves insertion order.
+fn hash_value<H: Hasher>(value: &Value, state: &mut H) {
+    core::mem::discriminant(value).hash(state);
+    match value {
+        Value::Null => {}
+        Value::Bool(b) => b.hash(state),
+        // equal numbers have the same representation, and so the same text
+        Value::Number(n) => n.to_string().hash(state),
+        Value::String(s) => s.hash(state),
+        Value::Array(arr) => {
+            arr.len().hash(state);
+            arr.iter().for_each(|e| hash_value(e, state));
+        }
+        Value::Object(obj) => {
+            let mut entries: Vec<_> = obj.iter().collect();
+            entries.sort_unstable_by_key(|(key, _)| *key);
+            entries.len().hash(state);
+            for (key, value) in entries {
+                key.hash(state);
+                hash_value(value, state);
+            }
+        }
+    }
+}
+
+// implemented by hand since deriving would require `T: Clone`, while `T` is only a marker
+impl<'a, T> Clone for QueryRef<'a, T>
+where
+    T: OperatorProvider,
+{
+    fn clone(&self) -> Self {
+        match self {
+            QueryRef::NullScalar => QueryRef::NullScalar,
+            QueryRef::NumericScalar(n) => QueryRef::NumericScalar(n.clone()),
+            QueryRef::BooleanScalar(b) => QueryRef::BooleanScalar(*b),
+            QueryRef::StringScalar(s) => QueryRef::StringScalar(s.clone()),
+            QueryRef::Sequence(seq) => QueryRef::Sequence(seq.clone()),
+            QueryRef::Compound(compound) => QueryRef::Compound(compound.clone()),
+            QueryRef::_Marker(never, _) => match *never {},
+        }
+    }
+}
+
+impl<'a, T> Clone for Condition<'a, T>
+where
+    T: OperatorProvider,
+{
+    fn clone(&self) -> Self {
+        match self {
+            Condition::And(operators) => Condition::And(operators.clone()),
+            Condition::Or(operators) => Condition::Or(operators.clone()),
+            Condition::Nor(operators) => Condition::Nor(operators.clone()),
+            Condition::Not { op } => Condition::Not { op: op.clone() },
+            Condition::ElemMatch { op } => Condition::ElemMatch { op: op.clone() },
+            Condition::Field { field_name, op } => Condition::Field {
+                field_name: field_name.clone(),
+                op: op.clone(),
+            },
+            Condition::Operator {
+                operator,
+                condition,
+                regex,
+            } => Condition::Operator {
+                operator: operator.clone(),
+                condition: condition.clone(),
+                regex: regex.clone(),
+            },
+            Condition::Invalid {
+                operator,
+                condition,
+                reason,
+            } => Condition::Invalid {
+                operator: operator.clone(),
+                condition: condition.clone(),
+                reason: reason.clone(),
+            },
+        }
+    }
+}
+
+/// Simplifies the branches of a logical condition, splicing in the branches of nested conditions of the same kind.
+fn flatten<'a, T>(
+    operators: Vec<QueryRef<'a, T>>,
+    same_kind: fn(&Condition<'a, T>) -> bool,
+) -> Vec<QueryRef<'a, T>>
+where
+    T: OperatorProvider,
+{
+    let mut flattened = Vec::with_capacity(operators.len());
+    for op in operators.into_iter().map(QueryRef::simplify) {
+        match op {
+            QueryRef::Compound(mut compound) if compound.len() == 1 && same_kind(&compound[0]) => {
+                match compound.pop() {
+                    Some(Condition::And(inner) | Condition::Or(inner)) => flattened.extend(inner),
+                    _ => unreachable!("only logical conditions are flattened"),
+                }
+            }
+            op => flattened.push(op),
+        }
+    }
+    dedup(flattened)
+}
+
+/// Removes duplicates while keeping the first occurrence of each item in place.
+fn dedup<Q: PartialEq>(items: Vec<Q>) -> Vec<Q> {
+    let mut unique: Vec<Q> = Vec::with_capacity(items.len());
+    for item in items {
+        if !unique.contains(&item) {
+            unique.push(item);
+        }
+    }
+    unique
+}
+
+/// The field name matching any top-level field of the value.
+pub(crate) const WILDCARD_FIELD: &str = "$**";
+
+/// Whether the field name is a JSONPath expression (`$.a[*].b`) rather than a dotted path.
+///
+/// Always `false` unless the `jsonpath` feature is enabled.
+pub(crate) fn is_json_path(field_name: &str) -> bool {
+    cfg!(feature = "jsonpath") && (field_name.starts_with("$.") || field_name.starts_with("$["))
+}
+
+/// Resolves the field of a field condition against the value.
+///
+/// Field names are dotted paths (see [extract]) or, with the `jsonpath` feature, JSONPath expressions.
+/// A JSONPath expression selecting no node is a missing field, one selecting a single node is
+/// that node, and one selecting several nodes is an array of them.
+pub(crate) fn select<'v>(
+    value: Option<&'v Value>,
+    field_name: &str,
+    options: &EvaluationOptions,
+) -> Result<Option<Cow<'v, Value>>, QueryError> {
+    #[cfg(feature = "jsonpath")]
+    if is_json_path(field_name) {
+        let path = serde_json_path::JsonPath::parse(field_name).map_err(|e| {
+            QueryError::OperatorError {
+                operator: "jsonpath".to_string(),
+                reason: e.to_string(),
+            }
+        })?;
+        let nodes = value.map(|v| path.query(v).all()).unwrap_or_default();
+        return Ok(match nodes.as_slice() {
+            [] => None,
+            [node] => Some(Cow::Borrowed(*node)),
+            nodes => Some(Cow::Owned(Value::Array(
+                nodes.iter().map(|&n| n.clone()).collect(),
+            ))),
+        });
+    }
+    let field = extract(value, field_name.split('.'), options);
+    // scalars on the path make it missing, or are skipped as elements of an array
+    if options.error_on_path_through_scalar {
+        if let Some(depth) = value.and_then(|v| scalar_depth(v, field_name.split('.'), options)) {
+            let mut segments = field_name.split('.');
+            let scalar = segments.by_ref().take(depth).collect::<Vec<_>>().join(".");
+            let segment = segments.next().unwrap_or_default();
+            return Err(QueryError::OperatorError {
+                operator: "path".to_string(),
+                reason: if scalar.is_empty() {
+                    format!("the value is a scalar and has no field `{}`", segment)
                 } else {
-                    // key-based nested document parallel indexing
-                    let mut v = Vec::with_capacity(arr.len());
-                    for e in arr.iter() {
-                        v.push(extract(Some(e), path)?);
-                    }
-                    Some(Value::Array(v))
+                    format!("`{}` is a scalar and has no field `{}`", scalar, segment)
+                },
+            });
+        }
+    }
+    Ok(field)
+}
+
+/// The number of leading segments of a dotted path that resolve to a scalar other than `null`
+/// with segments left to descend into, following the traversal of [extract].
+fn scalar_depth(
+    entry: &Value,
+    mut path: Split<'_, char>,
+    options: &EvaluationOptions,
+) -> Option<usize> {
+    let whole = path.clone();
+    let segment = path.next()?;
+    match entry {
+        Value::Null => None,
+        Value::Array(arr) => {
+            if let Ok(v) = i64::from_str(segment) {
+                let depth = scalar_depth(array_index(arr, v)?, path, options)?;
+                Some(depth + 1)
+            } else if options.strict_array {
+                None
+            } else {
+                arr.iter()
+                    .find_map(|e| scalar_depth(e, whole.clone(), options))
+            }
+        }
+        Value::Object(obj) => {
+            let depth = scalar_depth(object_field(obj, segment, options)?, path, options)?;
+            Some(depth + 1)
+        }
+        _ => Some(0),
+    }
+}
+
+/// Copies the field at a dotted path, split into its segments, from `source` into `target`,
+/// along with the documents leading to it. Arrays are copied whole.
+fn project(
+    target: &mut Map<String, Value>,
+    source: &Map<String, Value>,
+    mut path: Split<'_, char>,
+) {
+    let Some(segment) = path.next() else {
+        return;
+    };
+    match source.get(segment) {
+        Some(Value::Object(obj)) if path.clone().next().is_some() => {
+            if let Value::Object(nested) = target
+                .entry(segment)
+                .or_insert_with(|| Value::Object(Map::new()))
+            {
+                project(nested, obj, path);
+            }
+        }
+        Some(field) => {
+            target.insert(segment.to_string(), field.clone());
+        }
+        None => {}
+    }
+}
+
+/// Resolves a dotted path, split into its segments, against the entry.
+///
+/// Numeric segments index into arrays. Any other segment reaching an array is applied to each
+/// of its elements in parallel, yielding one array level per array traversed:
+/// `a.b.c` on `{"a": [{"b": {"c": [1, 2]}}, {"b": {"c": [3]}}]}` resolves to `[[1, 2], [3]]`.
+/// Elements the path is missing from are skipped: `memos.by` on
+/// `{"memos": [{"by": "shipping"}, {"memo": "x"}]}` resolves to `["shipping"]`.
+/// The path is missing if it is missing from all of them.
+///
+/// The resolved value is borrowed from the entry unless an array was traversed in parallel,
+/// so that only parallel descent allocates.
+///
+/// With [EvaluationOptions::case_insensitive_fields], a key without an exact match falls back to
+/// the first key equal to it up to case (see [eq_ignore_case]).
+/// With [EvaluationOptions::strict_array], only numeric segments reach into arrays.
+pub(crate) fn extract<'v>(
+    entry: Option<&'v Value>,
+    path: Split<'_, char>,
+    options: &EvaluationOptions,
+) -> Option<Cow<'v, Value>> {
+    let mut rest = path.clone();
+    let Some(segment) = rest.next() else {
+        return entry.map(Cow::Borrowed);
+    };
+    match entry? {
+        Value::Null => Some(Cow::Borrowed(&Value::Null)),
+        Value::Array(arr) => {
+            if let Ok(v) = i64::from_str(segment) {
+                extract(array_index(arr, v), rest, options)
+            } else {
+                if options.strict_array {
+                    return None;
                 }
+                // key-based nested document parallel indexing
+                let v: Vec<_> = arr
+                    .iter()
+                    .filter_map(|e| extract(Some(e), path.clone(), options))
+                    .map(Cow::into_owned)
+                    .collect();
+                (!v.is_empty()).then_some(Cow::Owned(Value::Array(v)))
             }
-            Value::Object(obj) => extract(obj.get(path[0]), &path[1..]),
-            _ => None,
         }
+        Value::Object(obj) => extract(object_field(obj, segment, options), rest, options),
+        _ => None,
+    }
+}
+
+/// Whether `matches` holds for any leaf of the value at a dotted path, split into its segments,
+/// as [any_leaf] of what [extract] resolves, but without building the arrays of parallel descent.
+fn any_extracted_leaf<F: FnMut(&Value) -> bool>(
+    entry: Option<&Value>,
+    path: Split<'_, char>,
+    options: &EvaluationOptions,
+    matches: &mut F,
+) -> bool {
+    let mut rest = path.clone();
+    let Some(segment) = rest.next() else {
+        return entry.is_some_and(|entry| any_leaf(entry, matches));
+    };
+    match entry {
+        Some(Value::Null) => matches(&Value::Null),
+        Some(Value::Array(arr)) => {
+            if let Ok(v) = i64::from_str(segment) {
+                any_extracted_leaf(array_index(arr, v), rest, options, matches)
+            } else {
+                !options.strict_array
+                    && arr
+                        .iter()
+                        .any(|e| any_extracted_leaf(Some(e), path.clone(), options, matches))
+            }
+        }
+        Some(Value::Object(obj)) => {
+            any_extracted_leaf(object_field(obj, segment, options), rest, options, matches)
+        }
+        _ => false,
+    }
+}
+
+/// The element at an index of a path, where negative indices count from the end.
+fn array_index(arr: &[Value], index: i64) -> Option<&Value> {
+    let index = if index < 0 {
+        arr.len().checked_sub(index.unsigned_abs() as usize)
+    } else {
+        Some(index as usize)
+    };
+    index.and_then(|i| arr.get(i))
+}
+
+/// The field of an object at a segment of a path, which numeric segments are plain keys of.
+fn object_field<'v>(
+    obj: &'v Map<String, Value>,
+    segment: &str,
+    options: &EvaluationOptions,
+) -> Option<&'v Value> {
+    obj.get(segment).or_else(|| {
+        if options.case_insensitive_fields {
+            obj.iter()
+                .find(|(key, _)| eq_ignore_case(key, segment, options))
+                .map(|(_, field)| field)
+        } else {
+            None
+        }
+    })
+}
+
+/// Whether the strings are equal up to case: ASCII case, or with
+/// [EvaluationOptions::unicode_case_folding], Unicode case folding.
+pub(crate) fn eq_ignore_case(lhs: &str, rhs: &str, options: &EvaluationOptions) -> bool {
+    if options.unicode_case_folding {
+        UniCase::unicode(lhs) == UniCase::unicode(rhs)
     } else {
-        None
+        lhs.eq_ignore_ascii_case(rhs)
     }
 }
 
-fn compound_condition_from_value<T>(v: &Value) -> Vec<Query<T>>
+/// Whether `matches` holds for the value or, if it is an array, for any of its leaves.
+///
+/// Nested arrays are searched all the way down, so `[[1, 2], [3]]` has the leaves `1`, `2` and `3`.
+pub(crate) fn any_leaf<F: FnMut(&Value) -> bool>(value: &Value, matches: &mut F) -> bool {
+    match value {
+        Value::Array(arr) => arr.iter().any(|e| any_leaf(e, matches)),
+        value => matches(value),
+    }
+}
+
+/// Whether a scalar condition matching with `matches` holds for the value.
+///
+/// That is [any_leaf], unless [EvaluationOptions::strict_array] restricts it to the value itself.
+pub(crate) fn scalar_match<F: FnMut(&Value) -> bool>(
+    value: &Value,
+    options: &EvaluationOptions,
+    matches: &mut F,
+) -> bool {
+    if options.strict_array {
+        matches(value)
+    } else {
+        any_leaf(value, matches)
+    }
+}
+
+/// The key of a reference to a field of the root document in an operator condition.
+///
+/// See [EvaluationOptions::resolve_field_refs].
+pub(crate) const FIELD_REF: &str = "$fieldRef";
+
+/// Replaces every `{"$fieldRef": "path"}` in the condition, at any depth, with the field
+/// of the root document at that dotted path.
+///
+/// The condition is borrowed if it holds no references. Fails with [QueryError::OperatorError]
+/// if there is no root document or a referenced field is missing from it.
+pub(crate) fn resolve_field_refs<'c>(
+    condition: &'c Value,
+    context: &OperatorContext,
+) -> Result<Cow<'c, Value>, QueryError> {
+    let error = |reason: String| QueryError::OperatorError {
+        operator: "fieldRef".to_string(),
+        reason,
+    };
+    match condition {
+        Value::Object(obj) if obj.len() == 1 && obj.contains_key(FIELD_REF) => {
+            let Value::String(path) = &obj[FIELD_REF] else {
+                return Err(error("reference must be a field path string".to_string()));
+            };
+            let root = crate::root_document("fieldRef", context)?;
+            extract(Some(root), path.split('.'), context.options)
+                .map(|field| Cow::Owned(field.into_owned()))
+                .ok_or_else(|| error(format!("field `{path}` is missing from the root document")))
+        }
+        Value::Array(arr) => {
+            let resolved = arr
+                .iter()
+                .map(|e| resolve_field_refs(e, context))
+                .collect::<Result<Vec<_>, _>>()?;
+            if resolved.iter().all(|e| matches!(e, Cow::Borrowed(_))) {
+                return Ok(Cow::Borrowed(condition));
+            }
+            Ok(Cow::Owned(Value::Array(
+                resolved.into_iter().map(Cow::into_owned).collect(),
+            )))
+        }
+        Value::Object(obj) => {
+            let resolved = obj
+                .iter()
+                .map(|(key, value)| Ok((key, resolve_field_refs(value, context)?)))
+                .collect::<Result<Vec<_>, QueryError>>()?;
+            if resolved.iter().all(|(_, v)| matches!(v, Cow::Borrowed(_))) {
+                return Ok(Cow::Borrowed(condition));
+            }
+            Ok(Cow::Owned(Value::Object(
+                resolved
+                    .into_iter()
+                    .map(|(key, value)| (key.clone(), value.into_owned()))
+                    .collect(),
+            )))
+        }
+        _ => Ok(Cow::Borrowed(condition)),
+    }
+}
+
+/// Whether the value is a non-empty object whose keys are all operators (`{"$gt": 1, "$lt": 5}`).
+pub(crate) fn is_operator_document(v: &Value) -> bool {
+    match v {
+        Value::Object(obj) => !obj.is_empty() && obj.keys().all(|k| k.starts_with('$')),
+        _ => false,
+    }
+}
+
+fn compound_condition_from_value<'a, T>(v: &'a Value) -> Vec<QueryRef<'a, T>>
 where
     T: OperatorProvider,
 {
     match v {
-        Value::Array(vec) => vec.iter().map(Query::from_value).collect(),
+        Value::Array(vec) => vec.iter().map(QueryRef::from_value_borrowed).collect(),
         _ => vec![],
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BaseOperators;
+    use serde_json::json;
+
+    #[test]
+    fn test_borrowed_operator_condition() {
+        let candidates: Vec<_> = (0..10_000).map(|i| json!(i)).collect();
+        let source = json!({"a": {"$in": candidates}});
+        let query = QueryRef::<BaseOperators>::from_value_borrowed(&source);
+
+        // the `$in` array is borrowed from the source rather than copied
+        let condition = match &query {
+            QueryRef::Compound(compound) => match compound.as_slice() {
+                [Condition::Field {
+                    op: QueryRef::Compound(ops),
+                    ..
+                }] => match ops.as_slice() {
+                    [Condition::Operator { condition, .. }] => condition,
+                    _ => panic!("expected a single operator"),
+                },
+                _ => panic!("expected a single field condition"),
+            },
+            _ => panic!("expected a compound query"),
+        };
+        assert!(matches!(condition, Cow::Borrowed(_)));
+        assert!(core::ptr::eq(condition.as_ref(), &source["a"]["$in"]));
+        assert!(query.evaluate(Some(&json!({"a": 9_999}))).unwrap());
+        assert!(!query.evaluate(Some(&json!({"a": 10_000}))).unwrap());
+    }
+
+    #[test]
+    fn test_regex_compiled_once() {
+        fn compiled(source: &Value) -> Option<String> {
+            match QueryRef::<BaseOperators>::from_value_borrowed(source) {
+                QueryRef::Compound(compound) => match compound.as_slice() {
+                    [Condition::Field {
+                        op: QueryRef::Compound(ops),
+                        ..
+                    }] => match ops.as_slice() {
+                        [Condition::Operator { regex, .. }] => {
+                            regex.as_deref().map(|r| r.regex().as_str().to_string())
+                        }
+                        _ => panic!("expected a single operator"),
+                    },
+                    _ => panic!("expected a single field condition"),
+                },
+                _ => panic!("expected a compound query"),
+            }
+        }
+
+        for (source, expected) in [
+            (json!({"a": {"$regex": "^j[k]"}}), Some("^j[k]")),
+            (json!({"a": {"$deepKeyMatch": "^j[k]"}}), Some("^j[k]")),
+            (
+                json!({"a": {"$regexCapture": {"pattern": "(j)k", "group": 1, "equals": "j"}}}),
+                Some("(j)k"),
+            ),
+            // literal patterns take the fast path, and broken ones are reported when evaluated
+            (json!({"a": {"$regex": "^jk"}}), None),
+            (json!({"a": {"$regex": "(jk"}}), None),
+            (json!({"a": {"$eq": "^j[k]"}}), None),
+        ] {
+            assert_eq!(expected.map(String::from), compiled(&source), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::operator::{ContextOperator, EvaluationOptions, StandardOperator};
+use crate::HashMap;
+use crate::{OperatorProvider, Query, QueryError};
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::fmt;
+use serde_json::Value;
+
+/// A [Query] that can be shared across threads and reused for many evaluations,
+/// e.g. by the request handlers of a web server.
+///
+/// The operators of the provider are retrieved once, when the query is shared,
+/// rather than on every evaluation. Clones are cheap and share the same query.
+/// ```
+/// use mongoquery::{BaseQuerier, Querier, SharedQuery};
+/// use serde_json::json;
+/// use std::thread;
+///
+/// let query = BaseQuerier::new_shared(&json!({"qty": {"$gt": 20}}));
+/// let handle = {
+///     let query = query.clone();
+///     thread::spawn(move || query.evaluate(Some(&json!({"qty": 25}))).unwrap())
+/// };
+/// assert!(handle.join().unwrap());
+/// assert!(!query.evaluate(Some(&json!({"qty": 10}))).unwrap());
+/// ```
+pub struct SharedQuery<T>
+where
+    T: OperatorProvider,
+{
+    inner: Arc<Shared<T>>,
+}
+
+struct Shared<T>
+where
+    T: OperatorProvider,
+{
+    query: Query<T>,
+    std_ops: HashMap<String, StandardOperator>,
+    ctx_ops: HashMap<String, ContextOperator>,
+}
+
+impl<T> SharedQuery<T>
+where
+    T: OperatorProvider,
+{
+    /// Shares the query, retrieving the operators of its provider.
+    pub fn new(query: Query<T>) -> Self {
+        SharedQuery::with_operators(query, T::get_operators(), T::get_context_operators())
+    }
+
+    /// Shares the query with the given operators rather than those of its provider.
+    pub(crate) fn with_operators(
+        query: Query<T>,
+        std_ops: HashMap<String, StandardOperator>,
+        ctx_ops: HashMap<String, ContextOperator>,
+    ) -> Self {
+        SharedQuery {
+            inner: Arc::new(Shared {
+                query,
+                std_ops,
+                ctx_ops,
+            }),
+        }
+    }
+
+    /// The shared query, for the evaluation methods not offered here.
+    pub fn query(&self) -> &Query<T> {
+        &self.inner.query
+    }
+
+    /// Evaluate the query on the specified value, as [Query::evaluate] does.
+    pub fn evaluate(&self, value: Option<&Value>) -> Result<bool, QueryError> {
+        self.evaluate_with_options(value, &EvaluationOptions::default())
+    }
+
+    /// Evaluate the query on the specified value with the given options,
+    /// as [Query::evaluate_with_options] does.
+    pub fn evaluate_with_options(
+        &self,
+        value: Option<&Value>,
+        options: &EvaluationOptions,
+    ) -> Result<bool, QueryError> {
+        let Shared {
+            query,
+            std_ops,
+            ctx_ops,
+        } = &*self.inner;
+        query.evaluate_with_operators(value, std_ops, ctx_ops, options)
+    }
+}
+
+impl<T> Clone for SharedQuery<T>
+where
+    T: OperatorProvider,
+{
+    fn clone(&self) -> Self {
+        SharedQuery {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T> From<Query<T>> for SharedQuery<T>
+where
+    T: OperatorProvider,
+{
+    fn from(query: Query<T>) -> Self {
+        SharedQuery::new(query)
+    }
+}
+
+impl<T> fmt::Debug for SharedQuery<T>
+where
+    T: OperatorProvider,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SharedQuery")
+            .field(&self.inner.query)
+            .finish()
+    }
+}
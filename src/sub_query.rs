@@ -0,0 +1,69 @@
+use crate::operator::OperatorContext;
+use crate::QueryError;
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+use serde_json::Value;
+
+/// The sub-queries an operator like `$countWhere` matches by, once its condition is checked
+/// against the evaluatee.
+///
+/// Operators describe their sub-queries rather than evaluating them, so that both the sync and
+/// the async engine evaluate them with every operator of the enclosing query.
+pub(crate) enum SubQueries<'a> {
+    /// The operator matched, or not, without any sub-query.
+    Matched(bool),
+    /// Matches when `query` matches `value`.
+    Match {
+        query: &'a Value,
+        value: Cow<'a, Value>,
+    },
+    /// Matches when each of the `queries` matches some of the `elements`.
+    EachMatchesAnElement {
+        queries: Vec<&'a Value>,
+        elements: &'a [Value],
+    },
+    /// Matches when `count` matches the number of `elements` matching `predicate`.
+    CountWhere {
+        predicate: &'a Value,
+        elements: &'a [Value],
+        count: &'a Value,
+    },
+}
+
+impl SubQueries<'_> {
+    /// Evaluates the sub-queries as part of the evaluation of `context`.
+    pub(crate) fn evaluate(self, context: &OperatorContext) -> Result<bool, QueryError> {
+        match self {
+            SubQueries::Matched(matched) => Ok(matched),
+            SubQueries::Match { query, value } => context.evaluate_sub_query(query, Some(&value)),
+            SubQueries::EachMatchesAnElement { queries, elements } => {
+                for query in queries {
+                    let mut matched = false;
+                    for e in elements {
+                        if context.evaluate_sub_query(query, Some(e))? {
+                            matched = true;
+                            break;
+                        }
+                    }
+                    if !matched {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            SubQueries::CountWhere {
+                predicate,
+                elements,
+                count,
+            } => {
+                let mut matching = 0;
+                for e in elements {
+                    if context.evaluate_sub_query(predicate, Some(e))? {
+                        matching += 1;
+                    }
+                }
+                context.evaluate_sub_query(count, Some(&Value::from(matching)))
+            }
+        }
+    }
+}
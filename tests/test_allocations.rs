@@ -0,0 +1,67 @@
+//! Counts heap allocations made while evaluating queries.
+//!
+//! This binary installs a counting global allocator, so it holds a single test
+//! to keep other tests from allocating concurrently.
+use mongoquery::{BaseQuerier, Querier};
+use serde_json::{json, Value};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn count_allocations(query: &Value, value: &Value) -> usize {
+    let querier = BaseQuerier::new(query);
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    querier.evaluate(Some(value)).unwrap();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn document(len: usize) -> Value {
+    json!({
+        "item": "xyz",
+        "size": {"uom": "cm"},
+        "tags": (0..len).map(|i| format!("tag{i}")).collect::<Vec<_>>(),
+        "memos": (0..len)
+            .map(|i| json!({"memo": format!("memo{i}"), "by": if i + 1 == len { "shipping" } else { "billing" }}))
+            .collect::<Vec<_>>(),
+    })
+}
+
+#[test]
+fn test_path_resolution_allocations() {
+    let (small, large) = (document(10), document(1000));
+
+    // resolving a field borrows it from the document, whatever its size
+    for query in [
+        json!({"tags": "tag5"}),
+        json!({"size.uom": "cm"}),
+        json!({"tags.3": "tag3"}),
+        json!({"tags": {"$size": 10}}),
+        // scalars are matched against the leaves of parallel descent without collecting them
+        json!({"memos.by": "shipping"}),
+        json!({"memos.-1.by": "shipping"}),
+    ] {
+        assert_eq!(
+            count_allocations(&query, &small),
+            count_allocations(&query, &large),
+            "{}",
+            query
+        );
+    }
+}
@@ -154,6 +154,26 @@ async fn test_element() {
         vec![records_ref[5], records_ref[6], records_ref[8]],
         query(json!({"c": {"$exists": false}}), records_ref.clone()).await
     );
+
+    // `null` matches both explicit nulls and missing fields
+    assert_eq!(
+        vec![
+            records_ref[1],
+            records_ref[4],
+            records_ref[6],
+            records_ref[9]
+        ],
+        query(json!({"b": null}), records_ref.clone()).await
+    );
+    assert_eq!(
+        vec![
+            records_ref[0],
+            records_ref[5],
+            records_ref[6],
+            records_ref[8]
+        ],
+        query(json!({"c": null}), records_ref.clone()).await
+    );
 }
 
 #[tokio::test]
@@ -209,3 +229,250 @@ async fn test_custom_ops() {
         .await
     );
 }
+
+#[tokio::test]
+async fn test_not() {
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"qty": {"$not": {"$gt": 20}}}), all()).await
+    );
+
+    let querier = AsyncBaseQuerier::new(&json!({"qty": {"$not": 5}}));
+    assert!(matches!(
+        querier.evaluate(Some(&FOOD)).await,
+        Err(QueryError::OperatorError { operator, .. }) if operator == "not"
+    ));
+}
+
+#[tokio::test]
+async fn test_elem_match() {
+    assert_eq!(
+        vec![&*FRUIT],
+        query(
+            json!({"memos": {"$elemMatch": {"memo": "on time", "by": "payment"}}}),
+            all()
+        )
+        .await
+    );
+    assert_eq!(
+        all(),
+        query(
+            json!({"ratings": {"$elemMatch": {"$not": {"$eq": 5}}}}),
+            all()
+        )
+        .await
+    );
+}
+
+#[tokio::test]
+async fn test_async_standard_operator() {
+    use mongoquery::{
+        AsyncOperatorFuture, AsyncOperatorProvider, AsyncStandardOperator, BaseOperators,
+        ContextOperator, OperatorProvider, StandardOperator,
+    };
+
+    fn slow_eq<'a>(evaluatee: Option<&'a Value>, condition: &'a Value) -> AsyncOperatorFuture<'a> {
+        Box::pin(async move {
+            tokio::task::yield_now().await;
+            Ok(evaluatee == Some(condition))
+        })
+    }
+
+    #[derive(Debug)]
+    struct SlowOperators;
+    impl OperatorProvider for SlowOperators {
+        fn get_operators() -> HashMap<String, StandardOperator> {
+            BaseOperators::get_operators()
+        }
+        fn get_context_operators() -> HashMap<String, ContextOperator> {
+            BaseOperators::get_context_operators()
+        }
+    }
+    impl AsyncOperatorProvider for SlowOperators {
+        fn get_async_operators() -> HashMap<String, AsyncStandardOperator> {
+            let mut map: HashMap<String, AsyncStandardOperator> = HashMap::new();
+            map.insert("slowEq".into(), slow_eq);
+            map
+        }
+    }
+    struct SlowQuerier;
+    impl AsyncQuerier for SlowQuerier {
+        type Provider = SlowOperators;
+    }
+
+    let querier = SlowQuerier::new(&json!({"item": {"$slowEq": "jkl"}, "qty": {"$lt": 20}}));
+    assert!(querier.evaluate(Some(&FRUIT)).await.unwrap());
+    assert!(!querier.evaluate(Some(&FOOD)).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_async_sub_queries() {
+    use mongoquery::{
+        AsyncContextOperator, AsyncOperatorContainer, AsyncOperatorFuture, AsyncOperatorProvider,
+        AsyncStandardOperator, BaseOperators, ContextOperator, OperatorProvider, StandardOperator,
+    };
+
+    fn slow_eq<'a>(evaluatee: Option<&'a Value>, condition: &'a Value) -> AsyncOperatorFuture<'a> {
+        Box::pin(async move {
+            tokio::task::yield_now().await;
+            Ok(evaluatee == Some(condition))
+        })
+    }
+
+    struct Above;
+    #[async_trait::async_trait]
+    impl AsyncCustomOperator for Above {
+        async fn evaluate(
+            &self,
+            evaluatee: Option<&Value>,
+            condition: &Value,
+        ) -> Result<bool, QueryError> {
+            Ok(evaluatee.and_then(Value::as_i64) > condition.as_i64())
+        }
+    }
+
+    #[derive(Debug)]
+    struct SlowOperators;
+    impl OperatorProvider for SlowOperators {
+        fn get_operators() -> HashMap<String, StandardOperator> {
+            BaseOperators::get_operators()
+        }
+        fn get_context_operators() -> HashMap<String, ContextOperator> {
+            BaseOperators::get_context_operators()
+        }
+    }
+    impl AsyncOperatorProvider for SlowOperators {
+        fn get_async_operators() -> HashMap<String, AsyncStandardOperator> {
+            let mut map: HashMap<String, AsyncStandardOperator> = HashMap::new();
+            map.insert("slowEq".into(), slow_eq);
+            map
+        }
+        fn get_async_context_operators() -> HashMap<String, AsyncContextOperator> {
+            BaseOperators::get_async_context_operators()
+        }
+    }
+    struct SlowQuerier;
+    impl AsyncQuerier for SlowQuerier {
+        type Provider = SlowOperators;
+    }
+
+    let mut ops = AsyncOperatorContainer::new();
+    ops.insert("above", Above);
+    // FOOD's ratings are [5, 8, 9], FRUIT's [5, 9]
+    for (q, expected) in [
+        (
+            json!({"ratings": {"$all": [
+                {"$elemMatch": {"$slowEq": 8}},
+                {"$elemMatch": {"$above": 8}},
+            ]}}),
+            vec![&*FOOD],
+        ),
+        (
+            json!({"ratings": {"$all": [{"$elemMatch": {"$above": 8}}]}}),
+            all(),
+        ),
+        (
+            json!({"ratings": {"$countWhere": {"predicate": {"$above": 6}, "count": {"$slowEq": 1}}}}),
+            vec![&*FRUIT],
+        ),
+        (
+            json!({"ratings": {"$atIndex": {"index": 1, "match": {"$slowEq": 9}}}}),
+            vec![&*FRUIT],
+        ),
+    ] {
+        let querier = SlowQuerier::new(&q);
+        let mut matching = vec![];
+        for doc in all() {
+            if querier
+                .evaluate_with_custom_ops(Some(doc), ops.as_ref())
+                .await
+                .unwrap()
+            {
+                matching.push(doc);
+            }
+        }
+        assert_eq!(expected, matching, "{}", q);
+    }
+}
+
+#[tokio::test]
+async fn test_sync_operator_provider() {
+    use mongoquery::{AsyncOperatorProvider, OperatorProvider, StandardOperator};
+
+    fn always(_evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        Ok(condition == &Value::Bool(true))
+    }
+
+    // providers written for `Querier` only need an empty impl to be used by `AsyncQuerier`
+    #[derive(Debug)]
+    struct SyncOperators;
+    impl OperatorProvider for SyncOperators {
+        fn get_operators() -> HashMap<String, StandardOperator> {
+            let mut map: HashMap<String, StandardOperator> = HashMap::new();
+            map.insert("always".into(), always);
+            map
+        }
+    }
+    impl AsyncOperatorProvider for SyncOperators {}
+    struct SyncQuerier;
+    impl AsyncQuerier for SyncQuerier {
+        type Provider = SyncOperators;
+    }
+
+    assert!(SyncQuerier::new(&json!({"item": {"$always": true}}))
+        .evaluate(Some(&FOOD))
+        .await
+        .unwrap());
+    assert!(!SyncQuerier::new(&json!({"item": {"$always": false}}))
+        .evaluate(Some(&FOOD))
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+async fn test_ignored_keys() {
+    assert_eq!(
+        vec![&*FOOD],
+        query(
+            json!({"qty": {"$gt": 20}, "$comment": "restock", "$maxTimeMS": 500}),
+            all()
+        )
+        .await
+    );
+    assert_eq!(all(), query(json!({"$hint": {"qty": 1}}), all()).await);
+}
+
+#[cfg(feature = "stream")]
+#[tokio::test]
+async fn test_filter_async_read() {
+    use futures::io::Cursor;
+
+    let input = format!(
+        "{}\n\n{}\n{{\"qty\": \"not a number\"}}\n{{\"qty\": 30\n{{\"qty\": 50}}\n",
+        *FOOD, *FRUIT
+    );
+    let querier = AsyncBaseQuerier::new(&json!({"qty": {"$gt": 20}}));
+    let results: Vec<_> = querier
+        .filter_async_read(Cursor::new(input.into_bytes()))
+        .collect()
+        .await;
+
+    assert_eq!(3, results.len());
+    assert_eq!(&*FOOD, results[0].as_ref().unwrap());
+    assert!(matches!(
+        results[1],
+        Err(QueryError::SerializationError { .. })
+    ));
+    assert_eq!(&json!({"qty": 50}), results[2].as_ref().unwrap());
+
+    // evaluation errors are yielded as well
+    let querier = AsyncBaseQuerier::new(&json!({"qty": {"$size": -1}}));
+    let results: Vec<_> = querier
+        .filter_async_read(Cursor::new(b"{\"qty\": 1}\n{\"qty\": 2}".to_vec()))
+        .collect()
+        .await;
+    assert_eq!(2, results.len());
+    assert!(results
+        .iter()
+        .all(|result| matches!(result, Err(QueryError::OperatorError { .. }))));
+}
@@ -0,0 +1,68 @@
+//! Captures the trace records of operator evaluations.
+//!
+//! This binary installs a global logger, so it holds a single test
+//! to keep other tests from logging concurrently.
+#![cfg(feature = "log")]
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use mongoquery::{AsyncBaseQuerier, AsyncQuerier, BaseQuerier, Querier};
+use serde_json::json;
+use std::sync::Mutex;
+
+struct CapturingLogger;
+
+static RECORDS: Mutex<Vec<(Level, String, String)>> = Mutex::new(Vec::new());
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        RECORDS.lock().unwrap().push((
+            record.level(),
+            record.target().to_string(),
+            record.args().to_string(),
+        ));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger;
+
+#[test]
+fn test_operator_trace_records() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(LevelFilter::Trace);
+
+    let querier = BaseQuerier::new(&json!({"qty": {"$gt": 20}, "item": {"$regex": "^x"}}));
+    assert!(querier
+        .evaluate(Some(&json!({"qty": 25, "item": "xyz"})))
+        .unwrap());
+    let querier = BaseQuerier::new(&json!({"qty": {"$size": -1}}));
+    assert!(querier.evaluate(Some(&json!({"qty": 25}))).is_err());
+    let querier = AsyncBaseQuerier::new(&json!({"qty": {"$lt": 30}, "$**": {"$eq": "xyz"}}));
+    assert!(futures::executor::block_on(
+        querier.evaluate(Some(&json!({"item": "xyz", "qty": 25})))
+    )
+    .unwrap());
+
+    let records = RECORDS.lock().unwrap();
+    let messages: Vec<_> = records
+        .iter()
+        .map(|(level, target, message)| {
+            assert_eq!((Level::Trace, "mongoquery"), (*level, target.as_str()));
+            message.as_str()
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            r#"$regex "^x" on field Some("item"): Ok(true)"#,
+            r#"$gt 20 on field Some("qty"): Ok(true)"#,
+            r#"$size -1 on field Some("qty"): Err(OperatorError { operator: "size", reason: "condition must be a non-negative integer" })"#,
+            r#"$eq "xyz" on field Some("item"): Ok(true)"#,
+            r#"$lt 30 on field Some("qty"): Ok(true)"#,
+        ],
+        messages
+    );
+}
@@ -1,6 +1,8 @@
 use lazy_static::lazy_static;
 use mongoquery::CustomOperator;
-use mongoquery::{BaseQuerier, Querier, QueryError};
+use mongoquery::{
+    BaseQuerier, EvaluationOptions, FailureInfo, Index, Mismatch, Querier, QueryError,
+};
 use serde_json::{json, Value};
 use std::collections::HashMap;
 
@@ -126,6 +128,99 @@ fn test_element() {
         vec![records_ref[5], records_ref[6], records_ref[8]],
         query(json!({"c": {"$exists": false}}), records_ref.clone())
     );
+
+    // `null` matches both explicit nulls and missing fields
+    assert_eq!(
+        vec![
+            records_ref[1],
+            records_ref[4],
+            records_ref[6],
+            records_ref[9]
+        ],
+        query(json!({"b": null}), records_ref.clone())
+    );
+    assert_eq!(
+        vec![
+            records_ref[0],
+            records_ref[5],
+            records_ref[6],
+            records_ref[8]
+        ],
+        query(json!({"c": null}), records_ref.clone())
+    );
+    // and so does `$eq`, while `$ne` only matches present, non-null fields
+    assert_eq!(
+        query(json!({"b": null}), records_ref.clone()),
+        query(json!({"b": {"$eq": null}}), records_ref.clone())
+    );
+    assert_eq!(
+        query(json!({"c": null}), records_ref.clone()),
+        query(json!({"c": {"$eq": null}}), records_ref.clone())
+    );
+    assert_eq!(
+        vec![
+            records_ref[0],
+            records_ref[2],
+            records_ref[3],
+            records_ref[5],
+            records_ref[7],
+            records_ref[8]
+        ],
+        query(json!({"b": {"$ne": null}}), records_ref.clone())
+    );
+
+    // `$nullish` tells null and missing fields apart from present ones, and `$exists` null from missing
+    assert_eq!(
+        vec![
+            records_ref[1],
+            records_ref[4],
+            records_ref[6],
+            records_ref[9]
+        ],
+        query(json!({"b": {"$nullish": true}}), records_ref.clone())
+    );
+    assert_eq!(
+        vec![
+            records_ref[0],
+            records_ref[2],
+            records_ref[3],
+            records_ref[5],
+            records_ref[7],
+            records_ref[8]
+        ],
+        query(json!({"b": {"$nullish": false}}), records_ref.clone())
+    );
+    assert_eq!(
+        vec![records_ref[1]],
+        query(
+            json!({"b": {"$nullish": true, "$exists": true}}),
+            records_ref.clone()
+        )
+    );
+    assert_eq!(
+        vec![records_ref[0]],
+        query(
+            json!({"c": {"$nullish": true, "$exists": true}}),
+            records_ref.clone()
+        )
+    );
+    // arrays of nulls are present values
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"ratings": {"$nullish": false}}), vec![&*FOOD])
+    );
+    let record = json!({"tags": [null]});
+    assert!(BaseQuerier::new(&json!({"tags": null}))
+        .evaluate(Some(&record))
+        .unwrap());
+    assert!(!BaseQuerier::new(&json!({"tags": {"$nullish": true}}))
+        .evaluate(Some(&record))
+        .unwrap());
+    let querier = BaseQuerier::new(&json!({"b": {"$nullish": 1}}));
+    assert!(matches!(
+        querier.evaluate(Some(&records[0])),
+        Err(QueryError::OperatorError { operator, .. }) if operator == "nullish"
+    ));
 }
 
 #[test]
@@ -177,3 +272,4570 @@ fn test_custom_ops() {
         )
     );
 }
+
+#[test]
+fn test_custom_operator_macro() {
+    use mongoquery::{custom_operator, OperatorContainer};
+
+    let mut ops = OperatorContainer::new();
+    custom_operator!(ops, "longer_than", |evaluatee, condition| {
+        match (evaluatee, condition.as_u64()) {
+            (Some(Value::String(s)), Some(len)) => Ok(s.len() as u64 > len),
+            _ => Ok(false),
+        }
+    });
+    custom_operator!(ops, "divisible_by", |evaluatee, condition| {
+        match (evaluatee.and_then(Value::as_i64), condition.as_i64()) {
+            (Some(n), Some(d)) if d != 0 => Ok(n % d == 0),
+            _ => Err(QueryError::OperatorError {
+                operator: "divisible_by".to_string(),
+                reason: "non-integer operand".to_string(),
+            }),
+        }
+    });
+
+    assert_eq!(
+        vec![&*FRUIT],
+        query_custom(
+            json!({"type": {"$longer_than": 4}, "qty": {"$divisible_by": 5}}),
+            all(),
+            ops.as_ref()
+        )
+    );
+    assert_eq!(
+        vec![&*FOOD],
+        query_custom(json!({"qty": {"$divisible_by": 25}}), all(), ops.as_ref())
+    );
+}
+
+#[test]
+fn test_evaluate_with_matches() {
+    let querier = BaseQuerier::new(&json!({"ratings": {"$gt": 8}}));
+    let report = querier.evaluate_with_matches(Some(&FOOD)).unwrap();
+    assert!(report.matched);
+    assert_eq!(Some(&2), report.indices.get("ratings"));
+
+    let querier = BaseQuerier::new(&json!({"$and": [{"type": "fruit"}, {"memos.by": "shipping"}]}));
+    let report = querier.evaluate_with_matches(Some(&FRUIT)).unwrap();
+    assert!(report.matched);
+    assert_eq!(Some(&1), report.indices.get("memos.by"));
+    assert_eq!(None, report.indices.get("type"));
+
+    let report = querier.evaluate_with_matches(Some(&FOOD)).unwrap();
+    assert!(!report.matched);
+    assert!(report.indices.is_empty());
+
+    // an array condition equal to the whole array matches no element in particular
+    let querier = BaseQuerier::new(&json!({"ratings": [5, 8, 9]}));
+    let report = querier.evaluate_with_matches(Some(&FOOD)).unwrap();
+    assert!(report.matched);
+    assert_eq!(None, report.indices.get("ratings"));
+
+    // unlike one equal to an element
+    let record = json!({"ratings": [[5, 8], [9]]});
+    let querier = BaseQuerier::new(&json!({"ratings": [9]}));
+    let report = querier.evaluate_with_matches(Some(&record)).unwrap();
+    assert!(report.matched);
+    assert_eq!(Some(&1), report.indices.get("ratings"));
+}
+
+#[test]
+fn test_strlen() {
+    assert_eq!(all(), query(json!({"item": {"$strlen": 3}}), all()));
+    assert_eq!(empty(), query(json!({"item": {"$strlen": 4}}), all()));
+    assert_eq!(vec![&*FRUIT], query(json!({"type": {"$strlen": 5}}), all()));
+
+    // lengths are counted in unicode scalar values, not bytes
+    let records = [json!({"name": "héllo"}), json!({"name": "日本"})];
+    let records_ref: Vec<_> = records.iter().collect();
+    assert_eq!(
+        vec![records_ref[0]],
+        query(json!({"name": {"$strlen": 5}}), records_ref.clone())
+    );
+    assert_eq!(
+        vec![records_ref[1]],
+        query(json!({"name": {"$strlen": 2}}), records_ref.clone())
+    );
+
+    let querier = BaseQuerier::new(&json!({"qty": {"$strlen": 2}}));
+    assert!(matches!(
+        querier.evaluate(Some(&FOOD)),
+        Err(QueryError::OperatorError { .. })
+    ));
+    let querier = BaseQuerier::new(&json!({"item": {"$strlen": 2.5}}));
+    assert!(matches!(
+        querier.evaluate(Some(&FOOD)),
+        Err(QueryError::OperatorError { .. })
+    ));
+}
+
+#[test]
+fn test_string_matches() {
+    let records = [
+        json!({"name": "abc"}),
+        json!({"name": "héllo"}),
+        json!({"name": "abc123"}),
+        json!({"name": "2024"}),
+        json!({"name": "日本"}),
+        json!({"name": "a b"}),
+        json!({"name": ""}),
+        json!({"name": 12345}),
+        json!({"name": ["abc"]}),
+        json!({}),
+    ];
+    let records_ref: Vec<_> = records.iter().collect();
+    let matching = |condition: Value| {
+        query(
+            json!({"name": {"$stringMatches": condition}}),
+            records_ref.clone(),
+        )
+        .into_iter()
+        .map(|record| record["name"].clone())
+        .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        vec![json!("héllo"), json!("abc123"), json!("2024")],
+        matching(json!({"minLen": 4}))
+    );
+    assert_eq!(
+        vec![
+            json!("abc"),
+            json!("2024"),
+            json!("日本"),
+            json!("a b"),
+            json!("")
+        ],
+        matching(json!({"maxLen": 4}))
+    );
+    assert_eq!(
+        vec![json!("abc"), json!("a b")],
+        matching(json!({"minLen": 3, "maxLen": 3}))
+    );
+    assert_eq!(
+        vec![json!("abc"), json!("héllo"), json!("日本"), json!("")],
+        matching(json!({"charset": "alpha"}))
+    );
+    assert_eq!(
+        vec![
+            json!("abc"),
+            json!("héllo"),
+            json!("abc123"),
+            json!("2024"),
+            json!("日本"),
+            json!("")
+        ],
+        matching(json!({"charset": "alnum"}))
+    );
+    assert_eq!(
+        vec![json!("2024"), json!("")],
+        matching(json!({"charset": "digit"}))
+    );
+    assert_eq!(
+        vec![
+            json!("abc"),
+            json!("abc123"),
+            json!("2024"),
+            json!("a b"),
+            json!("")
+        ],
+        matching(json!({"charset": "ascii"}))
+    );
+    assert_eq!(
+        vec![json!("abc"), json!("héllo"), json!("abc123"), json!("2024")],
+        matching(json!({"minLen": 3, "maxLen": 10, "charset": "alnum"}))
+    );
+    assert_eq!(
+        vec![
+            json!("abc"),
+            json!("héllo"),
+            json!("abc123"),
+            json!("2024"),
+            json!("日本"),
+            json!("a b"),
+            json!("")
+        ],
+        matching(json!({}))
+    );
+
+    for invalid in [
+        json!("alnum"),
+        json!({"minLen": -1}),
+        json!({"maxLen": 2.5}),
+        json!({"charset": "hex"}),
+        json!({"charset": 1}),
+        json!({"minLen": 1, "pattern": "a*"}),
+    ] {
+        let querier = BaseQuerier::new(&json!({"item": {"$stringMatches": invalid}}));
+        assert!(
+            matches!(
+                querier.evaluate(Some(&FOOD)),
+                Err(QueryError::OperatorError { operator, .. }) if operator == "stringMatches"
+            ),
+            "{}",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn test_field_scoped_logical_operators() {
+    let records = [
+        json!({"status": "A", "qty": 5}),
+        json!({"status": "B", "qty": 15}),
+        json!({"status": "D", "qty": 25}),
+        json!({"status": ["C", "D"], "qty": 35}),
+        json!({"qty": 45}),
+    ];
+    let r: Vec<_> = records.iter().collect();
+
+    for (q, expected) in [
+        // either value, like `$in`
+        (
+            json!({"status": {"$or": ["A", "D"]}}),
+            vec![r[0], r[2], r[3]],
+        ),
+        (
+            json!({"status": {"$in": ["A", "D"]}}),
+            vec![r[0], r[2], r[3]],
+        ),
+        (json!({"status": {"$or": ["E"]}}), vec![]),
+        (json!({"status": {"$or": [null, "B"]}}), vec![r[1], r[4]]),
+        // branches may be operator documents
+        (
+            json!({"qty": {"$or": [5, {"$gt": 30}]}}),
+            vec![r[0], r[3], r[4]],
+        ),
+        (
+            json!({"qty": {"$and": [{"$gt": 10}, {"$lt": 30}]}}),
+            vec![r[1], r[2]],
+        ),
+        (json!({"qty": {"$nor": [5, {"$gt": 30}]}}), vec![r[1], r[2]]),
+        // alongside other operators on the field
+        (json!({"qty": {"$or": [5, 25], "$lt": 20}}), vec![r[0]]),
+    ] {
+        assert_eq!(expected, query(q.clone(), r.clone()), "{}", q);
+    }
+}
+
+#[test]
+fn test_mixed_field_and_logical_conditions() {
+    // a field condition and a logical operator in the same document must both hold
+    let extra = json!({"type": "food", "item": "abc", "qty": 5, "price": 10});
+    let collection = || vec![&*FOOD, &*FRUIT, &extra];
+    let or = json!([{"qty": {"$gt": 20}}, {"price": {"$lt": 3}}]);
+
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"type": "food", "$or": or}), collection())
+    );
+    // neither FRUIT nor extra satisfies the $or, while extra does satisfy the field condition
+    assert_eq!(vec![&*FOOD], query(json!({"$or": or}), collection()));
+    assert_eq!(
+        vec![&*FOOD, &extra],
+        query(json!({"type": "food"}), collection())
+    );
+    assert_eq!(
+        empty(),
+        query(json!({"type": "fruit", "$or": or}), collection())
+    );
+    assert_eq!(
+        vec![&*FRUIT],
+        query(
+            json!({"type": "fruit", "$or": [{"qty": {"$lt": 20}}, {"price": {"$lt": 3}}]}),
+            collection()
+        )
+    );
+
+    // as do several logical operators, however keys are ordered
+    let q = json!({
+        "$nor": [{"item": "abc"}],
+        "type": "food",
+        "$and": [{"qty": {"$gte": 5}}],
+        "$or": [{"price": {"$lt": 3}}, {"price": {"$gt": 5}}],
+    });
+    assert_eq!(vec![&*FOOD], query(q, collection()));
+    assert_eq!(
+        vec![&extra],
+        query(
+            json!({"type": "food", "$nor": [{"item": "xyz"}], "qty": {"$lt": 10}}),
+            collection()
+        )
+    );
+}
+
+#[test]
+fn test_not() {
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"qty": {"$not": {"$gt": 20}}}), all())
+    );
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"qty": {"$not": {"$lt": 20, "$gt": 5}}}), all())
+    );
+
+    for invalid in [
+        json!(5),
+        json!("xyz"),
+        json!([1, 2]),
+        json!({}),
+        json!({"a": 1}),
+    ] {
+        let querier = BaseQuerier::new(&json!({ "qty": { "$not": invalid } }));
+        assert!(matches!(
+            querier.evaluate(Some(&FOOD)),
+            Err(QueryError::OperatorError { operator, .. }) if operator == "not"
+        ));
+    }
+}
+
+#[test]
+fn test_not_in() {
+    let no_qty = json!({"_id": 102, "type": "food", "item": "abc"});
+    let collection = || vec![&*FOOD, &*FRUIT, &no_qty];
+
+    // $not over $in is "not in, or field missing", which is what $nin means
+    for values in [
+        json!([10, 42]),
+        json!([25]),
+        json!([10, 25]),
+        json!([]),
+        json!([[10]]),
+    ] {
+        let not_in = query(json!({"qty": {"$not": {"$in": values}}}), collection());
+        assert_eq!(
+            query(json!({"qty": {"$nin": values}}), collection()),
+            not_in,
+            "{}",
+            values
+        );
+        assert!(not_in.contains(&&no_qty), "{}", values);
+    }
+    assert_eq!(
+        vec![&*FOOD, &no_qty],
+        query(json!({"qty": {"$not": {"$in": [10, 42]}}}), collection())
+    );
+
+    // and $not over $nin only matches present fields that are in the list
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"qty": {"$not": {"$nin": [10, 42]}}}), collection())
+    );
+    assert_eq!(
+        query(json!({"qty": {"$in": [10, 42]}}), collection()),
+        query(json!({"qty": {"$not": {"$nin": [10, 42]}}}), collection())
+    );
+
+    // arrays are in the list if any of their elements is
+    assert_eq!(
+        vec![&no_qty],
+        query(json!({"ratings": {"$not": {"$in": [9]}}}), collection())
+    );
+    assert_eq!(
+        vec![&*FRUIT, &no_qty],
+        query(json!({"ratings": {"$not": {"$in": [8]}}}), collection())
+    );
+}
+
+#[test]
+fn test_pointer_exists() {
+    assert_eq!(
+        all(),
+        query(json!({"$pointerExists": "/memos/1/by"}), all())
+    );
+    assert_eq!(empty(), query(json!({"$pointerExists": "/memos/2"}), all()));
+    assert_eq!(empty(), query(json!({"$pointerExists": "/size/h"}), all()));
+
+    // pointers always resolve against the root document, even under a field
+    let doc = json!({"size": {"h": 14, "w": 21}, "status": "A"});
+    let querier = BaseQuerier::new(&json!({"status": {"$pointerExists": "/size/h"}}));
+    assert!(querier.evaluate(Some(&doc)).unwrap());
+    let querier = BaseQuerier::new(&json!({"status": {"$pointerExists": "/size/d"}}));
+    assert!(!querier.evaluate(Some(&doc)).unwrap());
+
+    let querier = BaseQuerier::new(&json!({"$pointerExists": 1}));
+    assert!(matches!(
+        querier.evaluate(Some(&doc)),
+        Err(QueryError::OperatorError { .. })
+    ));
+}
+
+#[test]
+fn test_between() {
+    assert_eq!(all(), query(json!({"qty": {"$between": [10, 25]}}), all()));
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"qty": {"$between": [10, 25, [true, false]]}}), all())
+    );
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"qty": {"$between": [10, 25, [false, true]]}}), all())
+    );
+    assert_eq!(
+        empty(),
+        query(json!({"qty": {"$between": [10, 25, [true, true]]}}), all())
+    );
+    assert_eq!(
+        vec![&*FOOD],
+        query(
+            json!({"price": {"$between": [2.5, 4.25, [false, true]]}}),
+            all()
+        )
+    );
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"price": {"$between": [3, 5]}}), all())
+    );
+    // a single array element has to satisfy both bounds
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"ratings": {"$between": [6, 8]}}), all())
+    );
+
+    for malformed in [
+        json!(5),
+        json!([1]),
+        json!([1, 2, 3]),
+        json!([1, 2, [true]]),
+    ] {
+        let querier = BaseQuerier::new(&json!({ "qty": { "$between": malformed } }));
+        assert!(matches!(
+            querier.evaluate(Some(&FOOD)),
+            Err(QueryError::OperatorError { .. })
+        ));
+    }
+}
+
+#[test]
+fn test_evaluate_detailed() {
+    use mongoquery::{DetailedOperator, MatchOutcome};
+
+    /// Matches strings starting with the condition and captures the rest of the string.
+    struct StripPrefix;
+    impl DetailedOperator for StripPrefix {
+        fn evaluate(
+            &self,
+            evaluatee: Option<&Value>,
+            condition: &Value,
+        ) -> Result<MatchOutcome, QueryError> {
+            let rest = match (evaluatee, condition) {
+                (Some(Value::String(s)), Value::String(prefix)) => s.strip_prefix(prefix.as_str()),
+                _ => None,
+            };
+            Ok(MatchOutcome {
+                matched: rest.is_some(),
+                detail: rest.map(Value::from),
+            })
+        }
+    }
+
+    let mut detailed_ops: HashMap<String, Box<dyn DetailedOperator>> = HashMap::new();
+    detailed_ops.insert("strip_prefix".to_string(), Box::new(StripPrefix));
+
+    let querier =
+        BaseQuerier::new(&json!({"item": {"$strip_prefix": "x"}, "type": {"$strip_prefix": "fo"}}));
+    assert_eq!(
+        MatchOutcome {
+            matched: true,
+            detail: Some(json!(["yz", "od"])),
+        },
+        querier
+            .evaluate_detailed(Some(&FOOD), &detailed_ops)
+            .unwrap()
+    );
+    assert_eq!(
+        MatchOutcome {
+            matched: false,
+            detail: None,
+        },
+        querier
+            .evaluate_detailed(Some(&FRUIT), &detailed_ops)
+            .unwrap()
+    );
+
+    // detailed operators of sub-queries report their details too
+    let querier = BaseQuerier::new(&json!({
+        "memos": {"$atIndex": {"index": 0, "match": {"memo": {"$strip_prefix": "on "}}}}
+    }));
+    assert_eq!(
+        MatchOutcome {
+            matched: true,
+            detail: Some(json!(["time"])),
+        },
+        querier
+            .evaluate_detailed(Some(&FOOD), &detailed_ops)
+            .unwrap()
+    );
+
+    // standard operators keep working alongside detailed ones
+    let querier = BaseQuerier::new(&json!({"qty": {"$gt": 20}}));
+    assert_eq!(
+        MatchOutcome {
+            matched: true,
+            detail: None,
+        },
+        querier
+            .evaluate_detailed(Some(&FOOD), &detailed_ops)
+            .unwrap()
+    );
+}
+
+#[test]
+fn test_simplify() {
+    let nested = json!({"$and": [
+        {"$and": [
+            {"$and": [{"type": "food"}, {"qty": {"$gt": 20}}]},
+            {"price": {"$lt": 3}},
+        ]},
+        {"qty": {"$gt": 20}},
+        {"item": "xyz"},
+    ]});
+    let flat = json!({"$and": [
+        {"type": "food"},
+        {"qty": {"$gt": 20}},
+        {"price": {"$lt": 3}},
+        {"item": "xyz"},
+    ]});
+    let simplified = BaseQuerier::new(&nested).simplify();
+    assert_eq!(BaseQuerier::new(&flat), simplified);
+    assert_ne!(BaseQuerier::new(&flat), BaseQuerier::new(&nested));
+    for doc in all() {
+        assert_eq!(
+            BaseQuerier::new(&nested).evaluate(Some(doc)).unwrap(),
+            simplified.evaluate(Some(doc)).unwrap()
+        );
+    }
+    assert_eq!(vec![&*FOOD], query(nested, all()));
+
+    let nested = json!({"$or": [{"$or": [{"type": "ham"}, {"qty": 10}]}, {"type": "ham"}]});
+    let simplified = BaseQuerier::new(&nested).simplify();
+    assert_eq!(
+        BaseQuerier::new(&json!({"$or": [{"type": "ham"}, {"qty": 10}]})),
+        simplified
+    );
+    for doc in all() {
+        assert_eq!(
+            BaseQuerier::new(&nested).evaluate(Some(doc)).unwrap(),
+            simplified.evaluate(Some(doc)).unwrap()
+        );
+    }
+
+    // single-branch logical operators are unwrapped
+    let single = json!({"$or": [{"$and": [{"type": "food", "qty": {"$gt": 20}}]}]});
+    let simplified = BaseQuerier::new(&single).simplify();
+    assert_eq!(
+        BaseQuerier::new(&json!({"type": "food", "qty": {"$gt": 20}})),
+        simplified
+    );
+    let single_nor = json!({"$nor": [{"type": "food"}]});
+    assert_eq!(
+        BaseQuerier::new(&json!({"type": "food"})).negate(),
+        BaseQuerier::new(&single_nor).simplify()
+    );
+    for (original, simplified) in [
+        (&single, simplified),
+        (&single_nor, BaseQuerier::new(&single_nor).simplify()),
+    ] {
+        for doc in all() {
+            assert_eq!(
+                BaseQuerier::new(original).evaluate(Some(doc)).unwrap(),
+                simplified.evaluate(Some(doc)).unwrap()
+            );
+        }
+    }
+
+    // logical operators of different kinds are left alone
+    let mixed = json!({"$and": [{"$or": [{"type": "ham"}, {"qty": 10}]}, {"item": "jkl"}]});
+    assert_eq!(
+        BaseQuerier::new(&mixed),
+        BaseQuerier::new(&mixed).simplify()
+    );
+}
+
+#[cfg(feature = "timing")]
+#[test]
+fn test_timing_hook() {
+    use std::cell::RefCell;
+    use std::time::Duration;
+
+    let samples: RefCell<Vec<(String, Duration)>> = RefCell::new(vec![]);
+    let hook = |operator: &str, elapsed: Duration| {
+        samples.borrow_mut().push((operator.to_string(), elapsed));
+    };
+    let querier = BaseQuerier::new(&json!({"qty": {"$gt": 20}, "ratings": {"$in": [5, 6]}}));
+    assert!(querier
+        .evaluate_with_timing_hook(Some(&FOOD), &HashMap::new(), &hook)
+        .unwrap());
+
+    let operators: Vec<_> = samples.take().into_iter().map(|(op, _)| op).collect();
+    assert_eq!(vec!["gt", "in"], operators);
+
+    // operators of sub-queries are timed too, before the operator holding them
+    let querier = BaseQuerier::new(&json!({
+        "ratings": {"$countWhere": {"predicate": {"$gte": 8}, "count": {"$lt": 3}}}
+    }));
+    assert!(querier
+        .evaluate_with_timing_hook(Some(&FOOD), &HashMap::new(), &hook)
+        .unwrap());
+    let operators: Vec<_> = samples.into_inner().into_iter().map(|(op, _)| op).collect();
+    assert_eq!(vec!["gte", "gte", "gte", "lt", "countWhere"], operators);
+}
+
+#[test]
+fn test_truthy() {
+    let records = vec![
+        json!({"active": true}),
+        json!({"active": false}),
+        json!({"active": 1}),
+        json!({"active": 0}),
+        json!({"active": -0.5}),
+        json!({"active": "yes"}),
+        json!({"active": ""}),
+        json!({"active": [0]}),
+        json!({"active": []}),
+        json!({"active": {"a": 1}}),
+        json!({"active": {}}),
+        json!({"active": null}),
+        json!({}),
+    ];
+    let records_ref: Vec<_> = records.iter().collect();
+
+    let truthy: Vec<_> = [0, 2, 4, 5, 7, 9].iter().map(|&i| records_ref[i]).collect();
+    let falsy: Vec<_> = [1, 3, 6, 8, 10, 11, 12]
+        .iter()
+        .map(|&i| records_ref[i])
+        .collect();
+    assert_eq!(
+        truthy,
+        query(json!({"active": {"$truthy": true}}), records_ref.clone())
+    );
+    assert_eq!(
+        falsy,
+        query(json!({"active": {"$truthy": false}}), records_ref.clone())
+    );
+
+    let querier = BaseQuerier::new(&json!({"active": {"$truthy": 1}}));
+    assert!(matches!(
+        querier.evaluate(Some(records_ref[0])),
+        Err(QueryError::OperatorError { .. })
+    ));
+}
+
+#[test]
+fn test_negative_index() {
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"memos.-1.by": "billing"}), all())
+    );
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"memos.-1.by": "shipping"}), all())
+    );
+    assert_eq!(all(), query(json!({"memos.-2.memo": "on time"}), all()));
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"ratings.-1": 9, "ratings.-3": 5}), all())
+    );
+    assert_eq!(
+        empty(),
+        query(json!({"memos.-3.memo": {"$exists": true}}), all())
+    );
+    assert_eq!(
+        vec![&*FRUIT],
+        query(
+            json!({"memos.-1": {"memo": "delayed", "by": "shipping"}}),
+            all()
+        )
+    );
+}
+
+#[test]
+fn test_unsupported_operators() {
+    use mongoquery::{BaseOperators, OperatorProvider};
+
+    let querier = BaseQuerier::new(&json!({
+        "qty": {"$gt": 20, "$near": [1, 2]},
+        "$or": [
+            {"item": {"$text": "xyz"}},
+            {"memos": {"$not": {"$near": 5}}},
+            {"$pointerExists": "/memos"},
+        ],
+        "type": "food",
+    }));
+    assert_eq!(
+        vec!["text".to_string(), "near".to_string()],
+        querier.unsupported_operators(&BaseOperators::get_operators())
+    );
+    assert!(BaseQuerier::new(&json!({"qty": {"$in": [1, 2]}}))
+        .unsupported_operators(&BaseOperators::get_operators())
+        .is_empty());
+}
+
+#[test]
+fn test_regex_capture() {
+    // FOOD's item is "xyz", FRUIT's "jkl"
+    for (condition, expected) in [
+        (
+            json!({"pattern": "(\\w)(\\w+)", "group": 1, "equals": "x"}),
+            vec![&*FOOD],
+        ),
+        (
+            json!({"pattern": "(\\w)(\\w+)", "group": 2, "equals": "kl"}),
+            vec![&*FRUIT],
+        ),
+        (
+            json!({"pattern": "(\\w)(\\w+)", "group": 0, "equals": "jkl"}),
+            vec![&*FRUIT],
+        ),
+        (
+            json!({"pattern": "(\\w)(\\w+)", "group": 1, "equals": "y"}),
+            empty(),
+        ),
+        (
+            json!({"pattern": "^.(?P<middle>.)", "group": "middle", "equals": "y"}),
+            vec![&*FOOD],
+        ),
+        // groups that don't participate equal nothing, not even the empty string
+        (
+            json!({"pattern": "x|(q)", "group": 1, "equals": ""}),
+            empty(),
+        ),
+        (
+            json!({"pattern": "x(q?)", "group": 1, "equals": ""}),
+            vec![&*FOOD],
+        ),
+    ] {
+        let q = json!({"item": {"$regexCapture": condition}});
+        assert_eq!(expected, query(q.clone(), all()), "{}", q);
+    }
+
+    // any string element of an array may match
+    let q = json!({"memos.by": {"$regexCapture": {"pattern": "^(bill|pay)", "group": 1, "equals": "pay"}}});
+    assert_eq!(vec![&*FRUIT], query(q, all()));
+
+    for (condition, compiles) in [
+        (json!({"pattern": "(\\w)", "group": 2, "equals": "x"}), true),
+        (
+            json!({"pattern": "(\\w)", "group": -1, "equals": "x"}),
+            true,
+        ),
+        (
+            json!({"pattern": "(\\w)", "group": "name", "equals": "x"}),
+            true,
+        ),
+        (json!({"pattern": "(\\w)", "equals": "x"}), true),
+        (json!({"pattern": "(\\w)", "group": 1}), true),
+        (json!({"pattern": "(\\w)", "group": 1, "equals": 1}), true),
+        (json!({"group": 1, "equals": "x"}), true),
+        (
+            json!({"pattern": "(\\w)", "group": 1, "equals": "x", "flags": "i"}),
+            true,
+        ),
+        (json!("(\\w)"), true),
+        (json!({"pattern": "(\\w", "group": 1, "equals": "x"}), false),
+    ] {
+        let querier = BaseQuerier::new(&json!({"item": {"$regexCapture": condition}}));
+        let result = querier.evaluate(Some(&FOOD));
+        if compiles {
+            assert!(querier.validate().is_ok(), "{}", condition);
+            assert!(
+                matches!(result, Err(QueryError::OperatorError { ref operator, .. }) if operator == "regexCapture"),
+                "{}: {:?}",
+                condition,
+                result
+            );
+        } else {
+            assert!(matches!(
+                querier.validate(),
+                Err(QueryError::CompileError { .. })
+            ));
+            assert!(matches!(result, Err(QueryError::CompileError { .. })));
+        }
+    }
+}
+
+#[test]
+fn test_regex() {
+    // literal patterns take the fast path, the equivalent non-literal patterns go through the regex engine
+    for (literal, regex) in [
+        ("^jk", "^j[k]"),
+        ("^jkl$", "^(?:jkl)$"),
+        ("yz$", "y(z)$"),
+        ("k", "[k]"),
+        ("^food", "^fo{2}d"),
+    ] {
+        for field in ["item", "type", "memos.memo"] {
+            assert_eq!(
+                query(json!({ field: { "$regex": regex } }), all()),
+                query(json!({ field: { "$regex": literal } }), all()),
+                "{literal} vs {regex} on {field}"
+            );
+        }
+    }
+
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"item": {"$regex": "^jk"}}), all())
+    );
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"item": {"$regex": "^xyz$"}}), all())
+    );
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"item": {"$regex": "(?i)^JKL$"}}), all())
+    );
+    assert_eq!(
+        all(),
+        query(json!({"memos.memo": {"$regex": "^on"}}), all())
+    );
+    assert_eq!(empty(), query(json!({"qty": {"$regex": "2"}}), all()));
+
+    let querier = BaseQuerier::new(&json!({"item": {"$regex": "(unclosed"}}));
+    assert!(matches!(
+        querier.evaluate(Some(&FOOD)),
+        Err(QueryError::CompileError { .. })
+    ));
+}
+
+#[test]
+fn test_elem_match() {
+    assert_eq!(
+        vec![&*FRUIT],
+        query(
+            json!({"memos": {"$elemMatch": {"memo": "on time", "by": "payment"}}}),
+            all()
+        )
+    );
+    assert_eq!(
+        empty(),
+        query(
+            json!({"memos": {"$elemMatch": {"memo": "approved", "by": "shipping"}}}),
+            all()
+        )
+    );
+    assert_eq!(
+        all(),
+        query(json!({"ratings": {"$elemMatch": {"$gt": 8}}}), all())
+    );
+
+    // `$not` inside `$elemMatch` negates the condition for a single element
+    assert_eq!(
+        all(),
+        query(
+            json!({"ratings": {"$elemMatch": {"$not": {"$eq": 5}}}}),
+            all()
+        )
+    );
+    let records = [
+        json!({"ratings": [5, 5, 5]}),
+        json!({"ratings": [5, 8, 9]}),
+        json!({"ratings": 8}),
+        json!({"ratings": []}),
+    ];
+    let records_ref: Vec<_> = records.iter().collect();
+    assert_eq!(
+        vec![records_ref[1]],
+        query(
+            json!({"ratings": {"$elemMatch": {"$not": {"$eq": 5}}}}),
+            records_ref.clone()
+        )
+    );
+
+    let querier = BaseQuerier::new(&json!({"ratings": {"$elemMatch": 5}}));
+    assert!(matches!(
+        querier.evaluate(Some(&FOOD)),
+        Err(QueryError::OperatorError { operator, .. }) if operator == "elemMatch"
+    ));
+}
+
+#[test]
+fn test_borrowed_query() {
+    use mongoquery::QueryRef;
+    use std::borrow::Cow;
+
+    let source = Value::Array((0..10_000).map(Value::from).collect());
+    let querier = BaseQuerier::new_borrowed(&source);
+    match &querier {
+        QueryRef::Sequence(Cow::Borrowed(seq)) => {
+            assert!(std::ptr::eq(
+                seq.as_ptr(),
+                source.as_array().unwrap().as_ptr()
+            ))
+        }
+        other => panic!("expected a borrowed sequence, got {:?}", other),
+    }
+    assert!(querier.evaluate(Some(&json!(42))).unwrap());
+    assert!(!querier.evaluate(Some(&json!(-1))).unwrap());
+
+    let source = json!({"item": "jkl", "qty": {"$lt": 20}, "ratings": [5, 9]});
+    let borrowed = BaseQuerier::new_borrowed(&source);
+    for doc in all() {
+        assert_eq!(
+            BaseQuerier::new(&source).evaluate(Some(doc)).unwrap(),
+            borrowed.evaluate(Some(doc)).unwrap()
+        );
+    }
+    assert_eq!(BaseQuerier::new(&source), borrowed.into_owned());
+}
+
+#[test]
+fn test_expr_regex_match() {
+    assert_eq!(
+        vec![&*FRUIT],
+        query(
+            json!({"$expr": {"$regexMatch": {"input": "$item", "regex": "^j"}}}),
+            all()
+        )
+    );
+    assert_eq!(
+        all(),
+        query(
+            json!({"$expr": {"$regexMatch": {"input": "$type", "regex": "^F", "options": "i"}}}),
+            all()
+        )
+    );
+    assert_eq!(
+        vec![&*FOOD],
+        query(
+            json!({"$expr": {"$regexMatch": {"input": "$memos.1.memo", "regex": "approved"}}}),
+            all()
+        )
+    );
+    // the regex itself can come from another field
+    let records = [
+        json!({"name": "journal", "pattern": "^jour"}),
+        json!({"name": "notebook", "pattern": "^jour"}),
+    ];
+    let records_ref: Vec<_> = records.iter().collect();
+    assert_eq!(
+        vec![records_ref[0]],
+        query(
+            json!({"$expr": {"$regexMatch": {"input": "$name", "regex": "$pattern"}}}),
+            records_ref
+        )
+    );
+    // missing inputs don't match
+    assert_eq!(
+        empty(),
+        query(
+            json!({"$expr": {"$regexMatch": {"input": "$missing", "regex": "."}}}),
+            all()
+        )
+    );
+
+    for invalid in [
+        json!({"$regexMatch": {"input": "$qty", "regex": "2"}}),
+        json!({"$regexMatch": {"input": "$item", "regex": 5}}),
+        json!({"$regexMatch": {"input": "$item", "regex": "x", "options": "q"}}),
+        json!({"$add": [1, 2]}),
+    ] {
+        let querier = BaseQuerier::new(&json!({ "$expr": invalid }));
+        assert!(matches!(
+            querier.evaluate(Some(&FOOD)),
+            Err(QueryError::OperatorError { .. })
+        ));
+    }
+}
+
+#[test]
+fn test_integer_keyed_object() {
+    let records = [
+        json!({"scores": {"1": 10, "2": 20, "-1": 30}}),
+        json!({"scores": [5, 10, 20]}),
+        json!({"scores": {"0": 10}}),
+    ];
+    let records_ref: Vec<_> = records.iter().collect();
+
+    // `scores.1` reads the "1" key of an object and index 1 of an array
+    assert_eq!(
+        vec![records_ref[0], records_ref[1]],
+        query(json!({"scores.1": 10}), records_ref.clone())
+    );
+    assert_eq!(
+        vec![records_ref[0], records_ref[1]],
+        query(json!({"scores.2": 20}), records_ref.clone())
+    );
+    // an object has no positional elements, "0" is only ever a key
+    assert_eq!(
+        vec![records_ref[1], records_ref[2]],
+        query(json!({"scores.0": {"$exists": true}}), records_ref.clone())
+    );
+    // negative segments are keys on objects, not indices from the end
+    assert_eq!(
+        vec![records_ref[0]],
+        query(json!({"scores.-1": 30}), records_ref.clone())
+    );
+    assert_eq!(
+        vec![records_ref[1]],
+        query(json!({"scores.-1": 20}), records_ref)
+    );
+}
+
+#[test]
+fn test_negate() {
+    let queries = [
+        json!({"item": "journal"}),
+        json!({"qty": {"$gt": 25}, "status": "A"}),
+        json!({"$or": [{"tags": "red"}, {"dim_cm": {"$lt": 15}}]}),
+        json!({"c": null}),
+        json!({"size.h": {"$exists": false}}),
+        json!({}),
+        json!({"$gt": 5}),
+    ];
+    for q in queries {
+        let matching = query(q.clone(), all());
+        let negated = BaseQuerier::new(&q).negate();
+        // negations round-trip through their JSON form
+        assert_eq!(
+            negated,
+            BaseQuerier::new(&negated.to_value()),
+            "negating {}",
+            q
+        );
+        let complement: Vec<_> = all()
+            .into_iter()
+            .filter(|e| negated.evaluate(Some(e)).unwrap())
+            .collect();
+        let expected: Vec<_> = all()
+            .into_iter()
+            .filter(|e| !matching.contains(e))
+            .collect();
+        assert_eq!(expected, complement, "negating {}", q);
+        assert_eq!(BaseQuerier::new(&q), negated.negate());
+    }
+}
+
+#[test]
+fn test_type() {
+    let records = [
+        json!({"v": "text"}),
+        json!({"v": 5}),
+        json!({"v": 5.5}),
+        json!({"v": 10_000_000_000i64}),
+        json!({"v": [1, "a"]}),
+        json!({"v": null}),
+        json!({"v": {"a": true}}),
+        json!({"v": true}),
+        json!({}),
+    ];
+    let r: Vec<_> = records.iter().collect();
+
+    // string alias and numeric code agree
+    assert_eq!(
+        vec![r[0], r[4]],
+        query(json!({"v": {"$type": "string"}}), r.clone())
+    );
+    assert_eq!(
+        vec![r[0], r[4]],
+        query(json!({"v": {"$type": 2}}), r.clone())
+    );
+    assert_eq!(
+        vec![r[1], r[4]],
+        query(json!({"v": {"$type": 16}}), r.clone())
+    );
+    assert_eq!(vec![r[2]], query(json!({"v": {"$type": 1}}), r.clone()));
+    assert_eq!(
+        vec![r[3]],
+        query(json!({"v": {"$type": "long"}}), r.clone())
+    );
+    assert_eq!(
+        vec![r[1], r[2], r[3], r[4]],
+        query(json!({"v": {"$type": "number"}}), r.clone())
+    );
+    assert_eq!(vec![r[4]], query(json!({"v": {"$type": 4}}), r.clone()));
+    assert_eq!(vec![r[5]], query(json!({"v": {"$type": 10}}), r.clone()));
+    assert_eq!(
+        vec![r[6]],
+        query(json!({"v": {"$type": "object"}}), r.clone())
+    );
+    assert_eq!(vec![r[7]], query(json!({"v": {"$type": 8}}), r.clone()));
+    // arrays of types, mixing codes and aliases
+    assert_eq!(
+        vec![r[0], r[2], r[4]],
+        query(json!({"v": {"$type": [2, "double"]}}), r.clone())
+    );
+    // codes JSON can't represent (ObjectId) never match
+    assert_eq!(
+        Vec::<&Value>::new(),
+        query(json!({"v": {"$type": 7}}), r.clone())
+    );
+
+    for invalid in [json!("objectId"), json!(2.5), json!(true)] {
+        let querier = BaseQuerier::new(&json!({"v": {"$type": invalid}}));
+        assert!(matches!(
+            querier.evaluate(Some(r[0])),
+            Err(QueryError::OperatorError { .. })
+        ));
+    }
+}
+
+#[test]
+fn test_type_in() {
+    let records = [
+        json!({"v": "text"}),
+        json!({"v": 5}),
+        json!({"v": 5.5}),
+        json!({"v": [true, {"a": 1}]}),
+        json!({"v": [null, "a"]}),
+        json!({"v": [[1, 2]]}),
+        json!({"v": null}),
+        json!({"v": {"a": true}}),
+        json!({}),
+    ];
+    let r: Vec<_> = records.iter().collect();
+
+    assert_eq!(
+        vec![r[0], r[1], r[2], r[4]],
+        query(json!({"v": {"$typeIn": ["string", "number"]}}), r.clone())
+    );
+    assert_eq!(
+        vec![r[3], r[4], r[5], r[6]],
+        query(json!({"v": {"$typeIn": ["bool", 10, "array"]}}), r.clone())
+    );
+    // arrays match as a whole or by any element, but nested arrays aren't searched
+    assert_eq!(
+        vec![r[3], r[7]],
+        query(json!({"v": {"$typeIn": ["object"]}}), r.clone())
+    );
+    assert_eq!(
+        vec![r[1]],
+        query(json!({"v": {"$typeIn": ["int"]}}), r.clone())
+    );
+    assert_eq!(
+        Vec::<&Value>::new(),
+        query(json!({"v": {"$typeIn": []}}), r.clone())
+    );
+    // the same as `$type` with a list
+    for types in [json!(["string", "number"]), json!([1, "null", "object"])] {
+        assert_eq!(
+            query(json!({"v": {"$type": types}}), r.clone()),
+            query(json!({"v": {"$typeIn": types}}), r.clone())
+        );
+    }
+
+    for invalid in [json!("string"), json!(["objectId"]), json!([2.5])] {
+        let querier = BaseQuerier::new(&json!({"v": {"$typeIn": invalid}}));
+        assert!(
+            matches!(
+                querier.evaluate(Some(r[0])),
+                Err(QueryError::OperatorError { operator, .. }) if operator == "typeIn"
+            ),
+            "{}",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn test_evaluate_with_index() {
+    let documents: Vec<Value> = all().into_iter().cloned().collect();
+    let queries = [
+        json!({"item": "journal"}),
+        json!({"status": "A", "qty": {"$lt": 30}}),
+        json!({"tags": "red"}),
+        json!({"qty": 25}),
+        json!({"qty": 25.0}),
+        json!({"size.uom": "in"}),
+        json!({"item": "missing"}),
+        json!({"qty": {"$gt": 50}}),
+        json!({"c": null}),
+        json!({"$or": [{"item": "journal"}, {"item": "paper"}]}),
+    ];
+    for field in ["item", "status", "tags", "qty", "size.uom"] {
+        let index = Index::new(field, &documents);
+        for q in &queries {
+            let querier = BaseQuerier::new(q);
+            assert_eq!(
+                query(q.clone(), all()),
+                querier.evaluate_with_index(&index).unwrap(),
+                "query {} with index on {}",
+                q,
+                field
+            );
+        }
+    }
+}
+
+#[test]
+fn test_nested_array_paths() {
+    let records = [
+        json!({"a": [{"b": {"c": [1, 2]}}, {"b": {"c": [3]}}]}),
+        json!({"a": [{"b": {"c": [4]}}, {"b": {"c": 5}}]}),
+        json!({"a": [{"b": {"c": [6]}}, {"b": {}}]}),
+    ];
+    let r: Vec<_> = records.iter().collect();
+
+    // each array traversed by a key adds one level of nesting
+    assert_eq!(
+        vec![r[0]],
+        query(json!({"a.b.c": [[1, 2], [3]]}), r.clone())
+    );
+    assert_eq!(vec![r[1]], query(json!({"a.b.c": [[4], 5]}), r.clone()));
+    // elements missing the path are skipped, and it's missing only if missing from all of them
+    assert_eq!(vec![r[2]], query(json!({"a.b.c": [[6]]}), r.clone()));
+    assert_eq!(
+        empty(),
+        query(json!({"a.b.c": {"$exists": false}}), r.clone())
+    );
+    assert_eq!(
+        vec![r[2]],
+        query(json!({"a.x.c": {"$exists": false}}), vec![r[2]])
+    );
+    let memos = json!({"memos": [{"by": "shipping"}, {"memo": "x"}]});
+    assert_eq!(
+        vec![&memos],
+        query(json!({"memos.by": "shipping"}), vec![&memos])
+    );
+    assert_eq!(
+        vec![&memos],
+        query(json!({"memos.by": ["shipping"]}), vec![&memos])
+    );
+    assert_eq!(
+        vec![&memos],
+        query(json!({"memos.by": {"$exists": true}}), vec![&memos])
+    );
+
+    // operators match any leaf of the nested arrays
+    assert_eq!(vec![r[0]], query(json!({"a.b.c": {"$in": [3]}}), r.clone()));
+    assert_eq!(
+        vec![r[1]],
+        query(json!({"a.b.c": {"$in": [5, 7]}}), r.clone())
+    );
+    assert_eq!(vec![r[0]], query(json!({"a.b.c": 2}), r.clone()));
+    assert_eq!(vec![r[1]], query(json!({"a.b.c": 4}), r.clone()));
+    assert_eq!(
+        vec![r[1], r[2]],
+        query(json!({"a.b.c": {"$gt": 4}}), r.clone())
+    );
+    assert_eq!(vec![r[0]], query(json!({"a.b.c": {"$lte": 1}}), r.clone()));
+    assert_eq!(
+        vec![r[1], r[2]],
+        query(json!({"a.b.c": {"$nin": [1, 2, 3]}}), r.clone())
+    );
+    // nested arrays themselves can be matched with $in too
+    assert_eq!(vec![r[0]], query(json!({"a.b.c": {"$in": [[1, 2]]}}), r));
+}
+
+#[test]
+fn test_field_eq() {
+    let records = [
+        json!({"a": 1, "b": 1}),
+        json!({"a": 1, "b": 2}),
+        json!({"a": "x", "nested": {"b": "x"}}),
+        json!({"a": [1, 2], "b": [1, 2]}),
+        json!({"a": null}),
+        json!({"b": 1}),
+    ];
+    let r: Vec<_> = records.iter().collect();
+
+    assert_eq!(
+        vec![r[0], r[3]],
+        query(json!({"a": {"$fieldEq": "b"}}), r.clone())
+    );
+    assert_eq!(
+        vec![r[2]],
+        query(json!({"a": {"$fieldEq": "nested.b"}}), r.clone())
+    );
+    // missing fields never match, not even each other or null
+    assert_eq!(
+        Vec::<&Value>::new(),
+        query(json!({"a": {"$fieldEq": "missing"}}), r.clone())
+    );
+    assert_eq!(
+        vec![r[1], r[2], r[4], r[5]],
+        query(json!({"a": {"$not": {"$fieldEq": "b"}}}), r.clone())
+    );
+
+    let querier = BaseQuerier::new(&json!({"a": {"$fieldEq": 1}}));
+    assert!(matches!(
+        querier.evaluate(Some(r[0])),
+        Err(QueryError::OperatorError { .. })
+    ));
+}
+
+#[test]
+fn test_strict_comparison() {
+    let strict = EvaluationOptions {
+        strict_comparison: true,
+        ..Default::default()
+    };
+    let record = json!({"s": "text", "n": 5, "mixed": ["a", 10]});
+
+    // lenient by default
+    let querier = BaseQuerier::new(&json!({"s": {"$gt": 1}}));
+    assert!(!querier.evaluate(Some(&record)).unwrap());
+    assert!(!querier
+        .evaluate_with_options(Some(&record), &EvaluationOptions::default())
+        .unwrap());
+
+    for op in ["$gt", "$gte", "$lt", "$lte"] {
+        let querier = BaseQuerier::new(&json!({"s": {op: 1}}));
+        match querier.evaluate_with_options(Some(&record), &strict) {
+            Err(QueryError::OperatorError { operator, reason }) => {
+                assert_eq!(op[1..], operator);
+                assert_eq!("cannot compare \"text\" with 1", reason);
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    // comparable values and missing fields are unaffected
+    let querier = BaseQuerier::new(&json!({"n": {"$gt": 1}}));
+    assert!(querier
+        .evaluate_with_options(Some(&record), &strict)
+        .unwrap());
+    let querier = BaseQuerier::new(&json!({"missing": {"$gt": 1}}));
+    assert!(!querier
+        .evaluate_with_options(Some(&record), &strict)
+        .unwrap());
+    // an array matches through its comparable leaves, and fails when none of them matches
+    let querier = BaseQuerier::new(&json!({"mixed": {"$gt": 5}}));
+    assert!(querier
+        .evaluate_with_options(Some(&record), &strict)
+        .unwrap());
+    let querier = BaseQuerier::new(&json!({"mixed": {"$gt": 50}}));
+    assert!(querier
+        .evaluate_with_options(Some(&record), &strict)
+        .is_err());
+}
+
+#[test]
+fn test_set_eq() {
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"ratings": {"$setEq": [9, 5, 8]}}), all())
+    );
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"ratings": {"$setEq": [8, 8, 9, 5, 5]}}), all())
+    );
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"ratings": {"$setEq": [9, 5]}}), all())
+    );
+    // neither subsets nor supersets match
+    assert_eq!(empty(), query(json!({"ratings": {"$setEq": [5]}}), all()));
+    assert_eq!(
+        empty(),
+        query(json!({"ratings": {"$setEq": [5, 8, 9, 10]}}), all())
+    );
+    // non-arrays never match
+    assert_eq!(empty(), query(json!({"qty": {"$setEq": [25]}}), all()));
+    assert_eq!(empty(), query(json!({"missing": {"$setEq": []}}), all()));
+
+    let querier = BaseQuerier::new(&json!({"ratings": {"$setEq": 5}}));
+    assert!(matches!(
+        querier.evaluate(Some(&FOOD)),
+        Err(QueryError::OperatorError { .. })
+    ));
+}
+
+#[test]
+fn test_check_schema() {
+    use mongoquery::{JsonType, SchemaWarning};
+
+    let schema: HashMap<String, JsonType> = [
+        ("item", JsonType::String),
+        ("qty", JsonType::Number),
+        ("sale", JsonType::Bool),
+        ("size", JsonType::Object),
+        ("size.h", JsonType::Number),
+        ("tags", JsonType::Array),
+    ]
+    .into_iter()
+    .map(|(field, json_type)| (field.to_string(), json_type))
+    .collect();
+    let warning = |field: &str, operator: Option<&str>, expected, value| SchemaWarning {
+        field: field.to_string(),
+        operator: operator.map(str::to_string),
+        expected,
+        value,
+    };
+
+    // well-typed queries, and fields the schema doesn't cover
+    for q in [
+        json!({"item": "xyz", "qty": {"$gt": 20, "$in": [1, 2.5]}, "sale": true}),
+        json!({"size": {"h": 14}, "size.h": {"$lte": 20}}),
+        json!({"qty": null, "item": {"$ne": null}}),
+        json!({"tags": "red", "tags.0": 5, "other": {"$gt": "x"}}),
+        json!({"size": {"$eq": {"h": 14}}}),
+        json!({"item": {"$regex": "^x", "$exists": true, "$strlen": 3}}),
+        json!({"$**": {"$gt": "x"}, "memos": {"$elemMatch": {"qty": "x"}}}),
+    ] {
+        assert_eq!(
+            Vec::<SchemaWarning>::new(),
+            BaseQuerier::new(&q).check_schema(&schema),
+            "{}",
+            q
+        );
+    }
+
+    let q = json!({
+        "qty": {"$gt": "5", "$nin": [1, "2"]},
+        "item": 5,
+        "$or": [{"sale": "yes"}, {"size": {"h": {"$not": {"$lt": "10"}}}}]
+    });
+    assert_eq!(
+        vec![
+            warning("sale", None, JsonType::Bool, json!("yes")),
+            warning("size.h", Some("$lt"), JsonType::Number, json!("10")),
+            warning("item", None, JsonType::String, json!(5)),
+            warning("qty", Some("$gt"), JsonType::Number, json!("5")),
+            warning("qty", Some("$nin"), JsonType::Number, json!("2")),
+        ],
+        BaseQuerier::new(&q).check_schema(&schema)
+    );
+
+    let warnings =
+        BaseQuerier::new(&json!({"size": 3, "qty": {"$eq": true}})).check_schema(&schema);
+    assert_eq!(
+        vec![
+            "field `qty` is of type number, but `$eq` compares it to `true`",
+            "field `size` is of type object, but is compared to `3`",
+        ],
+        warnings.iter().map(ToString::to_string).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_complexity() {
+    let complexity = |q: Value| BaseQuerier::new(&q).complexity();
+
+    let equality = complexity(json!({"item": "xyz"}));
+    let two_fields = complexity(json!({"item": "xyz", "qty": 25}));
+    let small_in = complexity(json!({"qty": {"$in": [10, 25]}}));
+    let large_in = complexity(json!({"qty": {"$in": [10, 25, 30, 40, 50, 60]}}));
+    let or_in = complexity(json!({"$or": [{"qty": {"$in": [10, 25]}}, {"item": "xyz"}]}));
+    let nested_or_in = complexity(json!({"$or": [
+        {"$or": [{"qty": {"$in": [10, 25]}}, {"item": "xyz"}]},
+        {"type": "food"},
+    ]}));
+    let regex = complexity(json!({"item": {"$regex": "^x"}}));
+
+    assert_eq!(1, equality);
+    assert!(equality < two_fields);
+    assert!(equality < small_in);
+    assert!(small_in < large_in);
+    assert!(small_in < or_in);
+    assert!(or_in < nested_or_in);
+    assert!(two_fields < regex);
+    assert_eq!(0, complexity(json!({})));
+}
+
+#[test]
+fn test_case_insensitive_fields() {
+    let insensitive = EvaluationOptions {
+        case_insensitive_fields: true,
+        ..Default::default()
+    };
+    let record =
+        json!({"qty": 25, "Size": {"UOM": "cm"}, "tags": [{"Color": "red"}], "a": 1, "A": 2});
+
+    for (q, matches) in [
+        (json!({"Qty": 25}), true),
+        (json!({"QTY": {"$gt": 20}}), true),
+        (json!({"size.uom": "cm"}), true),
+        (json!({"TAGS.color": "red"}), true),
+        (json!({"qty": 25}), true),
+        // exact matches take precedence
+        (json!({"a": 1}), true),
+        (json!({"A": 2}), true),
+        (json!({"a": 2}), false),
+        (json!({"Qty": 10}), false),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(
+            matches,
+            querier
+                .evaluate_with_options(Some(&record), &insensitive)
+                .unwrap(),
+            "{}",
+            q
+        );
+    }
+
+    // case-sensitive by default
+    let querier = BaseQuerier::new(&json!({"Qty": 25}));
+    assert!(!querier.evaluate(Some(&record)).unwrap());
+    assert!(querier
+        .evaluate_with_options(Some(&record), &insensitive)
+        .unwrap());
+}
+
+#[test]
+fn test_literal_values() {
+    let querier = BaseQuerier::new(&json!({
+        "$or": [{"qty": {"$gt": 20, "$lte": 30}}, {"tags": ["red", "blank"]}],
+        "item": "xyz",
+        "memos": {"$elemMatch": {"by": "shipping", "memo": {"$in": ["on time", "late"]}}},
+        "price": null,
+        "sale": false,
+        "status": {"$not": {"$eq": "D"}},
+    }));
+    assert_eq!(
+        vec![
+            json!(20),
+            json!(30),
+            json!(["red", "blank"]),
+            json!("xyz"),
+            json!("shipping"),
+            json!(["on time", "late"]),
+            json!(null),
+            json!(false),
+            json!("D"),
+        ],
+        querier.literal_values()
+    );
+    assert!(BaseQuerier::new(&json!({})).literal_values().is_empty());
+}
+
+#[test]
+fn test_all() {
+    assert_eq!(all(), query(json!({"ratings": {"$all": [9, 5]}}), all()));
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"ratings": {"$all": [5, 8]}}), all())
+    );
+    assert_eq!(vec![&*FOOD], query(json!({"qty": {"$all": [25]}}), all()));
+    assert_eq!(empty(), query(json!({"ratings": {"$all": []}}), all()));
+
+    assert_eq!(
+        vec![&*FOOD],
+        query(
+            json!({"memos": {"$all": [
+                {"$elemMatch": {"by": "shipping"}},
+                {"$elemMatch": {"memo": "approved"}},
+            ]}}),
+            all()
+        )
+    );
+    assert_eq!(
+        all(),
+        query(
+            json!({"memos": {"$all": [
+                {"$elemMatch": {"memo": "on time"}},
+                {"$elemMatch": {"by": {"$in": ["shipping", "payment"]}}},
+            ]}}),
+            all()
+        )
+    );
+    // each $elemMatch may be satisfied by a different element, but one element must satisfy it entirely
+    assert_eq!(
+        empty(),
+        query(
+            json!({"memos": {"$all": [{"$elemMatch": {"memo": "approved", "by": "shipping"}}]}}),
+            all()
+        )
+    );
+    assert_eq!(
+        vec![&*FOOD],
+        query(
+            json!({"ratings": {"$all": [{"$elemMatch": {"$gt": 7, "$lt": 9}}]}}),
+            all()
+        )
+    );
+
+    for invalid in [
+        json!({"memos": {"$all": [{"$elemMatch": {"by": "shipping"}}, "on time"]}}),
+        json!({"memos": {"$all": {"$elemMatch": {"by": "shipping"}}}}),
+        json!({"memos": {"$all": [{"$elemMatch": 5}]}}),
+    ] {
+        let querier = BaseQuerier::new(&invalid);
+        assert!(matches!(
+            querier.evaluate(Some(&FOOD)),
+            Err(QueryError::OperatorError { .. })
+        ));
+    }
+}
+
+#[test]
+fn test_size() {
+    assert_eq!(vec![&*FOOD], query(json!({"ratings": {"$size": 3}}), all()));
+    assert_eq!(all(), query(json!({"memos": {"$size": 2}}), all()));
+    assert_eq!(empty(), query(json!({"qty": {"$size": 1}}), all()));
+    let querier = BaseQuerier::new(&json!({"ratings": {"$size": -1}}));
+    assert!(matches!(
+        querier.evaluate(Some(&FOOD)),
+        Err(QueryError::OperatorError { .. })
+    ));
+}
+
+#[test]
+fn test_count_where() {
+    // FOOD's ratings are [5, 8, 9], FRUIT's [5, 9]
+    for (condition, expected) in [
+        (
+            json!({"predicate": {"$gte": 8}, "count": {"$gte": 2}}),
+            vec![&*FOOD],
+        ),
+        (json!({"predicate": {"$gte": 8}, "count": 1}), vec![&*FRUIT]),
+        (
+            json!({"predicate": {"$gte": 8}, "count": {"$gte": 1}}),
+            all(),
+        ),
+        (json!({"predicate": {"$gt": 9}, "count": 0}), all()),
+        (json!({"predicate": 5, "count": 1}), all()),
+        (
+            json!({"predicate": {"$in": [5, 8]}, "count": {"$lt": 2}}),
+            vec![&*FRUIT],
+        ),
+        (
+            json!({"predicate": {"$not": {"$eq": 5}}, "count": {"$in": [2, 3]}}),
+            vec![&*FOOD],
+        ),
+    ] {
+        let q = json!({"ratings": {"$countWhere": condition}});
+        assert_eq!(expected, query(q.clone(), all()), "{}", q);
+    }
+
+    // predicates on documents, and only arrays match
+    let q = json!({"memos": {"$countWhere": {"predicate": {"memo": "on time"}, "count": 1}}});
+    assert_eq!(all(), query(q, all()));
+    let q = json!({"qty": {"$countWhere": {"predicate": {"$gt": 0}, "count": {"$gte": 0}}}});
+    assert_eq!(empty(), query(q, all()));
+
+    for invalid in [
+        json!([5]),
+        json!({"predicate": {"$gte": 8}}),
+        json!({"count": 1}),
+        json!({"predicate": {"$gte": 8}, "count": -1}),
+        json!({"predicate": {"$gte": 8}, "count": {"min": 1}}),
+        json!({"predicate": {"$gte": 8}, "count": 1, "limit": 2}),
+    ] {
+        let querier = BaseQuerier::new(&json!({"ratings": {"$countWhere": invalid}}));
+        assert!(
+            matches!(
+                querier.evaluate(Some(&FOOD)),
+                Err(QueryError::OperatorError { operator, .. }) if operator == "countWhere"
+            ),
+            "{}",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn test_at_index() {
+    // FOOD's ratings are [5, 8, 9], FRUIT's [5, 9]
+    for (condition, expected) in [
+        (json!({"index": 0, "match": {"$eq": 5}}), all()),
+        (json!({"index": 1, "match": {"$eq": 8}}), vec![&*FOOD]),
+        (json!({"index": 1, "match": 9}), vec![&*FRUIT]),
+        (json!({"index": 2, "match": {"$gt": 5}}), vec![&*FOOD]),
+        (json!({"index": 3, "match": {"$exists": true}}), empty()),
+        (json!({"index": -1, "match": 9}), all()),
+        (json!({"index": -2, "match": {"$lt": 9}}), all()),
+        (json!({"index": -3, "match": 5}), vec![&*FOOD]),
+        (json!({"index": -4, "match": {"$exists": true}}), empty()),
+        (json!({"index": 0, "match": {"$in": [1, 2]}}), empty()),
+    ] {
+        let q = json!({"ratings": {"$atIndex": condition}});
+        assert_eq!(expected, query(q.clone(), all()), "{}", q);
+    }
+
+    // the element may be a document, or an array itself
+    let record = json!({"memos": FOOD["memos"], "matrix": [[1, 2], [3]], "qty": 25});
+    for (q, matches) in [
+        (
+            json!({"memos": {"$atIndex": {"index": 1, "match": {"by": "billing"}}}}),
+            true,
+        ),
+        (
+            json!({"memos": {"$atIndex": {"index": 0, "match": {"by": "billing"}}}}),
+            false,
+        ),
+        (
+            json!({"matrix": {"$atIndex": {"index": 0, "match": {"$size": 2}}}}),
+            true,
+        ),
+        (
+            json!({"matrix": {"$atIndex": {"index": 1, "match": 3}}}),
+            true,
+        ),
+        (
+            json!({"qty": {"$atIndex": {"index": 0, "match": 25}}}),
+            false,
+        ),
+        (
+            json!({"missing": {"$atIndex": {"index": 0, "match": 25}}}),
+            false,
+        ),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(matches, querier.evaluate(Some(&record)).unwrap(), "{}", q);
+    }
+
+    for invalid in [
+        json!(0),
+        json!({"match": 5}),
+        json!({"index": 0}),
+        json!({"index": "0", "match": 5}),
+        json!({"index": 0.5, "match": 5}),
+        json!({"index": 0, "match": 5, "default": false}),
+    ] {
+        let querier = BaseQuerier::new(&json!({"ratings": {"$atIndex": invalid}}));
+        assert!(
+            matches!(
+                querier.evaluate(Some(&FOOD)),
+                Err(QueryError::OperatorError { operator, .. }) if operator == "atIndex"
+            ),
+            "{}",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn test_count_in() {
+    // FOOD's ratings are [5, 8, 9], FRUIT's [5, 9]
+    for (condition, expected) in [
+        (json!({"values": [5, 9], "min": 2}), all()),
+        (json!({"values": [5, 8], "min": 2}), vec![&*FOOD]),
+        (json!({"values": [5, 8, 9], "min": 3}), vec![&*FOOD]),
+        (json!({"values": [8], "min": 1}), vec![&*FOOD]),
+        (json!({"values": [1, 2], "min": 1}), empty()),
+        (json!({"values": [1, 2], "min": 0}), all()),
+        (json!({"values": [], "min": 0}), all()),
+        (json!({"values": [5, 8, 9], "min": 4}), empty()),
+    ] {
+        let q = json!({"ratings": {"$countIn": condition}});
+        assert_eq!(expected, query(q.clone(), all()), "{}", q);
+    }
+
+    // each element counts, duplicates included, but only arrays match
+    let record = json!({"ratings": [5, 5, [5]], "qty": 5});
+    for (q, matches) in [
+        (
+            json!({"ratings": {"$countIn": {"values": [5], "min": 2}}}),
+            true,
+        ),
+        (
+            json!({"ratings": {"$countIn": {"values": [5], "min": 3}}}),
+            false,
+        ),
+        (
+            json!({"ratings": {"$countIn": {"values": [5, [5]], "min": 3}}}),
+            true,
+        ),
+        (
+            json!({"qty": {"$countIn": {"values": [5], "min": 1}}}),
+            false,
+        ),
+        (
+            json!({"qty": {"$countIn": {"values": [5], "min": 0}}}),
+            false,
+        ),
+        (
+            json!({"missing": {"$countIn": {"values": [5], "min": 0}}}),
+            false,
+        ),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(matches, querier.evaluate(Some(&record)).unwrap(), "{}", q);
+    }
+
+    for invalid in [
+        json!([5, 9]),
+        json!({"values": [5, 9]}),
+        json!({"min": 2}),
+        json!({"values": 5, "min": 1}),
+        json!({"values": [5], "min": -1}),
+        json!({"values": [5], "min": 1.5}),
+        json!({"values": [5], "min": 1, "max": 2}),
+    ] {
+        let querier = BaseQuerier::new(&json!({"ratings": {"$countIn": invalid}}));
+        assert!(
+            matches!(
+                querier.evaluate(Some(&FOOD)),
+                Err(QueryError::OperatorError { operator, .. }) if operator == "countIn"
+            ),
+            "{}",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn test_num_keys() {
+    // FOOD and FRUIT have 7 top-level keys each
+    let small = json!({"_id": 102, "item": "abc"});
+    let collection = || vec![&*FOOD, &*FRUIT, &small];
+    for (q, expected) in [
+        (json!({"$numKeys": {"$gte": 5}}), vec![&*FOOD, &*FRUIT]),
+        (json!({"$numKeys": {"$lt": 5}}), vec![&small]),
+        (json!({"$numKeys": 7}), vec![&*FOOD, &*FRUIT]),
+        (json!({"$numKeys": {"$in": [2, 3]}}), vec![&small]),
+        (json!({"$numKeys": {"$gt": 2, "$lt": 7}}), vec![]),
+        (
+            json!({"$numKeys": {"$not": {"$eq": 2}}}),
+            vec![&*FOOD, &*FRUIT],
+        ),
+        // on fields too, and only documents have keys
+        (json!({"memos.0": {"$numKeys": 2}}), vec![&*FOOD, &*FRUIT]),
+        (json!({"memos": {"$numKeys": {"$gte": 0}}}), vec![]),
+        (json!({"qty": {"$numKeys": {"$gte": 0}}}), vec![]),
+        (json!({"missing": {"$numKeys": 0}}), vec![]),
+    ] {
+        assert_eq!(expected, query(q.clone(), collection()), "{}", q);
+    }
+    assert!(BaseQuerier::new(&json!({"$numKeys": 0}))
+        .evaluate(Some(&json!({})))
+        .unwrap());
+
+    for invalid in [json!(-1), json!("5"), json!({"a": 1}), json!([5])] {
+        let querier = BaseQuerier::new(&json!({ "$numKeys": invalid }));
+        assert!(
+            matches!(
+                querier.evaluate(Some(&FOOD)),
+                Err(QueryError::OperatorError { operator, .. }) if operator == "numKeys"
+            ),
+            "{}",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn test_has_keys() {
+    let small = json!({"_id": 102, "item": "abc", "tags": []});
+    let collection = || vec![&*FOOD, &*FRUIT, &small];
+    for (q, expected) in [
+        (json!({"$hasKeys": ["qty", "price"]}), vec![&*FOOD, &*FRUIT]),
+        (json!({"$hasKeys": ["qty", "tags"]}), vec![]),
+        (
+            json!({"$hasKeys": ["_id", "item"]}),
+            vec![&*FOOD, &*FRUIT, &small],
+        ),
+        (json!({"$hasKeys": []}), vec![&*FOOD, &*FRUIT, &small]),
+        (
+            json!({"$hasAnyKey": ["qty", "tags"]}),
+            vec![&*FOOD, &*FRUIT, &small],
+        ),
+        (
+            json!({"$hasAnyKey": ["price", "missing"]}),
+            vec![&*FOOD, &*FRUIT],
+        ),
+        (json!({"$hasAnyKey": ["missing"]}), vec![]),
+        (json!({"$hasAnyKey": []}), vec![]),
+        // keys are top-level only, and only documents have keys
+        (json!({"$hasKeys": ["memos.by"]}), vec![]),
+        (
+            json!({"memos.0": {"$hasKeys": ["memo", "by"]}}),
+            vec![&*FOOD, &*FRUIT],
+        ),
+        (json!({"memos": {"$hasAnyKey": ["memo"]}}), vec![]),
+        (json!({"qty": {"$hasAnyKey": ["qty"]}}), vec![]),
+    ] {
+        assert_eq!(expected, query(q.clone(), collection()), "{}", q);
+    }
+
+    for operator in ["$hasKeys", "$hasAnyKey"] {
+        for invalid in [json!("qty"), json!(["qty", 1]), json!({"qty": true})] {
+            let querier = BaseQuerier::new(&json!({ operator: invalid }));
+            assert!(
+                matches!(
+                    querier.evaluate(Some(&FOOD)),
+                    Err(QueryError::OperatorError { operator: op, .. }) if op == operator[1..]
+                ),
+                "{} {}",
+                operator,
+                invalid
+            );
+        }
+    }
+}
+
+#[test]
+fn test_array_depth() {
+    let records = [
+        json!({"nested": 1}),
+        json!({"nested": []}),
+        json!({"nested": [1, 2]}),
+        json!({"nested": [[1], 2]}),
+        json!({"nested": [[], [[3]]]}),
+        json!({"nested": [{"a": [[1]]}]}),
+        json!({"nested": {"a": [1]}}),
+        json!({"nested": null}),
+        json!({}),
+    ];
+    let r: Vec<_> = records.iter().collect();
+    for (q, expected) in [
+        (
+            json!({"nested": {"$arrayDepth": 0}}),
+            vec![r[0], r[6], r[7]],
+        ),
+        (
+            json!({"nested": {"$arrayDepth": 1}}),
+            vec![r[1], r[2], r[5]],
+        ),
+        (
+            json!({"nested": {"$arrayDepth": {"$gte": 2}}}),
+            vec![r[3], r[4]],
+        ),
+        (json!({"nested": {"$arrayDepth": {"$eq": 3}}}), vec![r[4]]),
+        (
+            json!({"nested": {"$arrayDepth": {"$in": [1, 2]}}}),
+            vec![r[1], r[2], r[3], r[5]],
+        ),
+        // arrays within documents count on their own path, which may descend into arrays in parallel,
+        // while an empty array has no element to hold the path
+        (json!({"nested.a": {"$arrayDepth": 1}}), vec![r[6]]),
+        (json!({"nested.a": {"$arrayDepth": 3}}), vec![r[5]]),
+        (json!({"nested.0.a": {"$arrayDepth": 2}}), vec![r[5]]),
+    ] {
+        assert_eq!(expected, query(q.clone(), r.clone()), "{}", q);
+    }
+
+    for invalid in [json!(-1), json!(1.5), json!({"min": 1}), json!([2])] {
+        let querier = BaseQuerier::new(&json!({"nested": {"$arrayDepth": invalid}}));
+        assert!(
+            matches!(
+                querier.evaluate(Some(r[2])),
+                Err(QueryError::OperatorError { operator, .. }) if operator == "arrayDepth"
+            ),
+            "{}",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn test_size_with_all() {
+    // operators on the same array field must all hold: FOOD's ratings are [5, 8, 9], FRUIT's [5, 9]
+    for (field, operators, expected) in [
+        ("ratings", json!({"$size": 3, "$all": [5, 9]}), vec![&*FOOD]),
+        (
+            "ratings",
+            json!({"$size": 2, "$all": [5, 9]}),
+            vec![&*FRUIT],
+        ),
+        ("ratings", json!({"$size": 3, "$all": [5, 7]}), empty()),
+        ("ratings", json!({"$size": 4, "$all": [5, 9]}), empty()),
+        ("ratings", json!({"$all": [8], "$size": 3}), vec![&*FOOD]),
+        (
+            "ratings",
+            json!({"$size": 3, "$all": [5], "$gt": 8}),
+            vec![&*FOOD],
+        ),
+        (
+            "ratings",
+            json!({"$size": 2, "$all": [5], "$lt": 5}),
+            empty(),
+        ),
+        (
+            "ratings",
+            json!({"$not": {"$size": 3}, "$all": [5, 9]}),
+            vec![&*FRUIT],
+        ),
+        (
+            "memos",
+            json!({"$size": 2, "$all": [{"$elemMatch": {"by": "billing"}}]}),
+            vec![&*FOOD],
+        ),
+    ] {
+        let q = json!({ field: operators });
+        assert_eq!(expected, query(q.clone(), all()), "{}", q);
+
+        // which is the same as and-ing them
+        let and: Vec<_> = operators
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(operator, condition)| json!({ field: { operator: condition } }))
+            .collect();
+        assert_eq!(expected, query(json!({ "$and": and }), all()), "{}", q);
+    }
+}
+
+#[test]
+fn test_is_trivially_false() {
+    let impossible = [
+        json!({"item": "xyz", "memos": {"$exists": false, "$size": 2}}),
+        json!({"$and": [{"qty": 25}, {"tags": {"$exists": false, "$type": "string"}}]}),
+        json!({"$or": []}),
+        json!({"$or": [{"a": {"$exists": false, "$eq": 1}}, {"$or": []}]}),
+        json!({"memos": {"$elemMatch": {"by": {"$exists": false, "$all": ["x"]}}}}),
+    ];
+    for q in impossible {
+        let querier = BaseQuerier::new(&q);
+        assert!(querier.is_trivially_false(), "{}", q);
+        assert_eq!(empty(), query(q.clone(), all()));
+        let simplified = querier.simplify();
+        assert_eq!(BaseQuerier::new(&json!({"$or": []})), simplified);
+        assert!(!simplified.evaluate(Some(&FOOD)).unwrap());
+    }
+
+    // comparisons fail on incomparable values under strict comparison, so the query is kept
+    let q = json!({"qty": {"$exists": false, "$gt": 5}});
+    let querier = BaseQuerier::new(&q);
+    assert!(querier.is_trivially_false());
+    assert_eq!(empty(), query(q.clone(), all()));
+    assert_eq!(querier.clone(), querier.simplify());
+
+    let possible = [
+        json!({"tags": {"$size": 2, "$eq": ["red", "blank"]}}),
+        // scalar equality matches array elements
+        json!({"tags": {"$size": 2, "$eq": "red"}}),
+        json!({"qty": {"$exists": true, "$gt": 5}}),
+        json!({"qty": {"$exists": false, "$eq": null}}),
+        json!({"$or": [{"a": {"$exists": false, "$eq": 1}}, {"qty": 25}]}),
+        json!({"qty": {"$not": {"$exists": false, "$gt": 5}}}),
+        json!({"tags": {"$type": "string", "$size": 2}}),
+    ];
+    for q in possible {
+        assert!(!BaseQuerier::new(&q).is_trivially_false(), "{}", q);
+    }
+}
+
+#[test]
+fn test_simplify_keeps_errors() {
+    let strict = EvaluationOptions {
+        strict_comparison: true,
+        ..Default::default()
+    };
+    let records = [
+        json!({"qty": "many", "memos": []}),
+        json!({"memos": [1, 2]}),
+        json!({}),
+    ];
+    for q in [
+        json!({"qty": {"$exists": false, "$gt": 5}}),
+        json!({"memos": {"$exists": false, "$size": 2}, "item": {"$bogus": 1}}),
+        json!({"memos": {"$exists": false, "$size": "two"}}),
+        json!({"memos": {"$exists": false, "$size": 2}, "qty": {"$in": 5}}),
+        json!({"memos": {"$exists": false, "$size": 2}, "qty": {"$type": "text"}}),
+        json!({"memos": {"$exists": false, "$size": 2}, "qty": {"$not": 5}}),
+        json!({"$or": [{"memos": {"$exists": false, "$size": 2}}, {"qty": {"$lt": 5}}]}),
+        json!({"memos": {"$exists": false, "$size": 2}, "qty": {"$type": ["int", 1]}}),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        let simplified = querier.clone().simplify();
+        for record in &records {
+            for options in [&EvaluationOptions::default(), &strict] {
+                assert_eq!(
+                    format!("{:?}", querier.evaluate_with_options(Some(record), options)),
+                    format!(
+                        "{:?}",
+                        simplified.evaluate_with_options(Some(record), options)
+                    ),
+                    "{} on {}",
+                    q,
+                    record
+                );
+            }
+        }
+    }
+}
+
+#[cfg(feature = "jsonpath")]
+#[test]
+fn test_json_path() {
+    for (json_path, dotted) in [
+        (
+            json!({"$.memos[*].by": "shipping"}),
+            json!({"memos.by": "shipping"}),
+        ),
+        (
+            json!({"$.memos[1].memo": "approved"}),
+            json!({"memos.1.memo": "approved"}),
+        ),
+        (
+            json!({"$.ratings[*]": {"$gt": 8}}),
+            json!({"ratings": {"$gt": 8}}),
+        ),
+        (json!({"$['type']": "fruit"}), json!({"type": "fruit"})),
+        (
+            json!({"$.missing": {"$exists": false}}),
+            json!({"missing": {"$exists": false}}),
+        ),
+    ] {
+        assert_eq!(query(dotted, all()), query(json_path, all()));
+    }
+    // filters go beyond what dotted paths can express
+    assert_eq!(
+        vec![&*FRUIT],
+        query(
+            json!({"$.memos[?@.by == 'shipping'].memo": "delayed"}),
+            all()
+        )
+    );
+
+    let querier = BaseQuerier::new(&json!({"$.memos[": 1}));
+    assert!(matches!(
+        querier.evaluate(Some(&FOOD)),
+        Err(QueryError::OperatorError { .. })
+    ));
+}
+
+#[test]
+fn test_first_failure() {
+    let spec = BaseQuerier::new(&json!({
+        "item": {"$regex": "^[a-z]+$"},
+        "qty": {"$gte": 20},
+        "type": {"$in": ["food", "fruit"]},
+    }));
+    assert_eq!(None, spec.first_failure(Some(&FOOD)).unwrap());
+    assert_eq!(
+        Some(FailureInfo {
+            field: Some("qty".to_string()),
+            expected: json!({"$gte": 20}),
+            actual: Some(json!(10)),
+        }),
+        spec.first_failure(Some(&FRUIT)).unwrap()
+    );
+
+    // missing fields and conditions that aren't field conditions
+    let spec = BaseQuerier::new(&json!({
+        "$or": [{"qty": {"$lt": 5}}, {"price": {"$lt": 1}}],
+        "discount": {"$exists": true},
+    }));
+    assert_eq!(
+        Some(FailureInfo {
+            field: None,
+            expected: json!({"$or": [{"qty": {"$lt": 5}}, {"price": {"$lt": 1}}]}),
+            actual: Some(FOOD.clone()),
+        }),
+        spec.first_failure(Some(&FOOD)).unwrap()
+    );
+    let spec = BaseQuerier::new(&json!({"discount": {"$exists": true}}));
+    assert_eq!(
+        Some(FailureInfo {
+            field: Some("discount".to_string()),
+            expected: json!({"$exists": true}),
+            actual: None,
+        }),
+        spec.first_failure(Some(&FOOD)).unwrap()
+    );
+
+    let spec = BaseQuerier::new(&json!({"qty": {"$unknown": 1}}));
+    assert!(spec.first_failure(Some(&FOOD)).is_err());
+}
+
+#[test]
+fn test_to_value() {
+    for q in [
+        json!({}),
+        json!({"item": "xyz", "qty": {"$gt": 20, "$lte": 30}, "c": null, "sale": true}),
+        json!({"tags": ["red", "blank"], "price": 2.5}),
+        json!({"$or": [{"a": 1}, {"$and": [{"b": 2}, {"c": {"$in": [1, 2]}}]}]}),
+        json!({"$nor": [{"a": 1}], "b": {"$not": {"$gt": 5}}}),
+        json!({"memos": {"$elemMatch": {"by": "shipping"}}}),
+        json!({"a": {"$not": 5}, "b": {"$elemMatch": 5}}),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(q, querier.to_value());
+        assert_eq!(querier, BaseQuerier::new(&querier.to_value()));
+    }
+
+    // negations of plain documents and repeated keys can't be written as they are
+    let negated = BaseQuerier::new(&json!({"a": 1})).negate();
+    assert_eq!(json!({"$nor": [{"a": 1}]}), negated.to_value());
+    let simplified =
+        BaseQuerier::new(&json!({"$and": [{"a": {"$gt": 1}}, {"a": {"$lt": 5}}]})).simplify();
+    assert_eq!(
+        json!({"$and": [{"a": {"$gt": 1}}, {"a": {"$lt": 5}}]}),
+        simplified.to_value()
+    );
+    for doc in all() {
+        assert_eq!(
+            negated.evaluate(Some(doc)).unwrap(),
+            BaseQuerier::new(&negated.to_value())
+                .evaluate(Some(doc))
+                .unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_hash() {
+    use mongoquery::Query;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashSet;
+    use std::hash::{Hash, Hasher};
+
+    let hash = |q: &Value| {
+        let mut hasher = DefaultHasher::new();
+        BaseQuerier::new(q).hash(&mut hasher);
+        hasher.finish()
+    };
+    for q in [
+        json!({}),
+        json!({"item": "xyz", "qty": {"$gt": 20, "$lte": 30}, "c": null, "sale": true}),
+        json!({"tags": ["red", "blank"], "price": 2.5}),
+        json!({"$or": [{"a": 1}, {"$and": [{"b": 2}, {"c": {"$in": [1, 2]}}]}]}),
+        json!({"memos": {"$elemMatch": {"by": "shipping"}}, "b": {"$not": {"$gt": 5}}}),
+        json!({"a": {"$unknown": {"x": 1, "y": [1, 2]}}}),
+    ] {
+        assert_eq!(hash(&q), hash(&q.clone()), "{}", q);
+        assert_eq!(BaseQuerier::new(&q), BaseQuerier::new(&q.clone()));
+    }
+
+    // compiled queries can key a cache
+    let mut cache: HashMap<Query<_>, usize> = HashMap::new();
+    for q in [
+        json!({"qty": {"$gt": 20}}),
+        json!({"qty": {"$gt": 20}}),
+        json!({"qty": {"$gt": 20.0}}),
+        json!({"qty": {"$gte": 20}}),
+        json!({"qty": {"$gt": 20}}),
+    ] {
+        *cache.entry(BaseQuerier::new(&q)).or_default() += 1;
+    }
+    assert_eq!(3, cache.len());
+    assert_eq!(
+        Some(&3),
+        cache.get(&BaseQuerier::new(&json!({"qty": {"$gt": 20}})))
+    );
+
+    let distinct: HashSet<_> = [
+        json!({"a": 1}),
+        json!({"a": "1"}),
+        json!({"a": [1]}),
+        json!({"b": 1}),
+        json!({"a": 1, "b": 1}),
+    ]
+    .iter()
+    .map(BaseQuerier::new)
+    .collect();
+    assert_eq!(5, distinct.len());
+}
+
+#[test]
+fn test_non_finite_numbers() {
+    use mongoquery::value_partial_cmp;
+    use std::cmp::Ordering;
+
+    // serde_json turns non-finite floats into null
+    assert_eq!(Value::Null, json!(f64::NAN));
+    assert_eq!(Value::Null, json!(f64::INFINITY));
+    let record =
+        json!({"nan": f64::NAN, "max": f64::MAX, "tiny": f64::MIN_POSITIVE, "big": u64::MAX});
+    let matches = |q: Value| BaseQuerier::new(&q).evaluate(Some(&record)).unwrap();
+    assert!(matches(json!({"nan": null})));
+    assert!(!matches(json!({"nan": {"$gt": 0}})));
+    assert!(!matches(json!({"nan": {"$lt": 0}})));
+
+    // finite numbers at the limits compare as usual
+    assert!(matches(json!({"max": {"$gt": 1e300}})));
+    assert!(matches(json!({"max": {"$gte": f64::MAX}})));
+    assert!(!matches(json!({"max": {"$gt": f64::MAX}})));
+    assert!(matches(
+        json!({"tiny": {"$gt": 0}, "$and": [{"tiny": {"$lt": 1e-300}}]})
+    ));
+    assert!(matches(json!({"big": {"$gt": i64::MAX}})));
+    assert_eq!(
+        Some(Ordering::Less),
+        value_partial_cmp(&json!(f64::MIN), &json!(f64::MAX))
+    );
+    // sentinel strings are just strings
+    assert_eq!(
+        Some(Ordering::Less),
+        value_partial_cmp(&json!("Infinity"), &json!("NaN"))
+    );
+    assert_eq!(None, value_partial_cmp(&json!("NaN"), &json!(1)));
+}
+
+#[test]
+fn test_deep_key_match() {
+    let records = [
+        json!({"user": {"profile": {"settings": [{"api_secret": "x"}]}}}),
+        json!({"user": {"profile": {"name": "secret agent"}}}),
+        json!({"user": "secret"}),
+        json!({"Secret": 1}),
+    ];
+    let r: Vec<_> = records.iter().collect();
+
+    assert_eq!(
+        vec![r[0]],
+        query(json!({"user": {"$deepKeyMatch": "secret"}}), r.clone())
+    );
+    assert_eq!(
+        vec![r[0], r[1]],
+        query(
+            json!({"user.profile": {"$deepKeyMatch": "^(name|api_)"}}),
+            r.clone()
+        )
+    );
+    // keys of the field itself count, values and scalars don't
+    assert_eq!(
+        vec![r[0], r[1]],
+        query(json!({"user": {"$deepKeyMatch": "^profile$"}}), r.clone())
+    );
+    assert_eq!(
+        vec![r[3]],
+        query(json!({"$deepKeyMatch": "(?i)^secret$"}), r.clone())
+    );
+    assert_eq!(
+        empty(),
+        query(json!({"user": {"$deepKeyMatch": "agent"}}), r)
+    );
+
+    let querier = BaseQuerier::new(&json!({"$deepKeyMatch": "("}));
+    assert!(matches!(
+        querier.evaluate(Some(&FOOD)),
+        Err(QueryError::CompileError { .. })
+    ));
+}
+
+#[test]
+fn test_canonicalize_extended_json() {
+    use mongoquery::canonicalize_extended_json;
+
+    let exported = json!({
+        "_id": {"$oid": "5d505646cf6d4fe581014ab2"},
+        "qty": {"$numberLong": "25"},
+        "count": {"$numberInt": "-3"},
+        "price": {"$numberDouble": "2.5"},
+        "ratio": {"$numberDouble": "NaN"},
+        "created": {"$date": {"$numberLong": "1672531200000"}},
+        "updated": {"$date": "2023-02-01T12:30:00.000Z"},
+        "before_epoch": {"$date": {"$numberLong": "-1"}},
+        "memos": [{"at": {"$date": {"$numberLong": "951782400123"}}, "by": "shipping"}],
+        "not_a_wrapper": {"$numberLong": "25", "other": 1},
+    });
+    let document = canonicalize_extended_json(&exported);
+    assert_eq!(
+        json!({
+            "_id": "5d505646cf6d4fe581014ab2",
+            "qty": 25,
+            "count": -3,
+            "price": 2.5,
+            "ratio": {"$numberDouble": "NaN"},
+            "created": "2023-01-01T00:00:00.000Z",
+            "updated": "2023-02-01T12:30:00.000Z",
+            "before_epoch": "1969-12-31T23:59:59.999Z",
+            "memos": [{"at": "2000-02-29T00:00:00.123Z", "by": "shipping"}],
+            "not_a_wrapper": {"$numberLong": "25", "other": 1},
+        }),
+        document
+    );
+
+    let querier = BaseQuerier::new(&json!({
+        "_id": "5d505646cf6d4fe581014ab2",
+        "qty": {"$gte": 25},
+        "price": {"$lt": 3},
+        "created": {"$gte": "2023-01-01", "$lt": "2023-02-01"},
+        "memos.by": "shipping",
+    }));
+    assert!(!querier.evaluate(Some(&exported)).unwrap());
+    assert!(querier.evaluate(Some(&document)).unwrap());
+}
+
+#[test]
+fn test_count_matching() {
+    let records: Vec<Value> = (0..20)
+        .map(|i| json!({"n": i, "even": i % 2 == 0, "tags": ["a", if i % 3 == 0 { "b" } else { "c" }]}))
+        .collect();
+    for q in [
+        json!({"even": true}),
+        json!({"n": {"$gte": 5, "$lt": 15}, "tags": "b"}),
+        json!({"n": {"$gt": 100}}),
+        json!({}),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        let filtered: Vec<_> = records
+            .iter()
+            .filter(|r| querier.evaluate(Some(r)).unwrap())
+            .collect();
+        assert_eq!(filtered.len(), querier.count_matching(&records).unwrap());
+    }
+    assert_eq!(
+        2,
+        BaseQuerier::new(&json!({"qty": {"$gt": 5}}))
+            .count_matching(all())
+            .unwrap()
+    );
+
+    let querier = BaseQuerier::new(&json!({"n": {"$unknown": 1}}));
+    assert!(querier.count_matching(&records).is_err());
+}
+
+#[test]
+fn test_mod() {
+    // integer mode truncates like MongoDB
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"qty": {"$mod": [5, 0], "$gt": 20}}), all())
+    );
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"qty": {"$mod": [4, 2]}}), all())
+    );
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"price": {"$mod": [3, 2]}}), all())
+    );
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"price": {"$mod": [4.9, 0]}}), all())
+    );
+    assert_eq!(all(), query(json!({"ratings": {"$mod": [3, 0]}}), all()));
+    assert_eq!(empty(), query(json!({"item": {"$mod": [3, 0]}}), all()));
+
+    // float mode compares remainders within the tolerance
+    assert_eq!(
+        all(),
+        query(json!({"price": {"$mod": [0.25, 0, 1e-9]}}), all())
+    );
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"price": {"$mod": [0.5, 0, 1e-9]}}), all())
+    );
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"price": {"$mod": [1, 0.25, 1e-9]}}), all())
+    );
+    let records = [json!({"price": 0.3}), json!({"price": 0.35})];
+    let r: Vec<_> = records.iter().collect();
+    // 0.3 % 0.1 is 0.09999999999999998 in floating point
+    assert_eq!(
+        vec![r[0]],
+        query(json!({"price": {"$mod": [0.1, 0, 1e-9]}}), r.clone())
+    );
+    assert_eq!(empty(), query(json!({"price": {"$mod": [0.1, 0, 0]}}), r));
+
+    for invalid in [
+        json!([0, 1]),
+        json!([0.0, 0, 0.1]),
+        json!([2]),
+        json!("2"),
+        json!([2, "0"]),
+    ] {
+        let querier = BaseQuerier::new(&json!({"qty": {"$mod": invalid}}));
+        assert!(matches!(
+            querier.evaluate(Some(&FOOD)),
+            Err(QueryError::OperatorError { .. })
+        ));
+    }
+}
+
+#[test]
+fn test_short_circuit() {
+    use mongoquery::OperatorContainer;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // records the name of every branch evaluated, and matches if the condition says so
+    struct Probe {
+        calls: Rc<RefCell<Vec<String>>>,
+    }
+    impl CustomOperator for Probe {
+        fn evaluate(
+            &self,
+            _evaluatee: Option<&Value>,
+            condition: &Value,
+        ) -> Result<bool, QueryError> {
+            self.calls
+                .borrow_mut()
+                .push(condition[0].as_str().unwrap().to_string());
+            Ok(condition[1].as_bool().unwrap())
+        }
+    }
+    let calls = Rc::new(RefCell::new(Vec::<String>::new()));
+    let mut ops = OperatorContainer::new();
+    ops.insert(
+        "probe",
+        Probe {
+            calls: calls.clone(),
+        },
+    );
+    let ops = ops.to_hashmap();
+    let evaluated = |q: Value| {
+        calls.borrow_mut().clear();
+        let matched = BaseQuerier::new(&q)
+            .evaluate_with_custom_ops(Some(&FOOD), &ops)
+            .unwrap();
+        (matched, calls.borrow().clone())
+    };
+
+    assert_eq!(
+        (false, vec!["a".to_string(), "b".to_string()]),
+        evaluated(json!({"$and": [
+            {"$probe": ["a", true]},
+            {"$probe": ["b", false]},
+            {"$probe": ["c", true]},
+        ]}))
+    );
+    assert_eq!(
+        (true, vec!["a".to_string(), "b".to_string()]),
+        evaluated(json!({"$or": [
+            {"$probe": ["a", false]},
+            {"$probe": ["b", true]},
+            {"$probe": ["c", true]},
+        ]}))
+    );
+    assert_eq!(
+        (false, vec!["a".to_string()]),
+        evaluated(json!({"$nor": [
+            {"$probe": ["a", true]},
+            {"$probe": ["b", false]},
+        ]}))
+    );
+    assert_eq!(
+        (true, vec!["a".to_string(), "b".to_string()]),
+        evaluated(json!({"$nor": [
+            {"$probe": ["a", false]},
+            {"$probe": ["b", false]},
+        ]}))
+    );
+    // keys of a query document are evaluated in alphabetical order
+    assert_eq!(
+        (false, vec!["a".to_string()]),
+        evaluated(json!({
+            "b": {"$probe": ["b", true]},
+            "a": {"$probe": ["a", false]},
+        }))
+    );
+    // only the first of the three ratings is needed
+    assert_eq!(
+        (true, vec!["x".to_string()]),
+        evaluated(json!({"ratings": {"$elemMatch": {"$probe": ["x", true]}}}))
+    );
+
+    // branches after the deciding one can't fail the evaluation
+    let querier = BaseQuerier::new(&json!({"$or": [{"qty": 25}, {"qty": {"$unknown": 1}}]}));
+    assert!(querier.evaluate(Some(&FOOD)).unwrap());
+}
+
+#[test]
+fn test_evaluate_serializable() {
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Memo {
+        memo: String,
+        by: String,
+    }
+    #[derive(Serialize)]
+    struct Item {
+        item: String,
+        qty: u32,
+        ratings: Vec<u8>,
+        memos: Vec<Memo>,
+        discount: Option<f64>,
+    }
+    let item = Item {
+        item: "xyz".to_string(),
+        qty: 25,
+        ratings: vec![5, 8, 9],
+        memos: vec![Memo {
+            memo: "approved".to_string(),
+            by: "billing".to_string(),
+        }],
+        discount: None,
+    };
+
+    let querier = BaseQuerier::new(&json!({
+        "item": "xyz",
+        "qty": {"$gt": 20},
+        "ratings": 8,
+        "memos.by": "billing",
+        "discount": null,
+    }));
+    assert!(querier.evaluate_serializable(&item).unwrap());
+    let querier = BaseQuerier::new(&json!({"qty": {"$lt": 20}}));
+    assert!(!querier.evaluate_serializable(&item).unwrap());
+
+    // maps with non-string keys can't be serialized to JSON
+    let mut unserializable = BTreeMap::new();
+    unserializable.insert(vec![1], "a");
+    assert!(matches!(
+        querier.evaluate_serializable(&unserializable),
+        Err(QueryError::SerializationError { .. })
+    ));
+}
+
+#[test]
+fn test_array_intersects() {
+    assert_eq!(
+        all(),
+        query(json!({"ratings": {"$arrayIntersects": [9, 100]}}), all())
+    );
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"ratings": {"$arrayIntersects": [8]}}), all())
+    );
+    assert_eq!(
+        empty(),
+        query(json!({"ratings": {"$arrayIntersects": [1, 2, 3]}}), all())
+    );
+    assert_eq!(
+        empty(),
+        query(json!({"ratings": {"$arrayIntersects": []}}), all())
+    );
+    // unlike $in, scalars don't match
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"qty": {"$in": [25, 100]}}), all())
+    );
+    assert_eq!(
+        empty(),
+        query(json!({"qty": {"$arrayIntersects": [25, 100]}}), all())
+    );
+    // nested arrays are compared as whole elements
+    let records = [json!({"a": [[1, 2], [3]]})];
+    let r: Vec<_> = records.iter().collect();
+    assert_eq!(
+        r.clone(),
+        query(json!({"a": {"$arrayIntersects": [[3]]}}), r.clone())
+    );
+    assert_eq!(empty(), query(json!({"a": {"$arrayIntersects": [3]}}), r));
+
+    let querier = BaseQuerier::new(&json!({"ratings": {"$arrayIntersects": 9}}));
+    assert!(matches!(
+        querier.evaluate(Some(&FOOD)),
+        Err(QueryError::OperatorError { .. })
+    ));
+}
+
+#[test]
+fn test_strict_array() {
+    let strict = EvaluationOptions {
+        strict_array: true,
+        ..Default::default()
+    };
+    let scalar = json!({"ratings": 5, "size": {"uom": "cm"}});
+
+    for (q, record, lenient, strict_match) in [
+        (json!({"ratings": 5}), &*FOOD, true, false),
+        (json!({"ratings": 5}), &scalar, true, true),
+        (json!({"ratings": [5, 8, 9]}), &*FOOD, true, true),
+        (json!({"ratings": 8}), &*FOOD, true, false),
+        (json!({"ratings.1": 8}), &*FOOD, true, true),
+        (json!({"ratings": null}), &*FOOD, false, false),
+        (json!({"size.uom": "cm"}), &scalar, true, true),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(lenient, querier.evaluate(Some(record)).unwrap(), "{}", q);
+        assert_eq!(
+            strict_match,
+            querier
+                .evaluate_with_options(Some(record), &strict)
+                .unwrap(),
+            "{}",
+            q
+        );
+    }
+
+    // paths no longer reach into the documents of an array
+    let record = json!({"items": [{"name": "apple"}, {"name": "pear"}]});
+    let querier = BaseQuerier::new(&json!({"items.name": "apple"}));
+    assert!(querier.evaluate(Some(&record)).unwrap());
+    assert!(!querier
+        .evaluate_with_options(Some(&record), &strict)
+        .unwrap());
+    let querier = BaseQuerier::new(&json!({"items.0.name": "apple"}));
+    assert!(querier
+        .evaluate_with_options(Some(&record), &strict)
+        .unwrap());
+
+    // operators keep their own array semantics
+    for q in [
+        json!({"ratings": {"$gt": 8}}),
+        json!({"ratings": {"$eq": 5}}),
+        json!({"ratings": {"$in": [5]}}),
+        json!({"ratings": {"$elemMatch": {"$gte": 9}}}),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert!(
+            querier.evaluate_with_options(Some(&FOOD), &strict).unwrap(),
+            "{}",
+            q
+        );
+    }
+}
+
+#[test]
+fn test_compile_error() {
+    let q = json!({"item": {"$regex": "(unclosed"}});
+
+    // surfaced when the query is built
+    assert!(matches!(
+        BaseQuerier::try_new(&q),
+        Err(QueryError::CompileError { operator, .. }) if operator == "regex"
+    ));
+    // and during evaluation of a query built without validation
+    assert!(matches!(
+        BaseQuerier::new(&q).evaluate(Some(&FOOD)),
+        Err(QueryError::CompileError { operator, .. }) if operator == "regex"
+    ));
+    let nested = json!({"$or": [{"qty": 25}, {"memos": {"$elemMatch": {"$deepKeyMatch": "[a-"}}}]});
+    assert!(matches!(
+        BaseQuerier::try_new(&nested),
+        Err(QueryError::CompileError { operator, .. }) if operator == "deepKeyMatch"
+    ));
+    let expr = json!({"$expr": {"$regexMatch": {"input": "$item", "regex": "(unclosed"}}});
+    assert!(matches!(
+        BaseQuerier::new(&expr).evaluate(Some(&FOOD)),
+        Err(QueryError::CompileError { operator, .. }) if operator == "regexMatch"
+    ));
+
+    // malformed conditions remain runtime operator errors
+    let q = json!({"item": {"$regex": 5}});
+    let querier = BaseQuerier::try_new(&q).unwrap();
+    assert!(matches!(
+        querier.evaluate(Some(&FOOD)),
+        Err(QueryError::OperatorError { .. })
+    ));
+
+    let querier = BaseQuerier::try_new(&json!({"item": {"$regex": "^x.z$"}})).unwrap();
+    assert!(querier.evaluate(Some(&FOOD)).unwrap());
+}
+
+#[test]
+fn test_wildcard_field() {
+    assert_eq!(vec![&*FOOD], query(json!({"$**": {"$eq": "food"}}), all()));
+    assert_eq!(vec![&*FRUIT], query(json!({"$**": "jkl"}), all()));
+    // arrays are matched the way a named field would be
+    assert_eq!(all(), query(json!({"$**": 9}), all()));
+    assert_eq!(vec![&*FOOD], query(json!({"$**": 8}), all()));
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"$**": {"$gt": 4, "$lt": 5}}), all())
+    );
+    // only top-level fields are considered
+    assert_eq!(empty(), query(json!({"$**": "shipping"}), all()));
+    assert_eq!(
+        all(),
+        query(json!({"memos": {"$elemMatch": {"$**": "shipping"}}}), all())
+    );
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"$**": 25, "type": "food"}), all())
+    );
+    assert_eq!(vec![&*FRUIT], query(json!({"$nor": [{"$**": 25}]}), all()));
+
+    let querier = BaseQuerier::new(&json!({"$**": {"$exists": true}}));
+    assert!(!querier.evaluate(Some(&json!({}))).unwrap());
+    assert!(!querier.evaluate(Some(&json!(5))).unwrap());
+    assert!(!querier.evaluate(None).unwrap());
+}
+
+#[test]
+fn test_elem_match_single_element() {
+    // each operator is satisfied by some element of FOOD's [5, 8, 9] and FRUIT's [5, 9] ...
+    assert_eq!(
+        all(),
+        query(json!({"ratings": {"$gte": 8, "$lt": 9}}), all())
+    );
+    // ... but only FOOD has a single element satisfying both
+    assert_eq!(
+        vec![&*FOOD],
+        query(
+            json!({"ratings": {"$elemMatch": {"$gte": 8, "$lt": 9}}}),
+            all()
+        )
+    );
+    assert_eq!(
+        all(),
+        query(json!({"ratings": {"$gt": 5, "$lt": 9}}), all())
+    );
+    assert_eq!(
+        vec![&*FOOD],
+        query(
+            json!({"ratings": {"$elemMatch": {"$gt": 5, "$lt": 9}}}),
+            all()
+        )
+    );
+    assert_eq!(
+        empty(),
+        query(
+            json!({"ratings": {"$elemMatch": {"$gt": 5, "$lt": 8}}}),
+            all()
+        )
+    );
+    assert_eq!(
+        all(),
+        query(json!({"ratings": {"$elemMatch": {"$gte": 9}}}), all())
+    );
+    // $elemMatch only matches arrays, even if a scalar satisfies its operators
+    assert_eq!(
+        all(),
+        query(json!({"qty": {"$gte": 10, "$lte": 25}}), all())
+    );
+    assert_eq!(
+        empty(),
+        query(
+            json!({"qty": {"$elemMatch": {"$gte": 10, "$lte": 25}}}),
+            all()
+        )
+    );
+}
+
+#[test]
+fn test_clone() {
+    let querier = BaseQuerier::new(&json!({
+        "$or": [{"qty": {"$gt": 20}}, {"memos": {"$elemMatch": {"by": "payment"}}}],
+        "item": {"$not": {"$regex": "^a"}},
+    }));
+    let cloned = querier.clone();
+    assert_eq!(querier, cloned);
+    drop(querier);
+    assert_eq!(
+        all(),
+        all()
+            .into_iter()
+            .filter(|v| cloned.evaluate(Some(v)).unwrap())
+            .collect::<Vec<_>>()
+    );
+
+    // clones can be moved to other threads
+    let handles: Vec<_> = [&*FOOD, &*FRUIT]
+        .into_iter()
+        .map(|value| {
+            let querier = cloned.clone();
+            std::thread::spawn(move || querier.evaluate(Some(value)).unwrap())
+        })
+        .collect();
+    for handle in handles {
+        assert!(handle.join().unwrap());
+    }
+
+    // borrowed queries stay borrowed when cloned
+    let source = json!({"ratings": {"$in": [8]}});
+    let borrowed = BaseQuerier::new_borrowed(&source);
+    let cloned = borrowed.clone();
+    assert_eq!(borrowed, cloned);
+    assert!(cloned.evaluate(Some(&FOOD)).unwrap());
+    assert!(!cloned.evaluate(Some(&FRUIT)).unwrap());
+}
+
+#[test]
+fn test_evaluate_budgeted() {
+    let querier = BaseQuerier::new(&json!({"qty": {"$gt": 20, "$lt": 30}}));
+    assert!(matches!(
+        querier.evaluate_budgeted(Some(&FOOD), 1),
+        Err(QueryError::BudgetExceeded { max_steps: 1 })
+    ));
+    assert!(querier.evaluate_budgeted(Some(&FOOD), 2).unwrap());
+    // evaluation stops at the first failing operator, within budget
+    assert!(!querier.evaluate_budgeted(Some(&FRUIT), 1).unwrap());
+
+    // every element visited by $elemMatch counts
+    let querier = BaseQuerier::new(&json!({"ratings": {"$elemMatch": {"$gt": 8}}}));
+    assert!(matches!(
+        querier.evaluate_budgeted(Some(&FOOD), 2),
+        Err(QueryError::BudgetExceeded { .. })
+    ));
+    assert!(querier.evaluate_budgeted(Some(&FOOD), 3).unwrap());
+
+    // so do the operators of sub-queries, like the predicate of $countWhere
+    let querier = BaseQuerier::new(&json!({
+        "ratings": {"$countWhere": {"predicate": {"$gte": 8}, "count": 2}}
+    }));
+    assert!(matches!(
+        querier.evaluate_budgeted(Some(&FOOD), 3),
+        Err(QueryError::BudgetExceeded { .. })
+    ));
+    assert!(querier.evaluate_budgeted(Some(&FOOD), 4).unwrap());
+
+    // field equalities aren't operators
+    let querier = BaseQuerier::new(&json!({"item": "xyz", "qty": 25}));
+    assert!(querier.evaluate_budgeted(Some(&FOOD), 0).unwrap());
+}
+
+#[test]
+fn test_sub_query_evaluation() {
+    use mongoquery::{custom_operator, OperatorContainer};
+
+    // sub-queries have the custom operators of the enclosing query
+    let mut ops = OperatorContainer::new();
+    custom_operator!(ops, "divisible_by", |evaluatee, condition| {
+        Ok(matches!(
+            (evaluatee.and_then(Value::as_i64), condition.as_i64()),
+            (Some(n), Some(d)) if d != 0 && n % d == 0
+        ))
+    });
+    let querier = BaseQuerier::new(&json!({
+        "ratings": {"$countWhere": {"predicate": {"$divisible_by": 4}, "count": 1}}
+    }));
+    assert!(querier
+        .evaluate_with_custom_ops(Some(&FOOD), ops.as_ref())
+        .unwrap());
+    assert!(!querier
+        .evaluate_with_custom_ops(Some(&FRUIT), ops.as_ref())
+        .unwrap());
+
+    // and its options
+    let insensitive = EvaluationOptions {
+        case_insensitive_fields: true,
+        ..Default::default()
+    };
+    for q in [
+        json!({"memos": {"$all": [{"$elemMatch": {"BY": "billing"}}]}}),
+        json!({"memos": {"$atIndex": {"index": -1, "match": {"Memo": "approved"}}}}),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert!(!querier.evaluate(Some(&FOOD)).unwrap(), "{}", q);
+        assert!(
+            querier
+                .evaluate_with_options(Some(&FOOD), &insensitive)
+                .unwrap(),
+            "{}",
+            q
+        );
+    }
+}
+
+#[test]
+fn test_affix() {
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"item": {"$startsWith": "jk"}}), all())
+    );
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"item": {"$endsWith": "yz"}}), all())
+    );
+    assert_eq!(all(), query(json!({"item": {"$startsWith": ""}}), all()));
+    assert_eq!(
+        empty(),
+        query(json!({"item": {"$startsWith": "yz"}}), all())
+    );
+    // patterns are taken literally
+    assert_eq!(empty(), query(json!({"item": {"$startsWith": "."}}), all()));
+    // any string element of an array may match
+    assert_eq!(
+        all(),
+        query(json!({"memos.memo": {"$startsWith": "on"}}), all())
+    );
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"memos.memo": {"$endsWith": "ayed"}}), all())
+    );
+    // non-strings don't match
+    assert_eq!(empty(), query(json!({"qty": {"$startsWith": "2"}}), all()));
+
+    let querier = BaseQuerier::new(&json!({"item": {"$endsWith": 5}}));
+    assert!(matches!(
+        querier.evaluate(Some(&FOOD)),
+        Err(QueryError::OperatorError { .. })
+    ));
+}
+
+#[test]
+fn test_eq_arrays() {
+    // scalar conditions match arrays containing them, like field equalities
+    for q in [json!({"ratings": {"$eq": 5}}), json!({"ratings": 5})] {
+        assert_eq!(all(), query(q, all()));
+    }
+    for q in [json!({"ratings": {"$eq": 8}}), json!({"ratings": 8})] {
+        assert_eq!(vec![&*FOOD], query(q, all()));
+    }
+    // array conditions match the whole array only
+    for q in [
+        json!({"ratings": {"$eq": [5, 8, 9]}}),
+        json!({"ratings": [5, 8, 9]}),
+    ] {
+        assert_eq!(vec![&*FOOD], query(q, all()));
+    }
+    assert_eq!(empty(), query(json!({"ratings": {"$eq": [5, 8]}}), all()));
+    assert_eq!(
+        empty(),
+        query(json!({"ratings": {"$eq": [9, 8, 5]}}), all())
+    );
+    // $ne is the complement
+    assert_eq!(vec![&*FRUIT], query(json!({"ratings": {"$ne": 8}}), all()));
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"ratings": {"$ne": [5, 8, 9]}}), all())
+    );
+
+    // arrays and documents also match an equal element
+    let records = [json!({"a": [[1, 2], {"b": 1}]})];
+    let r: Vec<_> = records.iter().collect();
+    assert_eq!(r.clone(), query(json!({"a": {"$eq": [1, 2]}}), r.clone()));
+    assert_eq!(r.clone(), query(json!({"a": {"$eq": {"b": 1}}}), r.clone()));
+    assert_eq!(r.clone(), query(json!({"a": {"$eq": 2}}), r.clone()));
+    assert_eq!(empty(), query(json!({"a": {"$eq": [2, 1]}}), r));
+}
+
+#[test]
+fn test_diff() {
+    let querier = BaseQuerier::new(&json!({
+        "qty": {"$gt": 30, "$lt": 100},
+        "type": "fruit",
+        "item": "xyz",
+    }));
+    let diff = querier.diff(Some(&FOOD)).unwrap();
+    assert_eq!(
+        vec![
+            Mismatch {
+                field: Some("qty".to_string()),
+                expected: json!({"$gt": 30}),
+                actual: Some(json!(25)),
+            },
+            Mismatch {
+                field: Some("type".to_string()),
+                expected: json!("fruit"),
+                actual: Some(json!("food")),
+            },
+        ],
+        diff
+    );
+    assert_eq!(
+        vec![
+            "field `qty`: expected `$gt 30`, actual `25`",
+            "field `type`: expected `\"fruit\"`, actual `\"food\"`",
+        ],
+        diff.iter().map(ToString::to_string).collect::<Vec<_>>()
+    );
+
+    // nested fields are reported by their path, other conditions as a whole
+    let querier = BaseQuerier::new(&json!({
+        "size": {"uom": "in", "h": {"$exists": true}},
+        "$or": [{"qty": 10}, {"item": "jkl"}],
+        "$and": [{"price": {"$lte": 2}}],
+    }));
+    let diff: Vec<_> = querier
+        .diff(Some(&FOOD))
+        .unwrap()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        vec![
+            "field `price`: expected `$lte 2`, actual `2.5`".to_string(),
+            r#"expected `$or [{"qty":10},{"item":"jkl"}]`, actual `"#.to_string()
+                + &FOOD.to_string()
+                + "`",
+            "field `size.h`: expected `$exists true`, actual missing".to_string(),
+            "field `size.uom`: expected `\"in\"`, actual missing".to_string(),
+        ],
+        diff
+    );
+
+    assert!(BaseQuerier::new(&json!({"qty": 25}))
+        .diff(Some(&FOOD))
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_unicode_case_folding() {
+    let ascii = EvaluationOptions {
+        case_insensitive_fields: true,
+        ..Default::default()
+    };
+    let unicode = EvaluationOptions {
+        unicode_case_folding: true,
+        ..ascii.clone()
+    };
+    let record = json!({"café": "crème", "straße": 1, "Größe": {"Ölfarbe": "rot"}, "Σοφία": true});
+
+    for (q, ascii_match, unicode_match) in [
+        // ASCII letters fold either way, but "é" and "É" only with Unicode folding
+        (json!({"CAFÉ": "crème"}), false, true),
+        (json!({"Café": "crème"}), true, true),
+        (json!({"CAFé": "crème"}), true, true),
+        (json!({"STRASSE": 1}), false, true),
+        (json!({"grösse.ölfarbe": "rot"}), false, true),
+        (json!({"GRÖSSE.ÖLFARBE": "rot"}), false, true),
+        (json!({"ΣΟΦΊΑ": true}), false, true),
+        (json!({"cafe": "crème"}), false, false),
+        // values are compared as they are
+        (json!({"café": "CRÈME"}), false, false),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(
+            ascii_match,
+            querier
+                .evaluate_with_options(Some(&record), &ascii)
+                .unwrap(),
+            "{}",
+            q
+        );
+        assert_eq!(
+            unicode_match,
+            querier
+                .evaluate_with_options(Some(&record), &unicode)
+                .unwrap(),
+            "{}",
+            q
+        );
+    }
+
+    // folding only applies to case-insensitive comparisons
+    let folding_only = EvaluationOptions {
+        unicode_case_folding: true,
+        ..Default::default()
+    };
+    let querier = BaseQuerier::new(&json!({"CAFÉ": "crème"}));
+    assert!(!querier
+        .evaluate_with_options(Some(&record), &folding_only)
+        .unwrap());
+}
+
+#[test]
+fn test_custom_operator_field_name() {
+    use mongoquery::OperatorContainer;
+
+    // matches values under the limit the condition sets for the field being evaluated
+    struct UnderLimit;
+    impl CustomOperator for UnderLimit {
+        fn evaluate(&self, _: Option<&Value>, _: &Value) -> Result<bool, QueryError> {
+            unreachable!("evaluate_with_field is overridden")
+        }
+
+        fn evaluate_with_field(
+            &self,
+            evaluatee: Option<&Value>,
+            condition: &Value,
+            field_name: Option<&str>,
+        ) -> Result<bool, QueryError> {
+            let limit = field_name
+                .and_then(|field| condition.get(field))
+                .and_then(Value::as_f64)
+                .ok_or_else(|| QueryError::OperatorError {
+                    operator: "underLimit".to_string(),
+                    reason: format!("no limit for {:?}", field_name),
+                })?;
+            Ok(evaluatee.and_then(Value::as_f64).is_some_and(|v| v < limit))
+        }
+    }
+    // reports the field name it is evaluated on
+    struct FieldIs;
+    impl CustomOperator for FieldIs {
+        fn evaluate(&self, _: Option<&Value>, _: &Value) -> Result<bool, QueryError> {
+            unreachable!("evaluate_with_field is overridden")
+        }
+
+        fn evaluate_with_field(
+            &self,
+            _evaluatee: Option<&Value>,
+            condition: &Value,
+            field_name: Option<&str>,
+        ) -> Result<bool, QueryError> {
+            Ok(field_name == condition.as_str())
+        }
+    }
+
+    let mut ops = OperatorContainer::new();
+    ops.insert("underLimit", UnderLimit);
+    ops.insert("fieldIs", FieldIs);
+    let ops = ops.to_hashmap();
+
+    let limits = json!({"qty": 20, "price": 3});
+    assert_eq!(
+        vec![&*FRUIT],
+        query_custom(json!({"qty": {"$underLimit": limits}}), all(), &ops)
+    );
+    assert_eq!(
+        vec![&*FOOD],
+        query_custom(json!({"price": {"$underLimit": limits}}), all(), &ops)
+    );
+    assert!(matches!(
+        BaseQuerier::new(&json!({"_id": {"$underLimit": limits}}))
+            .evaluate_with_custom_ops(Some(&FOOD), &ops),
+        Err(QueryError::OperatorError { .. })
+    ));
+
+    for q in [
+        json!({"qty": {"$fieldIs": "qty"}}),
+        json!({"memos.by": {"$fieldIs": "memos.by"}}),
+        json!({"memos": {"$elemMatch": {"by": {"$fieldIs": "by"}}}}),
+        json!({"ratings": {"$elemMatch": {"$fieldIs": "ratings"}}}),
+        json!({"$or": [{"qty": {"$not": {"$fieldIs": "price"}}}]}),
+        json!({"$**": {"$fieldIs": "_id"}}),
+        json!({"$fieldIs": null}),
+    ] {
+        assert_eq!(all(), query_custom(q.clone(), all(), &ops), "{}", q);
+    }
+}
+
+#[test]
+fn test_context_operators_without_root() {
+    for q in [
+        json!({"$expr": true}),
+        json!({"$expr": {"$regexMatch": {"input": "$item", "regex": "x"}}}),
+        json!({"$fieldEq": "qty"}),
+        json!({"$pointerExists": "/qty"}),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        for root in [json!(25), json!("xyz"), json!([{"qty": 25}]), json!(null)] {
+            assert!(
+                matches!(
+                    querier.evaluate(Some(&root)),
+                    Err(QueryError::OperatorError { .. })
+                ),
+                "{} on {}",
+                q,
+                root
+            );
+        }
+        assert!(
+            matches!(
+                querier.evaluate(None),
+                Err(QueryError::OperatorError { .. })
+            ),
+            "{}",
+            q
+        );
+        // documents are fine
+        querier.evaluate(Some(&FOOD)).unwrap();
+    }
+
+    // nested in a field condition, the root is still the whole document
+    let querier = BaseQuerier::new(
+        &json!({"qty": {"$expr": {"$regexMatch": {"input": "$item", "regex": "^x"}}}}),
+    );
+    assert!(querier.evaluate(Some(&FOOD)).unwrap());
+}
+
+#[test]
+fn test_number_properties() {
+    // FOOD has a qty of 25, FRUIT of 10
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"qty": {"$isEven": true}}), all())
+    );
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"qty": {"$isEven": false}}), all())
+    );
+    assert_eq!(vec![&*FOOD], query(json!({"qty": {"$isOdd": true}}), all()));
+    assert_eq!(
+        vec![&*FRUIT],
+        query(json!({"qty": {"$isOdd": false}}), all())
+    );
+    assert_eq!(all(), query(json!({"qty": {"$isPositive": true}}), all()));
+    assert_eq!(empty(), query(json!({"qty": {"$isNegative": true}}), all()));
+    assert_eq!(all(), query(json!({"qty": {"$isNegative": false}}), all()));
+    // any leaf of an array may match
+    assert_eq!(
+        vec![&*FOOD],
+        query(json!({"ratings": {"$isEven": true}}), all())
+    );
+
+    for (value, even, odd, positive, negative) in [
+        (json!(0), true, false, false, false),
+        (json!(-3), false, true, false, true),
+        (json!(u64::MAX), false, true, true, false),
+        (json!(4.0), true, false, true, false),
+        // parity is undefined for non-integers, so they match neither way
+        (json!(2.5), false, false, true, false),
+        (json!(-0.5), false, false, false, true),
+    ] {
+        let record = json!({"n": value});
+        for (operator, expected) in [
+            ("$isEven", even),
+            ("$isOdd", odd),
+            ("$isPositive", positive),
+            ("$isNegative", negative),
+        ] {
+            let querier = BaseQuerier::new(&json!({"n": {operator: true}}));
+            assert_eq!(
+                expected,
+                querier.evaluate(Some(&record)).unwrap(),
+                "{} {}",
+                operator,
+                value
+            );
+        }
+    }
+    let record = json!({"n": 2.5});
+    for operator in ["$isEven", "$isOdd"] {
+        let querier = BaseQuerier::new(&json!({"n": {operator: false}}));
+        assert!(!querier.evaluate(Some(&record)).unwrap());
+    }
+
+    // non-numbers match neither way
+    for condition in [true, false] {
+        assert_eq!(
+            empty(),
+            query(json!({"item": {"$isEven": condition}}), all())
+        );
+        assert_eq!(
+            empty(),
+            query(json!({"missing": {"$isPositive": condition}}), all())
+        );
+    }
+
+    let querier = BaseQuerier::new(&json!({"qty": {"$isOdd": 1}}));
+    assert!(matches!(
+        querier.evaluate(Some(&FOOD)),
+        Err(QueryError::OperatorError { .. })
+    ));
+}
+
+#[cfg(feature = "yaml")]
+#[test]
+fn test_evaluate_yaml() {
+    let config: serde_yaml::Value = serde_yaml::from_str(
+        r#"
+service: api
+replicas: 3
+ratio: .5
+ports: [80, 443]
+env:
+  debug: false
+  region: !Region eu-west-1
+codes:
+  404: not found
+  true: yes
+"#,
+    )
+    .unwrap();
+
+    for (q, matches) in [
+        (json!({"service": "api", "replicas": {"$gte": 2}}), true),
+        (json!({"ratio": {"$lt": 1}}), true),
+        (json!({"ports": 443}), true),
+        (json!({"env.debug": false}), true),
+        // tags are dropped
+        (json!({"env.region": "eu-west-1"}), true),
+        // scalar keys become strings
+        (json!({"codes.404": "not found"}), true),
+        (json!({"codes.true": "yes"}), true),
+        (json!({"replicas": {"$gt": 3}}), false),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(matches, querier.evaluate_yaml(&config).unwrap(), "{}", q);
+    }
+
+    let config: serde_yaml::Value = serde_yaml::from_str("? [1, 2]\n: pair").unwrap();
+    assert!(matches!(
+        BaseQuerier::new(&json!({})).evaluate_yaml(&config),
+        Err(QueryError::SerializationError { .. })
+    ));
+}
+
+#[cfg(feature = "toml")]
+#[test]
+fn test_evaluate_toml() {
+    let config: toml::Value = toml::from_str(
+        r#"
+title = "example"
+released = 1979-05-27T07:32:00Z
+
+[server]
+ports = [8000, 8001]
+ratio = inf
+"#,
+    )
+    .unwrap();
+
+    for (q, matches) in [
+        (json!({"title": "example", "server.ports": 8001}), true),
+        // datetimes are RFC 3339 strings
+        (json!({"released": "1979-05-27T07:32:00Z"}), true),
+        (
+            json!({"released": {"$gt": "1970-01-01", "$lt": "1980-01-01"}}),
+            true,
+        ),
+        (json!({"released": {"$type": "string"}}), true),
+        // non-finite floats can't be represented in JSON
+        (json!({"server.ratio": null}), true),
+        (json!({"server.ports": 80}), false),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(matches, querier.evaluate_toml(&config).unwrap(), "{}", q);
+    }
+}
+
+#[test]
+fn test_base_operator() {
+    use mongoquery::{BaseOperator, BaseOperators, OperatorProvider};
+    use std::collections::HashSet;
+
+    for &operator in BaseOperator::ALL {
+        assert_eq!(operator, operator.as_str().parse().unwrap());
+        assert_eq!(operator, operator.key().parse().unwrap());
+        assert_eq!(format!("${}", operator), operator.key());
+    }
+    assert_eq!("elemMatch", BaseOperator::ElemMatch.as_str());
+    assert_eq!("$setEq", BaseOperator::SetEq.key());
+    assert!(matches!(
+        "elemmatch".parse::<BaseOperator>(),
+        Err(QueryError::UnsupportedOperator { operator }) if operator == "elemmatch"
+    ));
+    assert!("$$eq".parse::<BaseOperator>().is_err());
+
+    // every operator is either provided by BaseOperators or built into the query language
+    let mut names: HashSet<_> = BaseOperators::get_operators().into_keys().collect();
+    names.extend(BaseOperators::get_context_operators().into_keys());
+    names.extend(
+        ["and", "or", "nor", "not", "elemMatch"]
+            .iter()
+            .map(ToString::to_string),
+    );
+    if !cfg!(feature = "chrono") {
+        names.extend(["olderThan", "newerThan"].iter().map(ToString::to_string));
+    }
+    if !cfg!(feature = "decimal") {
+        names.extend(
+            ["decimalEq", "decimalGt", "decimalLt"]
+                .iter()
+                .map(ToString::to_string),
+        );
+    }
+    if !cfg!(feature = "sha2") {
+        names.insert("hashEq".to_string());
+    }
+    let all: HashSet<_> = BaseOperator::ALL
+        .iter()
+        .map(|operator| operator.as_str().to_string())
+        .collect();
+    assert_eq!(names, all);
+
+    let querier = BaseQuerier::new(
+        &json!({"ratings": {BaseOperator::ElemMatch.key(): {BaseOperator::Gt.key(): 8}}}),
+    );
+    assert!(querier.evaluate(Some(&FOOD)).unwrap());
+}
+
+#[test]
+fn test_arrays_of_arrays() {
+    let record = json!({"a": [[1, 2], [3, 4]], "b": [[{"c": 1}], [{"c": 2}]]});
+    let strict = EvaluationOptions {
+        strict_array: true,
+        ..Default::default()
+    };
+
+    for (q, matches) in [
+        // scalars match anywhere in nested arrays
+        (json!({"a": 3}), true),
+        (json!({"a": {"$gt": 3}}), true),
+        (json!({"a": {"$in": [3, 7]}}), true),
+        (json!({"a": {"$in": [5, 7]}}), false),
+        // arrays match the whole array or one of its elements
+        (json!({"a": [[1, 2], [3, 4]]}), true),
+        (json!({"a": [3, 4]}), true),
+        (json!({"a": {"$eq": [3, 4]}}), true),
+        (json!({"a": {"$in": [[3, 4]]}}), true),
+        (json!({"a": [4, 3]}), false),
+        (json!({"a": [[3, 4]]}), false),
+        // numeric segments index into the outer array, then into the inner one
+        (json!({"a.0": [1, 2]}), true),
+        (json!({"a.0": 2}), true),
+        (json!({"a.0": 3}), false),
+        (json!({"a.1": {"$in": [3]}}), true),
+        (json!({"a.0.1": 2}), true),
+        (json!({"a.1.0": 3}), true),
+        (json!({"a.2": {"$exists": true}}), false),
+        // other segments reach into documents at any nesting level
+        (json!({"b.c": 2}), true),
+        (json!({"b.c": [[1], [2]]}), true),
+        (json!({"b.c": [2]}), true),
+        (json!({"b.0.c": 1}), true),
+        (json!({"b.0.c": 2}), false),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(matches, querier.evaluate(Some(&record)).unwrap(), "{}", q);
+    }
+
+    // only whole arrays and indexing under strict_array
+    for (q, matches) in [
+        (json!({"a": [[1, 2], [3, 4]]}), true),
+        (json!({"a": [3, 4]}), false),
+        (json!({"a": 3}), false),
+        (json!({"a.1": [3, 4]}), true),
+        (json!({"a.1.0": 3}), true),
+        (json!({"b.c": 2}), false),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(
+            matches,
+            querier
+                .evaluate_with_options(Some(&record), &strict)
+                .unwrap(),
+            "{}",
+            q
+        );
+    }
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn test_older_newer_than() {
+    let options = EvaluationOptions {
+        now: Some("2024-03-10T12:00:00Z".parse().unwrap()),
+        ..Default::default()
+    };
+    let record = json!({
+        "created": "2024-03-01T12:00:00Z",
+        "updated": "2024-03-10T06:00:00+02:00",
+        "visits": ["2024-03-09T12:00:00Z", "2024-02-01T00:00:00Z"],
+        "scheduled": "2024-03-11T12:00:00Z",
+        "name": "xyz",
+    });
+
+    for (q, matches) in [
+        (json!({"created": {"$olderThan": "P1W"}}), true),
+        (json!({"created": {"$olderThan": "P9DT1S"}}), false),
+        (json!({"created": {"$newerThan": "P2W"}}), true),
+        (json!({"created": {"$olderThan": 86400}}), true),
+        // offsets are taken into account: updated is 8 hours old
+        (json!({"updated": {"$olderThan": "PT7H59M"}}), true),
+        (json!({"updated": {"$newerThan": "PT8H0.5S"}}), true),
+        (json!({"updated": {"$olderThan": "PT8H"}}), false),
+        (json!({"updated": {"$newerThan": "PT8H"}}), false),
+        // any element of an array may match
+        (json!({"visits": {"$olderThan": "P30D"}}), true),
+        (json!({"visits": {"$newerThan": "P2D"}}), true),
+        (json!({"visits": {"$newerThan": "PT1H"}}), false),
+        // future timestamps have a negative age
+        (json!({"scheduled": {"$newerThan": 0}}), true),
+        (json!({"scheduled": {"$olderThan": -90000}}), true),
+        // non-timestamps and missing fields don't match
+        (json!({"name": {"$olderThan": 0}}), false),
+        (json!({"name": {"$newerThan": "P1D"}}), false),
+        (json!({"missing": {"$newerThan": "P1D"}}), false),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(
+            matches,
+            querier
+                .evaluate_with_options(Some(&record), &options)
+                .unwrap(),
+            "{}",
+            q
+        );
+    }
+
+    // years and months vary in length, and only seconds may be fractional
+    for condition in [
+        json!("P1Y"),
+        json!("P1M"),
+        json!("P1.5D"),
+        json!("PT"),
+        json!("P"),
+        json!("1D"),
+        json!("PT1S1H"),
+        json!("P1D2"),
+        json!(true),
+    ] {
+        let querier = BaseQuerier::new(&json!({"created": {"$olderThan": condition}}));
+        assert!(
+            matches!(
+                querier.evaluate_with_options(Some(&record), &options),
+                Err(QueryError::OperatorError { operator, .. }) if operator == "olderThan"
+            ),
+            "{}",
+            condition
+        );
+    }
+
+    // without a reference time, ages are measured from the current time
+    let querier = BaseQuerier::new(&json!({"created": {"$olderThan": "P1D"}}));
+    assert!(querier.evaluate(Some(&record)).unwrap());
+}
+
+#[test]
+fn test_field_refs() {
+    let options = EvaluationOptions {
+        resolve_field_refs: true,
+        ..Default::default()
+    };
+    let record = json!({
+        "a": 25,
+        "threshold": 20,
+        "limits": {"low": 10, "high": 30},
+        "tags": ["red", "blank"],
+        "favorite": "red",
+    });
+
+    for (q, matches) in [
+        (json!({"a": {"$gt": {"$fieldRef": "threshold"}}}), true),
+        (json!({"a": {"$lt": {"$fieldRef": "threshold"}}}), false),
+        (json!({"a": {"$lt": {"$fieldRef": "limits.high"}}}), true),
+        (
+            json!({"a": {"$between": [{"$fieldRef": "limits.low"}, {"$fieldRef": "limits.high"}]}}),
+            true,
+        ),
+        (json!({"favorite": {"$in": {"$fieldRef": "tags"}}}), true),
+        (json!({"tags": {"$eq": {"$fieldRef": "favorite"}}}), true),
+        (
+            json!({"threshold": {"$not": {"$eq": {"$fieldRef": "a"}}}}),
+            true,
+        ),
+        (
+            json!({"$or": [{"a": {"$eq": {"$fieldRef": "threshold"}}}, {"a": 25}]}),
+            true,
+        ),
+        // conditions without references are unaffected
+        (json!({"a": {"$gt": 20}}), true),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(
+            matches,
+            querier
+                .evaluate_with_options(Some(&record), &options)
+                .unwrap(),
+            "{}",
+            q
+        );
+    }
+
+    // unresolved references are errors
+    for q in [
+        json!({"a": {"$gt": {"$fieldRef": "missing"}}}),
+        json!({"a": {"$in": [1, {"$fieldRef": "limits.medium"}]}}),
+        json!({"a": {"$gt": {"$fieldRef": 5}}}),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert!(
+            matches!(
+                querier.evaluate_with_options(Some(&record), &options),
+                Err(QueryError::OperatorError { operator, .. }) if operator == "fieldRef"
+            ),
+            "{}",
+            q
+        );
+    }
+    let querier = BaseQuerier::new(&json!({"a": {"$gt": {"$fieldRef": "threshold"}}}));
+    assert!(querier.evaluate_with_options(None, &options).is_err());
+
+    // references are plain values unless resolution is enabled
+    let querier = BaseQuerier::new(&json!({"a": {"$eq": {"$fieldRef": "a"}}}));
+    assert!(!querier.evaluate(Some(&record)).unwrap());
+}
+
+#[test]
+fn test_matches_field() {
+    use mongoquery::matches_field;
+
+    let record = json!({"qty": 25, "size": {"uom": "cm"}, "tags": ["blank", "red"]});
+
+    for (field, condition, matches) in [
+        ("qty", json!({"$gt": 20}), true),
+        ("qty", json!({"$gt": 20, "$lt": 25}), false),
+        ("qty", json!(25), true),
+        ("size.uom", json!("cm"), true),
+        ("size.uom", json!({"$in": ["in", "mm"]}), false),
+        ("tags", json!("red"), true),
+        ("tags.1", json!("red"), true),
+        ("tags", json!({"$all": ["red", "blank"]}), true),
+        ("missing", json!({"$exists": false}), true),
+        ("missing", json!(null), true),
+    ] {
+        assert_eq!(
+            matches,
+            matches_field(&record, field, &condition).unwrap(),
+            "{}: {}",
+            field,
+            condition
+        );
+        let query = json!({ field: condition });
+        assert_eq!(
+            BaseQuerier::new(&query).evaluate(Some(&record)).unwrap(),
+            matches_field(&record, field, &condition).unwrap(),
+            "{}",
+            query
+        );
+    }
+
+    assert!(matches!(
+        matches_field(&record, "qty", &json!({"$unknown": 1})),
+        Err(QueryError::UnsupportedOperator { .. })
+    ));
+}
+
+#[test]
+fn test_shared_query() {
+    use mongoquery::SharedQuery;
+    use std::thread;
+
+    fn assert_send_sync<S: Send + Sync>() {}
+    assert_send_sync::<SharedQuery<mongoquery::BaseOperators>>();
+
+    let shared = BaseQuerier::new_shared(&json!({"qty": {"$gt": 20}, "type": "food"}));
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                (0..100).all(|_| {
+                    shared.evaluate(Some(&FOOD)).unwrap() && !shared.evaluate(Some(&FRUIT)).unwrap()
+                })
+            })
+        })
+        .collect();
+    for handle in handles {
+        assert!(handle.join().unwrap());
+    }
+
+    // shared queries evaluate like the query they share
+    let query = BaseQuerier::new(&json!({"Qty": 25}));
+    let shared = SharedQuery::from(BaseQuerier::new(&json!({"Qty": 25})));
+    let options = EvaluationOptions {
+        case_insensitive_fields: true,
+        ..Default::default()
+    };
+    assert_eq!(&query, shared.query());
+    assert!(!shared.evaluate(Some(&FOOD)).unwrap());
+    assert!(shared.evaluate_with_options(Some(&FOOD), &options).unwrap());
+}
+
+#[test]
+fn test_new_interned() {
+    use mongoquery::Interner;
+    use std::sync::Arc;
+
+    let interner = Interner::new();
+    assert!(interner.is_empty());
+    let a = BaseQuerier::new_interned(&json!({"qty": {"$gt": 20}, "type": "food"}), &interner);
+    let b = BaseQuerier::new_interned(
+        &json!({"$or": [{"qty": 10}, {"size": {"h": 14}}], "memos": {"$elemMatch": {"by": "x"}}}),
+        &interner,
+    );
+    assert_eq!(6, interner.len());
+
+    // both queries share the interned field name: the interner, a, b and this reference
+    let qty = interner.intern("qty");
+    assert_eq!(4, Arc::strong_count(&qty));
+    assert_eq!(3, Arc::strong_count(&interner.intern("type")));
+    // clones share it too
+    let c = a.clone();
+    assert_eq!(5, Arc::strong_count(&qty));
+    drop(c);
+    assert_eq!(6, interner.len());
+
+    // interned queries are the same queries
+    for (interned, q) in [
+        (&a, json!({"qty": {"$gt": 20}, "type": "food"})),
+        (
+            &b,
+            json!({"$or": [{"qty": 10}, {"size": {"h": 14}}], "memos": {"$elemMatch": {"by": "x"}}}),
+        ),
+    ] {
+        let query = BaseQuerier::new(&q);
+        assert_eq!(&query, interned);
+        assert_eq!(q, interned.to_value());
+        for doc in all() {
+            assert_eq!(
+                query.evaluate(Some(doc)).unwrap(),
+                interned.evaluate(Some(doc)).unwrap()
+            );
+        }
+    }
+}
+
+#[test]
+fn test_compile_with_ops() {
+    use mongoquery::{BaseOperators, Query};
+
+    fn square(evaluatee: Option<&Value>, condition: &Value) -> Result<bool, QueryError> {
+        let square = evaluatee.and_then(Value::as_f64).map(|n| n * n);
+        let condition = condition
+            .as_f64()
+            .ok_or_else(|| QueryError::OperatorError {
+                operator: "square".to_string(),
+                reason: "condition must be a number".to_string(),
+            })?;
+        Ok(square == Some(condition))
+    }
+    fn never(_: Option<&Value>, _: &Value) -> Result<bool, QueryError> {
+        Ok(false)
+    }
+
+    let q = json!({"qty": {"$square": 625}, "ratings": {"$size": 3}});
+    let compiled = Query::<BaseOperators>::compile_with_ops(&q, &[("square", square)]);
+    assert_eq!(&BaseQuerier::new(&q), compiled.query());
+    assert!(compiled.evaluate(Some(&FOOD)).unwrap());
+    assert!(!compiled.evaluate(Some(&FRUIT)).unwrap());
+    // clones carry the operators too
+    assert!(compiled.clone().evaluate(Some(&FOOD)).unwrap());
+    assert!(matches!(
+        Query::<BaseOperators>::compile_with_ops(&json!({"qty": {"$square": "x"}}), &[("square", square)])
+            .evaluate(Some(&FOOD)),
+        Err(QueryError::OperatorError { operator, .. }) if operator == "square"
+    ));
+
+    // without the operator, it is unsupported
+    assert!(matches!(
+        BaseQuerier::new(&q).evaluate(Some(&FOOD)),
+        Err(QueryError::UnsupportedOperator { operator }) if operator == "square"
+    ));
+
+    // inline operators replace those of the provider, including context operators
+    for q in [
+        json!({"qty": {"$eq": 25}}),
+        json!({"item": {"$regex": "x"}}),
+    ] {
+        assert!(BaseQuerier::new(&q).evaluate(Some(&FOOD)).unwrap());
+        let compiled =
+            Query::<BaseOperators>::compile_with_ops(&q, &[("eq", never), ("regex", never)]);
+        assert!(!compiled.evaluate(Some(&FOOD)).unwrap(), "{}", q);
+    }
+}
+
+#[test]
+fn test_array_comparison() {
+    let record = json!({"ratings": [5, 8, 9], "nested": [[5, 8], [9]], "empty": []});
+
+    for (q, matches) in [
+        // any element satisfying the comparison is enough
+        (json!({"ratings": {"$gt": 8}}), true),
+        (json!({"ratings": {"$gt": 9}}), false),
+        (json!({"ratings": {"$gte": 9}}), true),
+        (json!({"ratings": {"$lt": 5}}), false),
+        (json!({"ratings": {"$lte": 5}}), true),
+        (json!({"nested": {"$gt": 8}}), true),
+        (json!({"empty": {"$gt": 0}}), false),
+        // each operator may be satisfied by a different element
+        (json!({"ratings": {"$gt": 8, "$lt": 6}}), true),
+        (
+            json!({"ratings": {"$elemMatch": {"$gt": 8, "$lt": 6}}}),
+            false,
+        ),
+        (
+            json!({"ratings": {"$elemMatch": {"$gt": 6, "$lt": 9}}}),
+            true,
+        ),
+        // negations require no element to satisfy the comparison
+        (json!({"ratings": {"$not": {"$gt": 8}}}), false),
+        (json!({"ratings": {"$not": {"$gt": 9}}}), true),
+        // indexing compares a single element
+        (json!({"ratings.1": {"$gt": 8}}), false),
+        (json!({"ratings.2": {"$gt": 8}}), true),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(matches, querier.evaluate(Some(&record)).unwrap(), "{}", q);
+    }
+
+    // under strict comparison, incomparable elements only fail the evaluation if no element matches
+    let strict = EvaluationOptions {
+        strict_comparison: true,
+        ..Default::default()
+    };
+    let mixed = json!({"ratings": ["n/a", 9]});
+    let querier = BaseQuerier::new(&json!({"ratings": {"$gt": 8}}));
+    assert!(querier
+        .evaluate_with_options(Some(&mixed), &strict)
+        .unwrap());
+    let querier = BaseQuerier::new(&json!({"ratings": {"$gt": 9}}));
+    assert!(querier
+        .evaluate_with_options(Some(&mixed), &strict)
+        .is_err());
+}
+
+#[test]
+fn test_fallback_operator() {
+    use mongoquery::{custom_operator, OperatorContainer};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let q = json!({"qty": {"$future": 1, "$gt": 20}, "$another": {"a": 1}});
+    let querier = BaseQuerier::new(&q);
+
+    // without a fallback, unknown operators are still errors
+    let ops = OperatorContainer::new();
+    assert!(matches!(
+        querier.evaluate_with_container(Some(&FOOD), &ops),
+        Err(QueryError::UnsupportedOperator { .. })
+    ));
+
+    // a fallback matching everything leaves the other operators to decide
+    let seen = Rc::new(RefCell::new(vec![]));
+    let mut ops = OperatorContainer::new();
+    ops.set_fallback({
+        let seen = Rc::clone(&seen);
+        move |operator, evaluatee, condition| {
+            seen.borrow_mut()
+                .push((operator.to_string(), evaluatee.cloned(), condition.clone()));
+            Ok(true)
+        }
+    });
+    assert!(querier.evaluate_with_container(Some(&FOOD), &ops).unwrap());
+    assert!(!querier.evaluate_with_container(Some(&FRUIT), &ops).unwrap());
+    assert_eq!(
+        vec![
+            ("another".to_string(), Some(FOOD.clone()), json!({"a": 1})),
+            ("future".to_string(), Some(json!(25)), json!(1)),
+        ],
+        seen.borrow()[..2]
+    );
+
+    // known operators, custom ones included, take precedence over the fallback
+    custom_operator!(ops, "future", |_evaluatee, _condition| Ok(false));
+    assert!(!querier.evaluate_with_container(Some(&FOOD), &ops).unwrap());
+    let querier = BaseQuerier::new(&json!({"qty": {"$eq": 10}}));
+    assert!(!querier.evaluate_with_container(Some(&FOOD), &ops).unwrap());
+}
+
+#[test]
+fn test_repeated_fields() {
+    // a field repeated across conditions resolves to the same value every time
+    let q = json!({
+        "qty": {"$gt": 20},
+        "$and": [{"qty": {"$lt": 30}}, {"qty": {"$ne": 26}}],
+        "$or": [{"qty": 10}, {"qty": 25}],
+    });
+    assert_eq!(vec![&*FOOD], query(q, all()));
+    let q = json!({"$nor": [{"qty": 25}, {"qty": {"$gt": 100}}], "qty": {"$exists": true}});
+    assert_eq!(vec![&*FRUIT], query(q, all()));
+
+    // including values produced by parallel descent
+    let q = json!({
+        "memos.by": "shipping",
+        "$and": [{"memos.by": {"$size": 2}}, {"memos.by": {"$all": ["billing"]}}],
+        "$or": [{"memos.by": ["shipping", "billing"]}, {"memos.by": []}],
+    });
+    assert_eq!(vec![&*FOOD], query(q, all()));
+
+    // and the same names within array elements resolve against each element
+    let q = json!({
+        "memos.memo": "on time",
+        "memos": {"$elemMatch": {"memo": "delayed", "by": "shipping"}},
+        "$or": [{"memos.memo": {"$size": 2}}],
+    });
+    assert_eq!(vec![&*FRUIT], query(q, all()));
+    let q = json!({"memos": {"$all": [
+        {"$elemMatch": {"memo": "on time"}},
+        {"$elemMatch": {"memo": "approved"}},
+    ]}});
+    assert_eq!(vec![&*FOOD], query(q, all()));
+
+    // as when evaluating the conditions separately
+    let record = json!({"items": [{"qty": 1}, {"qty": 5}], "qty": 3});
+    let conditions = [
+        json!({"items.qty": {"$gt": 4}}),
+        json!({"items.qty": [1, 5]}),
+        json!({"qty": {"$lt": 4}}),
+        json!({"items": {"$elemMatch": {"qty": {"$lt": 4}}}}),
+        json!({"items": {"$elemMatch": {"qty": {"$gt": 4}}}}),
+    ];
+    for condition in &conditions {
+        assert!(BaseQuerier::new(condition).evaluate(Some(&record)).unwrap());
+    }
+    let querier = BaseQuerier::new(&json!({ "$and": conditions }));
+    assert!(querier.evaluate(Some(&record)).unwrap());
+    assert!(querier.diff(Some(&record)).unwrap().is_empty());
+}
+
+#[test]
+fn test_negated_operator() {
+    use mongoquery::OperatorContainer;
+
+    // matches numbers greater than the condition, and fails on anything else
+    struct GreaterThan;
+    impl CustomOperator for GreaterThan {
+        fn evaluate(
+            &self,
+            evaluatee: Option<&Value>,
+            condition: &Value,
+        ) -> Result<bool, QueryError> {
+            match (evaluatee.and_then(Value::as_f64), condition.as_f64()) {
+                (Some(e), Some(c)) => Ok(e > c),
+                _ => Err(QueryError::OperatorError {
+                    operator: "greaterThan".to_string(),
+                    reason: "expected numbers".to_string(),
+                }),
+            }
+        }
+    }
+
+    let mut ops = OperatorContainer::new();
+    ops.insert("greaterThan", GreaterThan);
+    ops.insert("notGreaterThan", GreaterThan.negated());
+    ops.insert("greaterThanAgain", GreaterThan.negated().negated());
+
+    for (q, expected) in [
+        (json!({"qty": {"$greaterThan": 20}}), vec![&*FOOD]),
+        (json!({"qty": {"$notGreaterThan": 20}}), vec![&*FRUIT]),
+        (json!({"qty": {"$notGreaterThan": 25}}), all()),
+        (json!({"qty": {"$greaterThanAgain": 20}}), vec![&*FOOD]),
+    ] {
+        assert_eq!(
+            expected,
+            query_custom(q.clone(), all(), ops.as_ref()),
+            "{}",
+            q
+        );
+    }
+
+    // errors aren't negated
+    let querier = BaseQuerier::new(&json!({"item": {"$notGreaterThan": 20}}));
+    assert!(matches!(
+        querier.evaluate_with_custom_ops(Some(&FOOD), ops.as_ref()),
+        Err(QueryError::OperatorError { operator, .. }) if operator == "greaterThan"
+    ));
+}
+
+#[cfg(feature = "sha2")]
+#[test]
+fn test_hash_eq() {
+    let record = json!({
+        "item": "xyz",
+        "qty": 25,
+        "ratings": [5, 8, 9],
+        "payload": {"b": 1, "a": [1, 2]},
+        "name": "hé"
+    });
+    // digests of `"xyz"`, `25`, `[5,8,9]`, `{"a":[1,2],"b":1}` and `"hé"`, and of `"h\u00e9"`
+    let xyz = "2f95e07ca5b7bf6844703761168c5f1d9eb38b697a1b8f8f249d3f05b98d9dd6";
+    let qty = "b7a56873cd771f2c446d369b649430b65a756ba278ff97ec81bb6f55b2e73569";
+    let ratings = "c47e0f9966976cefd8dbeedce9896662dbc7f156a0ee559b47dc0657ac10e4d5";
+    let payload = "94a786c3662bc7beeb598efa7d8cb58d7bea25d6c275ea9785a0230ff1f8c2ba";
+    let name = "a27ad630c84ff0c01eb03a5051ff3d2dc85e03f3c0a5e7a74ac1f21c9b9b86ec";
+    let escaped_name = "3c47146d6f31fdb503bf3dae2f473973d2aa37f97f399996a09a733cfb4ab3c1";
+
+    for (field, hex, matches) in [
+        ("item", xyz, true),
+        ("item", &xyz.to_uppercase(), true),
+        ("qty", qty, true),
+        ("ratings", ratings, true),
+        ("payload", payload, true),
+        // non-ASCII characters are hashed as UTF-8, not escaped
+        ("name", name, true),
+        ("name", escaped_name, false),
+        ("item", qty, false),
+        ("missing", xyz, false),
+    ] {
+        let q = json!({field: {"$hashEq": {"algo": "sha256", "hex": hex}}});
+        assert_eq!(
+            matches,
+            BaseQuerier::new(&q).evaluate(Some(&record)).unwrap(),
+            "{}",
+            q
+        );
+    }
+    for invalid in [
+        json!({"algo": "md5", "hex": xyz}),
+        json!({"algo": "sha256"}),
+        json!({"algo": "sha256", "hex": "abc"}),
+        json!({"algo": "sha256", "hex": xyz.replace('f', "g")}),
+        json!({"hex": xyz}),
+        json!({"algo": "sha256", "hex": xyz, "salt": "x"}),
+        json!(xyz),
+    ] {
+        let querier = BaseQuerier::new(&json!({"item": {"$hashEq": invalid}}));
+        assert!(
+            matches!(
+                querier.evaluate(Some(&record)),
+                Err(QueryError::OperatorError { operator, .. }) if operator == "hashEq"
+            ),
+            "{}",
+            invalid
+        );
+    }
+}
+
+#[cfg(feature = "decimal")]
+#[test]
+fn test_decimal_comparison() {
+    let record = json!({
+        "price": "4.25",
+        "total": "12345678901234567890.12",
+        "fee": 0.1,
+        "history": ["4.10", 4.3],
+        "item": "xyz",
+    });
+
+    for (q, matches) in [
+        (json!({"price": {"$decimalEq": 4.25}}), true),
+        (json!({"price": {"$decimalEq": "4.250"}}), true),
+        (json!({"price": {"$decimalGt": "4.24"}}), true),
+        (
+            json!({"price": {"$decimalLt": "4.2500000000000000001"}}),
+            true,
+        ),
+        (json!({"price": {"$decimalGt": 4.25}}), false),
+        // beyond the precision of f64, which can't tell these apart
+        (
+            json!({"total": {"$decimalEq": "12345678901234567890.12"}}),
+            true,
+        ),
+        (
+            json!({"total": {"$decimalEq": "12345678901234567890.13"}}),
+            false,
+        ),
+        (
+            json!({"total": {"$decimalLt": "12345678901234567890.13"}}),
+            true,
+        ),
+        (
+            json!({"total": {"$decimalGt": "12345678901234567890.11"}}),
+            true,
+        ),
+        // numbers are compared as written, not as the nearest f64
+        (json!({"fee": {"$decimalEq": "0.1"}}), true),
+        (json!({"fee": {"$decimalEq": "1e-1"}}), true),
+        (json!({"fee": {"$decimalLt": "0.10000000000000001"}}), true),
+        // any element of an array may match
+        (json!({"history": {"$decimalEq": "4.3"}}), true),
+        (json!({"history": {"$decimalLt": 4.1}}), false),
+        (json!({"missing": {"$decimalEq": 0}}), false),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(matches, querier.evaluate(Some(&record)).unwrap(), "{}", q);
+    }
+
+    // unparseable values are errors
+    for q in [
+        json!({"item": {"$decimalEq": "4.25"}}),
+        json!({"price": {"$decimalGt": "four"}}),
+        json!({"price": {"$decimalLt": null}}),
+        json!({"price": {"$decimalEq": [4.25]}}),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert!(
+            matches!(
+                querier.evaluate(Some(&record)),
+                Err(QueryError::OperatorError { .. })
+            ),
+            "{}",
+            q
+        );
+    }
+}
+
+#[test]
+fn test_evaluate_projected() {
+    let record = json!({
+        "item": "xyz",
+        "qty": 25,
+        "size": {"h": 14, "w": 21, "uom": "cm"},
+        "memos": FOOD["memos"],
+    });
+
+    for (q, projection, matches) in [
+        (json!({"qty": {"$gt": 20}}), vec!["qty"], true),
+        (
+            json!({"qty": {"$gt": 20}, "item": "xyz"}),
+            vec!["item", "qty"],
+            true,
+        ),
+        (json!({"size.uom": "cm"}), vec!["size"], true),
+        (json!({"size.uom": "cm"}), vec!["size.uom"], true),
+        (
+            json!({"size": {"uom": "cm", "h": 14}}),
+            vec!["size.uom", "size.h"],
+            true,
+        ),
+        (json!({"size": {"$**": 21}}), vec!["size"], true),
+        (json!({"memos.by": "billing"}), vec!["memos"], true),
+        (
+            json!({"memos": {"$elemMatch": {"by": "billing"}}}),
+            vec!["memos"],
+            true,
+        ),
+        (
+            json!({"$or": [{"qty": 5}, {"item": "xyz"}]}),
+            vec!["qty", "item"],
+            true,
+        ),
+        (json!({"$nor": [{"qty": 25}]}), vec!["qty", "item"], false),
+        (json!({"qty": {"$not": {"$lt": 5}}}), vec!["qty"], true),
+        // missing fields are missing from the projection too
+        (
+            json!({"missing": {"$exists": false}}),
+            vec!["missing"],
+            true,
+        ),
+        // root-level operators only see the projected fields
+        (json!({"$numKeys": 2}), vec!["qty", "size.h"], true),
+        (
+            json!({"$pointerExists": "/size/uom"}),
+            vec!["size.h"],
+            false,
+        ),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(
+            matches,
+            querier
+                .evaluate_projected(Some(&record), &projection)
+                .unwrap(),
+            "{} with {:?}",
+            q,
+            projection
+        );
+    }
+
+    // referencing fields outside of the projection is an error, whatever the document
+    for (q, projection) in [
+        (json!({"qty": {"$gt": 20}}), vec!["item"]),
+        (json!({"qty": 25, "item": "xyz"}), vec!["qty"]),
+        (json!({"size": {"$exists": true}}), vec!["size.uom"]),
+        (json!({"size": {"uom": "cm", "h": 14}}), vec!["size.uom"]),
+        (json!({"$or": [{"qty": 25}, {"price": 2.5}]}), vec!["qty"]),
+        (json!({"$and": [{"qty": 5}]}), vec![]),
+        (json!({"$**": 25}), vec!["qty"]),
+        (json!({"quantity": 25}), vec!["qty"]),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        for value in [Some(&record), None] {
+            assert!(
+                matches!(
+                    querier.evaluate_projected(value, &projection),
+                    Err(QueryError::OperatorError { operator, .. }) if operator == "projection"
+                ),
+                "{} with {:?}",
+                q,
+                projection
+            );
+        }
+    }
+}
+
+#[test]
+fn test_evaluate_map() {
+    let map = FOOD.as_object().unwrap().clone();
+
+    for (q, matches) in [
+        (json!({}), true),
+        (json!({"qty": 25}), true),
+        (json!({"qty": {"$gt": 20}, "item": "xyz"}), true),
+        (json!({"qty": {"$gt": 20}, "item": "abc"}), false),
+        (json!({"memos.by": "billing"}), true),
+        (json!({"memos.0.by": "billing"}), false),
+        (json!({"ratings": {"$elemMatch": {"$gt": 8}}}), true),
+        (json!({"missing": {"$exists": false}}), true),
+        (json!({"missing.deeper": null}), true),
+        // queries needing the whole document
+        (json!({"$or": [{"qty": 10}, {"item": "xyz"}]}), true),
+        (json!({"$**": "food"}), true),
+        (json!({"$numKeys": 7}), true),
+        (json!({"qty": {"$fieldEq": "_id"}}), false),
+        (
+            json!({"$expr": {"$regexMatch": {"input": "$item", "regex": "^x"}}}),
+            true,
+        ),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(matches, querier.evaluate_map(&map).unwrap(), "{}", q);
+        assert_eq!(
+            querier.evaluate(Some(&FOOD)).unwrap(),
+            querier.evaluate_map(&map).unwrap(),
+            "{}",
+            q
+        );
+    }
+
+    let querier = BaseQuerier::new(&json!({"qty": {"$size": -1}}));
+    assert!(querier.evaluate_map(&map).is_err());
+}
+
+#[test]
+fn test_ignored_keys() {
+    use mongoquery::{BaseOperators, OperatorProvider, StandardOperator};
+
+    for (key, value) in [
+        ("$comment", json!("restock report")),
+        ("$hint", json!({"qty": 1})),
+        ("$maxTimeMS", json!(500)),
+    ] {
+        for (mut q, expected) in [
+            (json!({"qty": {"$gt": 20}}), vec![&*FOOD]),
+            (json!({"$or": [{"qty": 10}, {"item": "xyz"}]}), all()),
+            (json!({}), all()),
+        ] {
+            q[key] = value.clone();
+            assert_eq!(expected, query(q.clone(), all()), "{}", q);
+            assert!(BaseQuerier::new(&q)
+                .unsupported_operators(&BaseOperators::get_operators())
+                .is_empty());
+        }
+        // nested in logical operators too
+        let q = json!({"$and": [{"qty": 10, key: value}]});
+        assert_eq!(vec![&*FRUIT], query(q.clone(), all()), "{}", q);
+    }
+
+    // other keys are still operators
+    let querier = BaseQuerier::new(&json!({"qty": 25, "$explain": true}));
+    assert!(matches!(
+        querier.evaluate(Some(&FOOD)),
+        Err(QueryError::UnsupportedOperator { operator }) if operator == "explain"
+    ));
+
+    // providers choose which keys they ignore
+    #[derive(Debug)]
+    struct StrictOperators;
+    impl OperatorProvider for StrictOperators {
+        fn get_operators() -> HashMap<String, StandardOperator> {
+            BaseOperators::get_operators()
+        }
+        fn ignored_keys() -> &'static [&'static str] {
+            &["explain"]
+        }
+    }
+    struct StrictQuerier;
+    impl Querier for StrictQuerier {
+        type Provider = StrictOperators;
+    }
+    let querier = StrictQuerier::new(&json!({"qty": 25, "$explain": true}));
+    assert!(querier.evaluate(Some(&FOOD)).unwrap());
+    let querier = StrictQuerier::new(&json!({"qty": 25, "$comment": "restock"}));
+    assert!(querier.evaluate(Some(&FOOD)).is_err());
+}
+
+#[test]
+fn test_evaluate_bitmap() {
+    let docs = vec![
+        FOOD.clone(),
+        FRUIT.clone(),
+        json!({"qty": 30, "type": "food"}),
+        json!({}),
+        json!(null),
+        json!([{"qty": 25}]),
+    ];
+    for q in [
+        json!({"type": "food"}),
+        json!({"qty": {"$gt": 20}}),
+        json!({"$or": [{"qty": 10}, {"type": {"$exists": false}}]}),
+        json!({"qty": {"$exists": false}}),
+        json!({}),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        let bitmap = querier.evaluate_bitmap(&docs).unwrap();
+        let expected: Vec<_> = docs
+            .iter()
+            .map(|doc| querier.evaluate(Some(doc)).unwrap())
+            .collect();
+        assert_eq!(expected, bitmap, "{}", q);
+    }
+
+    let querier = BaseQuerier::new(&json!({"qty": {"$gt": 20}}));
+    assert_eq!(
+        vec![true, false, true, false, false, true],
+        querier.evaluate_bitmap(&docs).unwrap()
+    );
+    assert!(querier.evaluate_bitmap(&[]).unwrap().is_empty());
+
+    let querier = BaseQuerier::new(&json!({"qty": {"$size": -1}}));
+    assert!(querier.evaluate_bitmap(&docs).is_err());
+}
+
+#[test]
+fn test_evaluate_all_lenient() {
+    // `$strlen` fails on values that aren't strings
+    let docs = vec![
+        FOOD.clone(),
+        json!({"item": 123}),
+        FRUIT.clone(),
+        json!({"item": "abcd"}),
+        json!({}),
+    ];
+    let querier = BaseQuerier::new(&json!({"item": {"$strlen": 3}}));
+    let results = querier.evaluate_all_lenient(&docs);
+    assert_eq!(docs.len(), results.len());
+    assert!(results[0].as_ref().unwrap());
+    assert!(matches!(
+        &results[1],
+        Err(QueryError::OperatorError { operator, .. }) if operator == "strlen"
+    ));
+    assert!(results[2].as_ref().unwrap());
+    assert!(!results[3].as_ref().unwrap());
+    assert!(!results[4].as_ref().unwrap());
+    // while the bitmap fails as a whole
+    assert!(querier.evaluate_bitmap(&docs).is_err());
+
+    // without errors, the results are those of the bitmap
+    let querier = BaseQuerier::new(&json!({"qty": {"$gt": 20}}));
+    let results: Result<Vec<_>, _> = querier.evaluate_all_lenient(&docs).into_iter().collect();
+    assert_eq!(querier.evaluate_bitmap(&docs).unwrap(), results.unwrap());
+    assert!(querier.evaluate_all_lenient(&[]).is_empty());
+}
+
+#[test]
+fn test_coerce_regex() {
+    let coerce = EvaluationOptions {
+        coerce_regex: true,
+        ..Default::default()
+    };
+    let record =
+        json!({"qty": 25, "price": 2.5, "sale": true, "codes": [404, "n/a"], "item": "xyz"});
+
+    for (q, strict, coerced) in [
+        (json!({"qty": {"$regex": "^2\\d$"}}), false, true),
+        (json!({"qty": {"$regex": "^3"}}), false, false),
+        (json!({"price": {"$regex": "^2\\.5$"}}), false, true),
+        (json!({"sale": {"$regex": "^tr"}}), false, true),
+        (json!({"codes": {"$regex": "^40"}}), false, true),
+        (json!({"codes": {"$regex": "^n/"}}), true, true),
+        (json!({"item": {"$regex": "^x"}}), true, true),
+        (json!({"missing": {"$regex": ""}}), false, false),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(strict, querier.evaluate(Some(&record)).unwrap(), "{}", q);
+        assert_eq!(
+            coerced,
+            querier
+                .evaluate_with_options(Some(&record), &coerce)
+                .unwrap(),
+            "{}",
+            q
+        );
+    }
+
+    // documents and null are never coerced
+    let record = json!({"size": {"h": 14}, "note": null});
+    for q in [
+        json!({"size": {"$regex": "h"}}),
+        json!({"note": {"$regex": "null"}}),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert!(!querier
+            .evaluate_with_options(Some(&record), &coerce)
+            .unwrap());
+    }
+}
+
+#[test]
+fn test_error_on_path_through_scalar() {
+    let strict = EvaluationOptions {
+        error_on_path_through_scalar: true,
+        ..Default::default()
+    };
+    let record = json!({
+        "size": {"h": 14, "w": 21, "uom": null},
+        "items": [{"name": "apple"}, "pear"],
+        "tags": ["red", "blank"]
+    });
+
+    // (query, lenient match, strict match or the scalar part of the path)
+    for (q, lenient, strict_match) in [
+        (json!({"size.h": 14}), true, Ok(true)),
+        (json!({"size.d": {"$exists": false}}), true, Ok(true)),
+        (json!({"size.d.x": null}), true, Ok(true)),
+        (json!({"size.uom.x": null}), true, Ok(true)),
+        (
+            json!({"size.h.x": null}),
+            true,
+            Err("`size.h` is a scalar and has no field `x`"),
+        ),
+        (
+            json!({"size.h.x": {"$exists": true}}),
+            false,
+            Err("`size.h` is a scalar and has no field `x`"),
+        ),
+        (
+            json!({"items.1.name": "pear"}),
+            false,
+            Err("`items.1` is a scalar and has no field `name`"),
+        ),
+        (json!({"items.0.name": "apple"}), true, Ok(true)),
+        (json!({"items.5.name": "apple"}), false, Ok(false)),
+        // the scalar element is skipped, unless that's an error
+        (
+            json!({"items.name": "apple"}),
+            true,
+            Err("`items` is a scalar and has no field `name`"),
+        ),
+        (json!({"tags.0": "red"}), true, Ok(true)),
+        (
+            json!({"tags.0.x": null}),
+            true,
+            Err("`tags.0` is a scalar and has no field `x`"),
+        ),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(lenient, querier.evaluate(Some(&record)).unwrap(), "{}", q);
+        match (
+            strict_match,
+            querier.evaluate_with_options(Some(&record), &strict),
+        ) {
+            (Ok(expected), Ok(matched)) => assert_eq!(expected, matched, "{}", q),
+            (Err(expected), Err(QueryError::OperatorError { operator, reason })) => {
+                assert_eq!("path", operator);
+                assert_eq!(expected, reason, "{}", q);
+            }
+            (expected, actual) => panic!("{}: expected {:?}, got {:?}", q, expected, actual),
+        }
+    }
+
+    // field conditions on a scalar have no field to select either
+    let querier = BaseQuerier::new(&json!({"a": 1}));
+    assert!(!querier.evaluate(Some(&json!(5))).unwrap());
+    assert!(matches!(
+        querier.evaluate_with_options(Some(&json!(5)), &strict),
+        Err(QueryError::OperatorError { reason, .. })
+            if reason == "the value is a scalar and has no field `a`"
+    ));
+}
+
+#[test]
+fn test_resolve_path() {
+    use mongoquery::resolve_path;
+    use std::borrow::Cow;
+
+    let record = json!({
+        "size": {"h": 14, "uom": "cm"},
+        "ratings": [5, 8, 9],
+        "memos": FOOD["memos"],
+        "grid": [[{"x": 1}], [{"x": 2}, {"x": 3}]],
+        "note": null,
+        "0": "zero",
+    });
+
+    for (path, expected) in [
+        ("size", Some(json!({"h": 14, "uom": "cm"}))),
+        ("size.uom", Some(json!("cm"))),
+        ("size.w", None),
+        ("size.uom.x", None),
+        ("ratings.0", Some(json!(5))),
+        ("ratings.-1", Some(json!(9))),
+        ("ratings.3", None),
+        ("ratings.-4", None),
+        ("memos.1.by", Some(json!("billing"))),
+        ("memos.by", Some(json!(["shipping", "billing"]))),
+        ("memos.missing", None),
+        ("grid.x", Some(json!([[1], [2, 3]]))),
+        ("grid.1.x", Some(json!([2, 3]))),
+        ("grid.1.0.x", Some(json!(2))),
+        // numeric segments are plain keys on documents, and paths through null are null
+        ("0", Some(json!("zero"))),
+        ("note", Some(json!(null))),
+        ("note.deeper", Some(json!(null))),
+        ("missing", None),
+    ] {
+        assert_eq!(
+            expected,
+            resolve_path(&record, path).map(Cow::into_owned),
+            "{}",
+            path
+        );
+    }
+
+    // values are only copied when arrays are traversed in parallel
+    assert!(matches!(
+        resolve_path(&record, "memos.0.by"),
+        Some(Cow::Borrowed(_))
+    ));
+    assert!(matches!(
+        resolve_path(&record, "memos.by"),
+        Some(Cow::Owned(_))
+    ));
+}
+
+#[test]
+fn test_root_operators() {
+    // operators at the root are evaluated against the whole value, which may be a scalar
+    for (q, value, matches) in [
+        (json!({"$gt": 20}), json!(25), true),
+        (json!({"$gt": 20}), json!(10), false),
+        (json!({"$gt": 20, "$lt": 30}), json!(25), true),
+        (json!({"$gt": 20, "$lt": 30}), json!(30), false),
+        (json!({"$in": [10, 25]}), json!(10), true),
+        (json!({"$not": {"$gt": 20}}), json!(10), true),
+        (json!({"$not": {"$gt": 20}}), json!(25), false),
+        (json!({"$regex": "^x"}), json!("xyz"), true),
+        (json!({"$type": "string"}), json!(25), false),
+        // arrays match if any element does, as for fields
+        (json!({"$gt": 20}), json!([5, 25]), true),
+        (json!({"$gt": 20}), json!([5, 10]), false),
+        (
+            json!({"$elemMatch": {"$gt": 20, "$lt": 30}}),
+            json!([5, 25]),
+            true,
+        ),
+        // and documents don't compare to numbers
+        (json!({"$gt": 20}), json!({"qty": 25}), false),
+    ] {
+        let querier = BaseQuerier::new(&q);
+        assert_eq!(
+            matches,
+            querier.evaluate(Some(&value)).unwrap(),
+            "{} on {}",
+            q,
+            value
+        );
+    }
+    assert!(!BaseQuerier::new(&json!({"$gt": 20}))
+        .evaluate(None)
+        .unwrap());
+}